@@ -0,0 +1,86 @@
+use glam::Vec2;
+
+/// How far a stick has to move before it does anything. Sticks rarely
+/// rest exactly at zero.
+const STICK_DEADZONE: f32 = 0.15;
+/// Triggers are much more reliable at rest than sticks
+const TRIGGER_DEADZONE: f32 = 0.05;
+
+// Button and axis numbers from the "standard" gamepad mapping
+// https://w3c.github.io/gamepad/#remapping
+const BUTTON_LEFT_BUMPER: usize = 4;
+const BUTTON_RIGHT_BUMPER: usize = 5;
+const BUTTON_LEFT_TRIGGER: usize = 6;
+const BUTTON_RIGHT_TRIGGER: usize = 7;
+const AXIS_LEFT_STICK_X: usize = 0;
+const AXIS_LEFT_STICK_Y: usize = 1;
+const AXIS_RIGHT_STICK_X: usize = 2;
+const AXIS_RIGHT_STICK_Y: usize = 3;
+
+/// The analog state of a gamepad with the deadzones already applied.
+/// Sticks are in the range -1.0 to 1.0 with up being positive, and the
+/// triggers from 0.0 to 1.0.
+#[derive(Clone, Copy, Debug)]
+pub struct Gamepad {
+    pub left_stick: Vec2,
+    pub right_stick: Vec2,
+    pub left_trigger: f32,
+    pub right_trigger: f32,
+    pub left_bumper: bool,
+    pub right_bumper: bool,
+}
+
+impl Gamepad {
+    /// A gamepad with nothing pressed
+    pub fn new() -> Self {
+        Self {
+            left_stick: Vec2::ZERO,
+            right_stick: Vec2::ZERO,
+            left_trigger: 0.0,
+            right_trigger: 0.0,
+            left_bumper: false,
+            right_bumper: false,
+        }
+    }
+
+    /// Creates a gamepad from the raw axes and button values of a
+    /// gamepad using the browser's "standard" mapping.
+    pub fn from_standard_mapping(axes: &[f32], buttons: &[f32]) -> Self {
+        let axis = |id: usize| axes.get(id).cloned().unwrap_or(0.0);
+        let button = |id: usize| buttons.get(id).cloned().unwrap_or(0.0);
+
+        // The browser has down as positive on the sticks
+        let left_stick = Vec2::new(axis(AXIS_LEFT_STICK_X), -axis(AXIS_LEFT_STICK_Y));
+        let right_stick = Vec2::new(axis(AXIS_RIGHT_STICK_X), -axis(AXIS_RIGHT_STICK_Y));
+
+        Self {
+            left_stick: apply_stick_deadzone(left_stick),
+            right_stick: apply_stick_deadzone(right_stick),
+            left_trigger: apply_deadzone(button(BUTTON_LEFT_TRIGGER), TRIGGER_DEADZONE),
+            right_trigger: apply_deadzone(button(BUTTON_RIGHT_TRIGGER), TRIGGER_DEADZONE),
+            left_bumper: button(BUTTON_LEFT_BUMPER) > 0.5,
+            right_bumper: button(BUTTON_RIGHT_BUMPER) > 0.5,
+        }
+    }
+}
+
+/// Values smaller than the deadzone become zero and the rest of the
+/// range is stretched so that it still goes all the way to 1.0
+fn apply_deadzone(value: f32, deadzone: f32) -> f32 {
+    if value.abs() < deadzone {
+        0.0
+    } else {
+        value.signum() * (value.abs() - deadzone) / (1.0 - deadzone)
+    }
+}
+
+/// Applies the deadzone to how far the stick is pushed rather than to
+/// each axis, so that diagonals aren't snapped to the axes.
+fn apply_stick_deadzone(stick: Vec2) -> Vec2 {
+    let length = stick.length();
+    if length < STICK_DEADZONE {
+        Vec2::ZERO
+    } else {
+        stick * apply_deadzone(length.min(1.0), STICK_DEADZONE) / length
+    }
+}
@@ -4,6 +4,7 @@ use glow::Context;
 
 pub mod attributes;
 pub mod framebuffer;
+pub mod gamepad;
 pub mod keyboard;
 pub mod mesh;
 pub mod mesh_loader;
@@ -28,6 +29,7 @@ pub struct App {
     renderer: RendererState,
     gl: glow::Context,
     keyboard: keyboard::Keyboard,
+    gamepad: gamepad::Gamepad,
 }
 
 impl App {
@@ -118,6 +120,7 @@ impl App {
             renderer,
             gl,
             keyboard: keyboard::Keyboard::new(),
+            gamepad: gamepad::Gamepad::new(),
         }
     }
 
@@ -135,7 +138,7 @@ impl App {
         let time_since_start = self.world.time_since_start + delta;
         self.world.time_since_start = time_since_start;
 
-        fly_camera(&mut self.world.camera, &self.keyboard, delta);
+        fly_camera(&mut self.world.camera, &self.keyboard, &self.gamepad, delta);
 
         self.world.vehicles[0].transform = Mat4::from_rotation_translation(
             Quat::from_euler(EulerRot::XYZ, 0.0, time_since_start.sin(), 0.0),
@@ -170,10 +173,21 @@ impl App {
         self.keyboard.set_key_state(key, is_down);
     }
 
+    /// Sets the state of the gamepad. Should be called every frame
+    /// (before `animation_frame`) while a gamepad is connected.
+    pub fn gamepad_event(&mut self, gamepad: gamepad::Gamepad) {
+        self.gamepad = gamepad;
+    }
+
     pub fn mouse_event(&mut self) {}
 }
 
-fn fly_camera(camera: &mut Camera, key_state: &keyboard::Keyboard, delta: f32) {
+fn fly_camera(
+    camera: &mut Camera,
+    key_state: &keyboard::Keyboard,
+    gamepad: &gamepad::Gamepad,
+    delta: f32,
+) {
     let mut translation = Vec3::new(0.0, 0.0, 0.0);
     let mut rotation = Vec3::new(0.0, 0.0, 0.0);
 
@@ -215,6 +229,21 @@ fn fly_camera(camera: &mut Camera, key_state: &keyboard::Keyboard, delta: f32) {
         rotation[2] -= 1.0;
     }
 
+    // The gamepad flies like a plane: the triggers are the throttle,
+    // the left stick pitches and rolls, and the right stick yaws and
+    // strafes up and down.
+    translation[2] -= gamepad.right_trigger - gamepad.left_trigger;
+    translation[1] += gamepad.right_stick[1];
+    rotation[0] += gamepad.left_stick[1];
+    rotation[1] -= gamepad.right_stick[0];
+    rotation[2] -= gamepad.left_stick[0];
+    if gamepad.left_bumper {
+        translation[0] -= 1.0;
+    }
+    if gamepad.right_bumper {
+        translation[0] += 1.0;
+    }
+
     let translation_local = translation * delta * 100.0;
     let rotation_local = rotation * delta * 2.0;
 
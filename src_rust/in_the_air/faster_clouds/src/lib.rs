@@ -6,10 +6,10 @@ use js_sys::{Date, Function};
 
 use wasm_bindgen::prelude::{wasm_bindgen, Closure};
 use wasm_bindgen::JsCast;
-use web_sys::{window, HtmlCanvasElement, KeyboardEvent, MouseEvent};
+use web_sys::{window, Gamepad, GamepadButton, HtmlCanvasElement, KeyboardEvent, MouseEvent};
 
 mod app;
-use app::keyboard;
+use app::{gamepad, keyboard};
 
 // A macro to provide `println!(..)`-style syntax for `console.log` logging.
 macro_rules! log {
@@ -100,6 +100,10 @@ impl Core {
                     app_ref.update_resolution(target_resolution, pixels_per_centimeter);
                 }
 
+                // Gamepads don't have events for their axes, so they
+                // have to be checked every frame
+                app_ref.gamepad_event(read_gamepad().unwrap_or_else(gamepad::Gamepad::new));
+
                 let time = Date::new_0().get_time() / 1000.0;
 
                 app_ref.animation_frame(time);
@@ -195,6 +199,36 @@ fn make_callback(closure: &Closure<dyn FnMut()>) -> &Function {
     closure.as_ref().unchecked_ref()
 }
 
+/// Reads the first connected gamepad that uses the standard mapping
+fn read_gamepad() -> Option<gamepad::Gamepad> {
+    let gamepads = window().unwrap().navigator().get_gamepads().ok()?;
+    for gamepad in gamepads.iter() {
+        let gamepad: Gamepad = match gamepad.dyn_into() {
+            Ok(gamepad) => gamepad,
+            Err(_) => continue, // Null - nothing plugged in to this slot
+        };
+        if !gamepad.connected() || gamepad.mapping() != web_sys::GamepadMappingType::Standard {
+            continue;
+        }
+
+        let axes: Vec<f32> = gamepad
+            .axes()
+            .iter()
+            .map(|axis| axis.as_f64().unwrap_or(0.0) as f32)
+            .collect();
+        let buttons: Vec<f32> = gamepad
+            .buttons()
+            .iter()
+            .map(|button| match button.dyn_into::<GamepadButton>() {
+                Ok(button) => button.value() as f32,
+                Err(_) => 0.0,
+            })
+            .collect();
+        return Some(gamepad::Gamepad::from_standard_mapping(&axes, &buttons));
+    }
+    None
+}
+
 fn check_update_resolution(canvas: &HtmlCanvasElement) -> Option<([i32; 2], f64)> {
     // This is a somewhat hacky version.
     // For a proper approach see
@@ -0,0 +1,110 @@
+//! A player that races without a browser. It connects to the relay
+//! server the same way the game does, so a couple of them can be used
+//! to check the server and the online race from the command line.
+use std::io;
+use std::net::TcpStream;
+use std::time::Duration;
+
+use tungstenite::{client, Message, WebSocket};
+
+use super::WebSocketError;
+use swoop_win_condition::ai::calc_ai_control;
+use swoop_win_condition::gameplay::GamePlay;
+use swoop_win_condition::keymap::KeyMap;
+use swoop_win_condition::online::OnlineRace;
+
+/// How long to wait for a message from the server before carrying on
+const POLL_TIME: Duration = Duration::from_millis(1);
+
+pub struct HeadlessClient {
+    socket: WebSocket<TcpStream>,
+    pub race: OnlineRace,
+    pub gameplay: GamePlay,
+    pub key_map: KeyMap,
+}
+
+impl HeadlessClient {
+    /// Connects to a server (eg `ws://127.0.0.1:9001`) and asks to join
+    /// the room.
+    pub fn connect(url: &str, room: &str, players: usize) -> Result<Self, WebSocketError> {
+        let address = url
+            .trim_start_matches("ws://")
+            .split('/')
+            .next()
+            .unwrap_or("");
+        let stream = TcpStream::connect(address).map_err(tungstenite::Error::Io)?;
+        let (socket, _response) = client(url, stream).map_err(|err| match err {
+            tungstenite::HandshakeError::Failure(err) => err,
+            tungstenite::HandshakeError::Interrupted(_) => tungstenite::Error::ConnectionClosed,
+        })?;
+        socket
+            .get_ref()
+            .set_read_timeout(Some(POLL_TIME))
+            .map_err(tungstenite::Error::Io)?;
+
+        Ok(Self {
+            socket,
+            race: OnlineRace::new(room, players),
+            gameplay: GamePlay::new(),
+            key_map: KeyMap::new(),
+        })
+    }
+
+    /// Sends any waiting messages and handles everything the server
+    /// has sent since the last poll.
+    pub fn poll(&mut self) -> Result<(), WebSocketError> {
+        for message in self.race.take_outbox() {
+            self.socket.send(Message::Text(message.to_string()))?;
+        }
+
+        loop {
+            match self.socket.read() {
+                Ok(Message::Text(text)) => {
+                    if let Err(err) = self.race.receive(&mut self.gameplay, &text) {
+                        println!("Bad message from server: {:?}", err);
+                    }
+                }
+                Ok(Message::Close(_)) => {
+                    return Err(Box::new(tungstenite::Error::ConnectionClosed))
+                }
+                Ok(_) => (),
+                Err(tungstenite::Error::Io(err))
+                    if err.kind() == io::ErrorKind::WouldBlock
+                        || err.kind() == io::ErrorKind::TimedOut =>
+                {
+                    return Ok(())
+                }
+                Err(err) => return Err(Box::new(err)),
+            }
+        }
+    }
+
+    /// Advance the local prediction of the race by `dt` seconds
+    pub fn update(&mut self, dt: f64) {
+        self.race.update(&mut self.gameplay, dt, &self.key_map);
+        self.key_map.update();
+    }
+
+    /// Presses the keys the AI would press if it was driving this
+    /// player's ship
+    pub fn drive_like_ai(&mut self, skill: f32) {
+        let player_id = match self.gameplay.online_player {
+            Some(player_id) => player_id,
+            None => return,
+        };
+        let mut ship = self.gameplay.ship_entities[player_id].clone();
+        calc_ai_control(
+            &mut ship,
+            skill,
+            &self.gameplay.ai_params,
+            &self.gameplay.map,
+            &self.gameplay.racing_line,
+            &self.gameplay.ship_entities,
+            player_id,
+        );
+
+        let keys = &mut self.key_map.players[0];
+        keys.throttle = ship.linear_thrust;
+        keys.steering = ship.angular_thrust;
+    }
+}
@@ -0,0 +1,8 @@
+//! The relay server for online swoop races, plus a headless client
+//! that can join races from the command line.
+pub mod client;
+pub mod server;
+
+/// Errors from a WebSocket connection. tungstenite's error type is
+/// quite large so it is boxed.
+pub type WebSocketError = Box<tungstenite::Error>;
@@ -0,0 +1,62 @@
+use std::env;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use swoop_relay::client::HeadlessClient;
+use swoop_relay::server::RelayServer;
+
+const DEFAULT_ADDRESS: &str = "127.0.0.1:9001";
+const USAGE: &str = "Usage:
+    swoop_relay serve [address]
+    swoop_relay bot <url> <room> [players] [skill]
+
+eg: swoop_relay serve 0.0.0.0:9001
+    swoop_relay bot ws://127.0.0.1:9001 test 2 0.7";
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let args: Vec<&str> = args.iter().map(|arg| arg.as_str()).collect();
+
+    match args.as_slice() {
+        [_, "serve"] => serve(DEFAULT_ADDRESS),
+        [_, "serve", address] => serve(address),
+        [_, "bot", url, room] => bot(url, room, "2", "0.7"),
+        [_, "bot", url, room, players] => bot(url, room, players, "0.7"),
+        [_, "bot", url, room, players, skill] => bot(url, room, players, skill),
+        _ => println!("{}", USAGE),
+    }
+}
+
+fn serve(address: &str) {
+    let server = RelayServer::bind(address).expect("Failed to bind address");
+    println!("Listening on ws://{}", server.local_addr().unwrap());
+    server.run();
+}
+
+/// Joins a race and drives like the AI until the race finishes
+fn bot(url: &str, room: &str, players: &str, skill: &str) {
+    let players = players.parse().expect("Players must be a number");
+    let skill = skill.parse().expect("Skill must be a number");
+
+    let mut client = HeadlessClient::connect(url, room, players).expect("Failed to connect");
+    println!("Joined room {}", room);
+
+    let mut prev_time = Instant::now();
+    while !client.gameplay.game_complete() || client.gameplay.online_player.is_none() {
+        if let Err(err) = client.poll() {
+            println!("Disconnected: {}", err);
+            return;
+        }
+        let now = Instant::now();
+        client.drive_like_ai(skill);
+        client.update((now - prev_time).as_secs_f64());
+        prev_time = now;
+        thread::sleep(Duration::from_millis(5));
+    }
+
+    let player_id = client.gameplay.online_player.unwrap_or(0);
+    println!(
+        "Race complete. Laps: {:?}",
+        client.gameplay.scores[player_id].get_lap_timings()
+    );
+}
@@ -0,0 +1,297 @@
+//! Accepts WebSocket connections, puts players into rooms and runs the
+//! race for each room once it is full. The server's race is the real
+//! one: every player is sent snapshots of it.
+use std::collections::HashMap;
+use std::io;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use tungstenite::{accept, Message, WebSocket};
+
+use super::WebSocketError;
+use swoop_win_condition::gameplay::{GamePlay, SIM_TIME_STEP};
+use swoop_win_condition::keymap::{KeyMap, ShipKeys, MAX_PLAYERS};
+use swoop_win_condition::network::{ClientMessage, ServerMessage, Snapshot, SNAPSHOT_INTERVAL};
+
+/// How long a connection waits for a message before checking if it
+/// has anything to send.
+const POLL_TIME: Duration = Duration::from_millis(1);
+
+/// The latest input from each player in a race
+type SharedInputs = Arc<Mutex<Vec<u8>>>;
+
+/// Which ship a player drives. It isn't known until the race starts,
+/// because players can leave the room before then.
+type SharedPlayerId = Arc<Mutex<Option<usize>>>;
+
+/// A player in a room
+struct Seat {
+    client: Sender<String>,
+    player_id: SharedPlayerId,
+}
+
+/// A room that is waiting for players
+struct Room {
+    players: usize,
+    seats: Vec<Seat>,
+    inputs: SharedInputs,
+}
+
+/// What a connection needs to take part in a room's race
+struct Joined {
+    room: String,
+    inputs: SharedInputs,
+    player_id: SharedPlayerId,
+}
+
+/// Rooms that have not started yet, by name
+type Lobby = Arc<Mutex<HashMap<String, Room>>>;
+
+pub struct RelayServer {
+    listener: TcpListener,
+    lobby: Lobby,
+}
+
+impl RelayServer {
+    /// Listens on the address (eg `127.0.0.1:9001`). Use port 0 to pick
+    /// any free port.
+    pub fn bind(address: &str) -> io::Result<Self> {
+        Ok(Self {
+            listener: TcpListener::bind(address)?,
+            lobby: Arc::new(Mutex::new(HashMap::new())),
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Handles connections forever. Each connection gets its own thread
+    /// as does each race.
+    pub fn run(self) {
+        for stream in self.listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let lobby = self.lobby.clone();
+                    thread::spawn(move || {
+                        if let Err(err) = handle_connection(stream, lobby) {
+                            println!("Connection closed: {}", err);
+                        }
+                    });
+                }
+                Err(err) => println!("Failed to accept connection: {}", err),
+            }
+        }
+    }
+}
+
+/// Passes messages between a player and their room until the player
+/// disconnects. A player who leaves before the race starts gives up
+/// their place in the room.
+fn handle_connection(stream: TcpStream, lobby: Lobby) -> Result<(), WebSocketError> {
+    let mut socket = accept(stream).map_err(|err| match err {
+        tungstenite::HandshakeError::Failure(err) => err,
+        tungstenite::HandshakeError::Interrupted(_) => tungstenite::Error::ConnectionClosed,
+    })?;
+    socket
+        .get_ref()
+        .set_read_timeout(Some(POLL_TIME))
+        .map_err(tungstenite::Error::Io)?;
+
+    let mut joined = None;
+    let result = relay_messages(&mut socket, &lobby, &mut joined);
+    if let Some(joined) = joined {
+        leave_room(&lobby, &joined);
+    }
+    result
+}
+
+fn relay_messages(
+    socket: &mut WebSocket<TcpStream>,
+    lobby: &Lobby,
+    joined: &mut Option<Joined>,
+) -> Result<(), WebSocketError> {
+    let (sender, receiver): (Sender<String>, Receiver<String>) = channel();
+
+    loop {
+        for message in receiver.try_iter() {
+            socket.send(Message::Text(message))?;
+        }
+
+        let text = match socket.read() {
+            Ok(Message::Text(text)) => text,
+            Ok(Message::Close(_)) => return Ok(()),
+            Ok(_) => continue,
+            Err(tungstenite::Error::Io(err))
+                if err.kind() == io::ErrorKind::WouldBlock
+                    || err.kind() == io::ErrorKind::TimedOut =>
+            {
+                continue
+            }
+            Err(err) => return Err(Box::new(err)),
+        };
+
+        match text.parse() {
+            Ok(ClientMessage::Join { room, players }) => {
+                if joined.is_none() {
+                    match join_room(lobby, room, players, sender.clone()) {
+                        Ok(seat) => *joined = Some(seat),
+                        Err(players) => {
+                            let rejected = ServerMessage::Rejected { players };
+                            socket.send(Message::Text(rejected.to_string()))?;
+                            socket.close(None)?;
+                            return Ok(());
+                        }
+                    }
+                }
+            }
+            Ok(ClientMessage::Input { input, .. }) => {
+                if let Some(joined) = joined {
+                    if let Some(player_id) = *joined.player_id.lock().unwrap() {
+                        joined.inputs.lock().unwrap()[player_id] = input;
+                    }
+                }
+            }
+            Err(err) => println!("Bad message from client: {:?}", err),
+        }
+    }
+}
+
+/// Adds a player to a room, creating the room if needed. When the room
+/// is full the race starts. A room that already exists with a
+/// different number of players can't be joined, and its number of
+/// players is returned instead.
+fn join_room(
+    lobby: &Lobby,
+    name: String,
+    players: usize,
+    client: Sender<String>,
+) -> Result<Joined, usize> {
+    let players = players.clamp(1, MAX_PLAYERS);
+    let mut lobby = lobby.lock().unwrap();
+    let room = lobby.entry(name.clone()).or_insert_with(|| Room {
+        players,
+        seats: vec![],
+        inputs: Arc::new(Mutex::new(vec![0; players])),
+    });
+    if room.players != players {
+        return Err(room.players);
+    }
+
+    let player_id = Arc::new(Mutex::new(None));
+    room.seats.push(Seat {
+        client,
+        player_id: player_id.clone(),
+    });
+    let joined = Joined {
+        room: name.clone(),
+        inputs: room.inputs.clone(),
+        player_id,
+    };
+
+    if room.seats.len() >= room.players {
+        let room = lobby.remove(&name).unwrap();
+        for (player_id, seat) in room.seats.iter().enumerate() {
+            *seat.player_id.lock().unwrap() = Some(player_id);
+        }
+        thread::spawn(move || run_race(room));
+    } else {
+        send_waiting(room);
+    }
+
+    Ok(joined)
+}
+
+/// Takes a player out of the room they are waiting in. Once the race
+/// has started the room is no longer in the lobby, so this does
+/// nothing.
+fn leave_room(lobby: &Lobby, joined: &Joined) {
+    let mut lobby = lobby.lock().unwrap();
+    let room = match lobby.get_mut(&joined.room) {
+        Some(room) => room,
+        None => return,
+    };
+    // A room with the same name may have been made since this one
+    // started, so only the player's own seat counts
+    room.seats
+        .retain(|seat| !Arc::ptr_eq(&seat.player_id, &joined.player_id));
+    if room.seats.is_empty() {
+        lobby.remove(&joined.room);
+    } else {
+        send_waiting(room);
+    }
+}
+
+/// Tells everyone in a room how many players are still needed
+fn send_waiting(room: &Room) {
+    let waiting = ServerMessage::Waiting {
+        joined: room.seats.len(),
+        players: room.players,
+    }
+    .to_string();
+    for seat in room.seats.iter() {
+        seat.client.send(waiting.clone()).ok();
+    }
+}
+
+/// Runs the race in real time, sending snapshots to the players until
+/// the race is over or everyone has left.
+fn run_race(room: Room) {
+    let seed = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.subsec_nanos() ^ time.as_secs() as u32)
+        .unwrap_or(0);
+
+    for (player_id, seat) in room.seats.iter().enumerate() {
+        let start = ServerMessage::Start {
+            seed,
+            player_id,
+            num_players: room.players,
+        };
+        seat.client.send(start.to_string()).ok();
+    }
+
+    let mut gameplay = GamePlay::new();
+    gameplay.set_num_players(room.players);
+    gameplay.reset(seed);
+
+    let mut key_map = KeyMap::new();
+    key_map.num_players = room.players;
+
+    let start_time = Instant::now();
+    loop {
+        let step_time = start_time + Duration::from_secs_f64(gameplay.tick as f64 * SIM_TIME_STEP);
+        let now = Instant::now();
+        if step_time > now {
+            thread::sleep(step_time - now);
+        }
+
+        for (keys, input) in key_map
+            .players
+            .iter_mut()
+            .zip(room.inputs.lock().unwrap().iter())
+        {
+            *keys = ShipKeys::from_bits(*input);
+        }
+        gameplay.step(&key_map);
+
+        let complete = gameplay.game_complete();
+        if gameplay.tick % SNAPSHOT_INTERVAL == 0 || complete {
+            let snapshot = ServerMessage::Snapshot(Snapshot::capture(&gameplay)).to_string();
+            let connected = room
+                .seats
+                .iter()
+                .filter(|seat| seat.client.send(snapshot.clone()).is_ok())
+                .count();
+            if connected == 0 {
+                return;
+            }
+        }
+        if complete {
+            return;
+        }
+    }
+}
@@ -0,0 +1,139 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+use swoop_relay::client::HeadlessClient;
+use swoop_relay::server::RelayServer;
+use swoop_win_condition::keymap::{KeyState, ShipKeys};
+use swoop_win_condition::online::OnlineState;
+
+/// Long enough for the countdown and a couple of seconds of racing
+const RACE_TIME: Duration = Duration::from_secs(6);
+
+#[test]
+fn two_clients_race_each_other() {
+    let server = RelayServer::bind("127.0.0.1:0").unwrap();
+    let url = format!("ws://{}", server.local_addr().unwrap());
+    thread::spawn(move || server.run());
+
+    let mut clients = [
+        HeadlessClient::connect(&url, "test", 2).unwrap(),
+        HeadlessClient::connect(&url, "test", 2).unwrap(),
+    ];
+
+    let start = Instant::now();
+    let mut prev_time = start;
+    while start.elapsed() < RACE_TIME {
+        let now = Instant::now();
+        for client in clients.iter_mut() {
+            client.poll().unwrap();
+            client.key_map.players[0].forwards = KeyState::Down;
+            client.update((now - prev_time).as_secs_f64());
+        }
+        prev_time = now;
+        thread::sleep(Duration::from_millis(5));
+    }
+
+    let mut player_ids: Vec<usize> = clients
+        .iter()
+        .map(|client| client.gameplay.online_player.expect("Race did not start"))
+        .collect();
+    player_ids.sort_unstable();
+    assert_eq!(player_ids, [0, 1]);
+
+    for client in clients.iter() {
+        let snapshot = client
+            .race
+            .latest_snapshot
+            .as_ref()
+            .expect("No snapshots received");
+        assert!(snapshot.game_duration > 0.5, "The race never got going");
+
+        // The server saw both players holding forwards
+        assert_eq!(snapshot.inputs.len(), 2);
+        for input in snapshot.inputs.iter() {
+            assert_eq!(ShipKeys::from_bits(*input).get_linear_thrust(), 1.0);
+        }
+
+        // The client's prediction of every ship stays close to the
+        // server's version of the race
+        for (ship, state) in client
+            .gameplay
+            .ship_entities
+            .iter()
+            .zip(snapshot.ships.iter())
+        {
+            let error = f32::hypot(
+                ship.position.x - state.position.x,
+                ship.position.y - state.position.y,
+            );
+            assert!(error < 0.5, "Prediction is {} away from the server", error);
+        }
+    }
+}
+
+/// Polls the clients until `done` says so, or gives up after a second
+fn poll_until<F>(clients: &mut [&mut HeadlessClient], mut done: F) -> bool
+where
+    F: FnMut(&[&mut HeadlessClient]) -> bool,
+{
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_secs(1) {
+        for client in clients.iter_mut() {
+            // A rejected client is disconnected once it has been told
+            client.poll().ok();
+        }
+        if done(clients) {
+            return true;
+        }
+        thread::sleep(Duration::from_millis(5));
+    }
+    false
+}
+
+fn is_waiting(client: &HeadlessClient, joined: usize) -> bool {
+    matches!(client.race.state, OnlineState::Waiting { joined: j, .. } if j == joined)
+}
+
+#[test]
+fn players_who_leave_give_up_their_place() {
+    let server = RelayServer::bind("127.0.0.1:0").unwrap();
+    let url = format!("ws://{}", server.local_addr().unwrap());
+    thread::spawn(move || server.run());
+
+    let mut leaver = HeadlessClient::connect(&url, "test", 2).unwrap();
+    assert!(poll_until(&mut [&mut leaver], |c| is_waiting(c[0], 1)));
+    drop(leaver);
+
+    // The room is empty again, so the next player waits for another
+    // rather than racing against nobody
+    let mut first = HeadlessClient::connect(&url, "test", 2).unwrap();
+    assert!(poll_until(&mut [&mut first], |c| is_waiting(c[0], 1)));
+
+    let mut second = HeadlessClient::connect(&url, "test", 2).unwrap();
+    assert!(poll_until(&mut [&mut first, &mut second], |c| {
+        c.iter()
+            .all(|client| matches!(client.race.state, OnlineState::Racing))
+    }));
+    let mut player_ids = [
+        first.gameplay.online_player.unwrap(),
+        second.gameplay.online_player.unwrap(),
+    ];
+    player_ids.sort_unstable();
+    assert_eq!(player_ids, [0, 1]);
+}
+
+#[test]
+fn rooms_only_take_the_number_of_players_they_were_made_for() {
+    let server = RelayServer::bind("127.0.0.1:0").unwrap();
+    let url = format!("ws://{}", server.local_addr().unwrap());
+    thread::spawn(move || server.run());
+
+    let mut waiting = HeadlessClient::connect(&url, "test", 2).unwrap();
+    assert!(poll_until(&mut [&mut waiting], |c| is_waiting(c[0], 1)));
+
+    let mut other = HeadlessClient::connect(&url, "test", 3).unwrap();
+    assert!(poll_until(&mut [&mut waiting, &mut other], |c| {
+        matches!(c[1].race.state, OnlineState::Rejected { players: 2 })
+    }));
+    assert!(is_waiting(&waiting, 1));
+}
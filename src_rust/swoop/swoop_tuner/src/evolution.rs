@@ -0,0 +1,109 @@
+use swoop_win_condition::rng::Rng;
+
+/// The fraction of each generation that is kept to breed the next one
+const PARENT_FRACTION: usize = 4;
+
+/// How far (in the 0 to 1 range of each gene) children are mutated to
+/// begin with, and how much less each generation
+const INITIAL_MUTATION: f32 = 0.15;
+const MUTATION_DECAY: f32 = 0.9;
+
+/// A simple genetic algorithm. Each genome is a list of genes between
+/// 0 and 1. Each generation, the best genomes are kept and the rest are
+/// replaced by crossing over and mutating them. The search is seeded
+/// so the same settings always give the same result.
+pub struct Evolution {
+    rng: Rng,
+    population: Vec<Vec<f32>>,
+    mutation: f32,
+
+    /// The best genome seen so far and its score
+    best: Option<(Vec<f32>, f64)>,
+}
+
+impl Evolution {
+    /// Starts with a population made from mutations of `start`, which
+    /// is also kept as it is.
+    pub fn new(seed: u32, start: Vec<f32>, population_size: usize) -> Self {
+        let mut evolution = Self {
+            rng: Rng::new(seed),
+            population: vec![],
+            mutation: INITIAL_MUTATION,
+            best: None,
+        };
+        evolution.population.push(start.clone());
+        while evolution.population.len() < population_size {
+            let child = evolution.mutate(&start);
+            evolution.population.push(child);
+        }
+        evolution
+    }
+
+    /// The genomes that need to be scored for this generation
+    pub fn get_population(&self) -> &[Vec<f32>] {
+        &self.population
+    }
+
+    pub fn get_best(&self) -> Option<(&[f32], f64)> {
+        self.best
+            .as_ref()
+            .map(|(genome, score)| (genome.as_slice(), *score))
+    }
+
+    /// Breeds the next generation from the scores of this one, where
+    /// a lower score is better. There should be a score for each
+    /// genome in the population.
+    pub fn next_generation(&mut self, scores: &[f64]) {
+        let mut ranked: Vec<(Vec<f32>, f64)> = self
+            .population
+            .drain(..)
+            .zip(scores.iter().cloned())
+            .collect();
+        ranked.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+
+        let improved = match &self.best {
+            Some((_, best_score)) => ranked[0].1 < *best_score,
+            None => true,
+        };
+        if improved {
+            self.best = Some(ranked[0].clone());
+        }
+
+        let population_size = ranked.len();
+        let num_parents = (population_size / PARENT_FRACTION).max(1);
+        let parents: Vec<Vec<f32>> = ranked
+            .into_iter()
+            .take(num_parents)
+            .map(|(genome, _)| genome)
+            .collect();
+
+        // The parents carry on so a good genome is never lost
+        self.population = parents.clone();
+        while self.population.len() < population_size {
+            let mother = &parents[self.rng.next_u32() as usize % parents.len()];
+            let father = &parents[self.rng.next_u32() as usize % parents.len()];
+            let child: Vec<f32> = mother
+                .iter()
+                .zip(father.iter())
+                .map(|(m, f)| if self.rng.next_f32() < 0.5 { *m } else { *f })
+                .collect();
+            let child = self.mutate(&child);
+            self.population.push(child);
+        }
+        self.mutation *= MUTATION_DECAY;
+    }
+
+    fn mutate(&mut self, genome: &[f32]) -> Vec<f32> {
+        genome
+            .iter()
+            .map(|gene| (gene + self.next_gaussian() * self.mutation).clamp(0.0, 1.0))
+            .collect()
+    }
+
+    /// A normally distributed random number (Box-Muller transform)
+    fn next_gaussian(&mut self) -> f32 {
+        let u1 = 1.0 - self.rng.next_f32();
+        let u2 = self.rng.next_f32();
+        f32::sqrt(-2.0 * u1.ln()) * f32::cos(2.0 * std::f32::consts::PI * u2)
+    }
+}
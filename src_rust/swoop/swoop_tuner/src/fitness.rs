@@ -0,0 +1,309 @@
+use swoop_win_condition::ai::calc_ai_control;
+use swoop_win_condition::ai_params::{AiParams, NUM_AI_PARAMS};
+use swoop_win_condition::difficulty::Difficulty;
+use swoop_win_condition::gameplay::{NUM_LAPS_TO_WIN, SIM_TIME_STEP};
+use swoop_win_condition::headless::Simulation;
+use swoop_win_condition::map::Map;
+use swoop_win_condition::physics::{calc_ship_physics, Broadphase};
+use swoop_win_condition::racing_line::RacingLine;
+use swoop_win_condition::ship::Ship;
+use swoop_win_condition::track::{built_in_tracks, Track};
+
+/// The smallest and largest value the tuner tries for each parameter,
+/// in the same order as `AI_PARAM_NAMES`
+pub const PARAM_RANGES: [(f32, f32); NUM_AI_PARAMS] = [
+    (0.2, 1.5),  // lookahead_distance
+    (0.05, 0.6), // lookahead_time
+    (0.0, 0.4),  // reaction_time
+    (1.0, 10.0), // velocity_gain
+    (0.5, 5.0),  // steering_gain
+    (0.0, 0.5),  // steering_damping
+    (0.15, 0.5), // passing_gap
+    (0.15, 0.8), // follow_distance
+    (1.0, 6.0),  // follow_gain
+    (0.5, 1.0),  // min_speed_scale
+];
+
+/// Races are stopped after this long, even if the AI hasn't finished
+const MAX_RACE_TIME: f64 = 120.0;
+
+/// How many seconds each problem adds to the score. Scraping along a
+/// wall or another ship is scored per simulation step.
+const WALL_PENALTY: f64 = 0.01;
+const CONTACT_PENALTY: f64 = 0.05;
+
+/// Added to the score for each race the parameters fail: an AI ship
+/// didn't finish every lap, or it ran into a ship that had stopped.
+/// These are what `tests/headless.rs` checks, so no amount of speed
+/// makes up for them, but failing fewer races still scores better.
+const REJECTED: f64 = 1000.0;
+
+/// How far around the lap (as a fraction of the racing line) the
+/// player's ship is parked
+const PARKED_FRACTION: usize = 4;
+
+/// Ships closer than this are touching
+const CONTACT_DISTANCE: f32 = 0.11;
+
+/// Ships closer than this have run into each other
+const HIT_DISTANCE: f32 = 0.1;
+
+/// Where the ships start on the racing line when the AI comes up behind
+/// a stopped ship, and how long it has to get past
+const APPROACH_INDEX: usize = 10;
+const STOPPED_INDEX: usize = 40;
+const APPROACH_TIME: f64 = 3.0;
+
+/// How long (on average) the AI ships should take to finish a race on
+/// each difficulty. Insane is as fast as possible.
+pub fn target_race_time(difficulty: Difficulty) -> Option<f64> {
+    match difficulty {
+        Difficulty::Easy => Some(72.0),
+        Difficulty::Normal => Some(64.0),
+        Difficulty::Hard => Some(60.0),
+        Difficulty::Insane => None,
+    }
+}
+
+/// The built-in tracks with features or a width that changes, which
+/// the AI is raced on as well as the generated tracks. They are raced
+/// over the normal number of laps so the target race times still fit.
+pub fn tuning_tracks() -> Vec<Track> {
+    built_in_tracks()
+        .into_iter()
+        .filter(|track| !track.map.features.is_empty() || has_varying_width(&track.map))
+        .map(|mut track| {
+            track.laps = NUM_LAPS_TO_WIN - 1;
+            track
+        })
+        .collect()
+}
+
+fn has_varying_width(map: &Map) -> bool {
+    let spline_widths = match &map.spline {
+        Some(spline) => spline.get_widths(),
+        None => &[],
+    };
+    map.width_sin_consts
+        .iter()
+        .chain(map.width_cos_consts.iter())
+        .chain(spline_widths.iter())
+        .any(|width| *width != 0.0)
+}
+
+/// Converts parameters to genes between 0 and 1
+pub fn to_genome(params: &AiParams) -> Vec<f32> {
+    params
+        .to_values()
+        .iter()
+        .zip(PARAM_RANGES.iter())
+        .map(|(value, (min, max))| ((value - min) / (max - min)).clamp(0.0, 1.0))
+        .collect()
+}
+
+pub fn from_genome(genome: &[f32]) -> AiParams {
+    let mut values = [0.0; NUM_AI_PARAMS];
+    for (value, (gene, (min, max))) in values
+        .iter_mut()
+        .zip(genome.iter().zip(PARAM_RANGES.iter()))
+    {
+        *value = min + gene * (max - min);
+    }
+    AiParams::from_values(values)
+}
+
+/// What happened to the AI ships in a race
+#[derive(Debug)]
+pub struct RaceStats {
+    /// Whether every AI ship did all the laps within MAX_RACE_TIME
+    pub finished: bool,
+    /// Whether an AI ship ran into the player's parked ship
+    pub hit_parked_ship: bool,
+    /// The average time the AI ships took to finish. Ships that didn't
+    /// finish count as taking MAX_RACE_TIME.
+    pub average_race_time: f64,
+    /// How many simulation steps an AI ship spent touching a wall
+    pub wall_steps: u64,
+    /// How many simulation steps an AI ship spent touching another
+    /// ship, including the player's
+    pub contact_steps: u64,
+}
+
+/// Races the AI on a track, or on the track generated from `seed` if
+/// there isn't one. The player's ship is parked on the racing line
+/// part way around the lap, so the AI also has to get past a ship in
+/// its way at full speed.
+pub fn race(
+    params: &AiParams,
+    difficulty: Difficulty,
+    seed: u32,
+    track: Option<&Track>,
+) -> RaceStats {
+    let mut sim = Simulation::new(seed);
+    sim.gameplay.difficulty = difficulty;
+    match track {
+        Some(track) => sim.gameplay.reset_on_track(track),
+        None => sim.gameplay.reset(seed),
+    }
+    sim.gameplay.ai_params = params.clone();
+
+    let racing_line = &sim.gameplay.racing_line;
+    let parked_index = racing_line.points.len() / PARKED_FRACTION;
+    let parked_position = racing_line.points[parked_index].position;
+    let direction = racing_line.get_direction(parked_index);
+    let player = &mut sim.gameplay.ship_entities[0];
+    player.position.x = parked_position.0;
+    player.position.y = parked_position.1;
+    player.position.rot = f32::atan2(-direction.0, direction.1);
+    player.prev_position = player.position;
+
+    let mut hit_parked_ship = false;
+    let mut wall_steps = 0;
+    let mut contact_steps = 0;
+    let max_steps = ((MAX_RACE_TIME - sim.race_time()) / SIM_TIME_STEP) as u64;
+    for _ in 0..max_steps {
+        if sim.gameplay.scores[1..]
+            .iter()
+            .all(|score| score.finished())
+        {
+            break;
+        }
+        sim.step();
+
+        let ships = &sim.gameplay.ship_entities;
+        for (id, ship) in ships.iter().enumerate() {
+            let position = (ship.position.x, ship.position.y);
+            if id > 0 && sim.gameplay.map.distance_field(position) > -CONTACT_DISTANCE * 0.5 {
+                wall_steps += 1;
+            }
+            // Running into the player's ship counts too
+            for other in ships.iter().skip(id + 1) {
+                let gap = (
+                    other.position.x - ship.position.x,
+                    other.position.y - ship.position.y,
+                );
+                let distance = (gap.0 * gap.0 + gap.1 * gap.1).sqrt();
+                if distance < CONTACT_DISTANCE {
+                    contact_steps += 1;
+                }
+                hit_parked_ship |= id == 0 && distance < HIT_DISTANCE;
+            }
+        }
+    }
+
+    let ai_scores = &sim.gameplay.scores[1..];
+    let race_times: Vec<f64> = ai_scores
+        .iter()
+        .map(|score| match score.laps.get(score.laps_to_win - 1) {
+            Some(race_time) => *race_time,
+            None => MAX_RACE_TIME,
+        })
+        .collect();
+
+    RaceStats {
+        finished: ai_scores.iter().all(|score| score.finished()),
+        hit_parked_ship,
+        average_race_time: race_times.iter().sum::<f64>() / race_times.len() as f64,
+        wall_steps,
+        contact_steps,
+    }
+}
+
+/// Whether an AI ship coming up behind a stopped ship at full speed
+/// gets past it without touching it, as `tests/headless.rs` checks
+pub fn drives_around_stopped_ship(
+    params: &AiParams,
+    skill: f32,
+    map: &Map,
+    racing_line: &RacingLine,
+) -> bool {
+    let place_ship = |index: usize, speed: f32| {
+        let mut ship = Ship::new((1.0, 1.0, 1.0, 1.0));
+        let point = &racing_line.points[index];
+        let direction = racing_line.get_direction(index);
+        ship.position.x = point.position.0;
+        ship.position.y = point.position.1;
+        ship.position.rot = f32::atan2(-direction.0, direction.1);
+        ship.velocity.x = direction.0 * speed;
+        ship.velocity.y = direction.1 * speed;
+        ship
+    };
+    let speed = racing_line.points[APPROACH_INDEX].target_speed;
+    let mut ships = vec![
+        place_ship(APPROACH_INDEX, speed),
+        place_ship(STOPPED_INDEX, 0.0),
+    ];
+    let mut snapshot = ships.clone();
+
+    let mut broadphase = Broadphase::new(ships.len());
+    for _ in 0..(APPROACH_TIME / SIM_TIME_STEP) as usize {
+        snapshot.clone_from(&ships);
+        calc_ai_control(&mut ships[0], skill, params, map, racing_line, &snapshot, 0);
+        calc_ship_physics(
+            &mut ships,
+            map,
+            SIM_TIME_STEP as f32,
+            &mut broadphase,
+            &mut vec![],
+        );
+
+        let gap = (
+            ships[0].position.x - ships[1].position.x,
+            ships[0].position.y - ships[1].position.y,
+        );
+        if (gap.0 * gap.0 + gap.1 * gap.1).sqrt() <= HIT_DISTANCE {
+            return false;
+        }
+    }
+    racing_line.get_index(map, (ships[0].position.x, ships[0].position.y)) > STOPPED_INDEX
+}
+
+/// Scores a set of parameters by racing on the tracks generated from
+/// each seed and on each of `tracks`. Lower is better: the score is
+/// how far the race time is from the target (or just the race time on
+/// Insane) plus the penalties for driving into things. Parameters that
+/// fail a race, or that run into a stopped ship at the slowest or
+/// fastest skill of the difficulty, are rejected (see `is_rejected`).
+pub fn evaluate(params: &AiParams, difficulty: Difficulty, seeds: &[u32], tracks: &[Track]) -> f64 {
+    let (min_skill, max_skill) = difficulty.get_skill_range();
+    let courses: Vec<(u32, Option<&Track>)> = seeds
+        .iter()
+        .map(|seed| (*seed, None))
+        .chain(tracks.iter().map(|track| (0, Some(track))))
+        .collect();
+
+    let mut total = 0.0;
+    let mut failures = 0;
+    for (seed, track) in courses.iter() {
+        let mut sim = Simulation::new(*seed);
+        if let Some(track) = track {
+            sim.gameplay.reset_on_track(track);
+        }
+        let (map, racing_line) = (&sim.gameplay.map, &sim.gameplay.racing_line);
+        if [min_skill, max_skill]
+            .iter()
+            .any(|skill| !drives_around_stopped_ship(params, *skill, map, racing_line))
+        {
+            failures += 1;
+        }
+
+        let stats = race(params, difficulty, *seed, *track);
+        if !stats.finished || stats.hit_parked_ship {
+            failures += 1;
+        }
+        let time_score = match target_race_time(difficulty) {
+            Some(target) => (stats.average_race_time - target).abs(),
+            None => stats.average_race_time,
+        };
+        total += time_score
+            + stats.wall_steps as f64 * WALL_PENALTY
+            + stats.contact_steps as f64 * CONTACT_PENALTY;
+    }
+    total / courses.len() as f64 + failures as f64 * REJECTED
+}
+
+/// Whether a score from `evaluate` belongs to parameters that failed
+/// a race
+pub fn is_rejected(score: f64) -> bool {
+    score >= REJECTED
+}
@@ -0,0 +1,5 @@
+//! Tunes the AI's parameters by racing it against itself on lots of
+//! tracks and keeping the parameters that drive the way each
+//! difficulty should.
+pub mod evolution;
+pub mod fitness;
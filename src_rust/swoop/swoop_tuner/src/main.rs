@@ -0,0 +1,144 @@
+use std::env;
+use std::fs;
+
+use swoop_tuner::evolution::Evolution;
+use swoop_tuner::fitness::{evaluate, from_genome, is_rejected, to_genome, tuning_tracks};
+use swoop_win_condition::ai_params::{format_param_sets, parse_param_sets, AiParams};
+use swoop_win_condition::difficulty::Difficulty;
+use swoop_win_condition::rng::Rng;
+
+const USAGE: &str = "Usage:
+    swoop_tuner [--difficulty <name|all>] [--generations <n>] [--population <n>]
+                [--races <n>] [--seed <n>] [--output <file>]
+
+The tuned parameters are merged into the output file, which can then be
+copied to swoop_win_condition/src/resources/ai_params.txt
+
+eg: swoop_tuner --difficulty hard --generations 20 --output ai_params.txt";
+
+/// Written at the top of a new output file. Comments already at the
+/// top of the output file are kept instead.
+const HEADER: &str = "# AI parameters for each difficulty, written by swoop_tuner\n";
+
+struct Settings {
+    difficulties: Vec<Difficulty>,
+    generations: usize,
+    population: usize,
+    races: usize,
+    seed: u32,
+    output: String,
+}
+
+impl Settings {
+    fn defaults() -> Self {
+        Self {
+            difficulties: Difficulty::ALL.to_vec(),
+            generations: 15,
+            population: 16,
+            races: 3,
+            seed: 1,
+            output: "ai_params.txt".to_string(),
+        }
+    }
+
+    fn from_args(args: &[&str]) -> Option<Self> {
+        let mut settings = Self::defaults();
+        for pair in args.chunks(2) {
+            let (flag, value) = match pair {
+                [flag, value] => (*flag, *value),
+                _ => return None,
+            };
+            match flag {
+                "--difficulty" if value.eq_ignore_ascii_case("all") => {
+                    settings.difficulties = Difficulty::ALL.to_vec()
+                }
+                "--difficulty" => settings.difficulties = vec![value.parse().ok()?],
+                "--generations" => settings.generations = value.parse().ok()?,
+                "--population" => settings.population = value.parse().ok()?,
+                "--races" => settings.races = value.parse().ok()?,
+                "--seed" => settings.seed = value.parse().ok()?,
+                "--output" => settings.output = value.to_string(),
+                _ => return None,
+            }
+        }
+        if settings.population < 2 || settings.races == 0 {
+            return None;
+        }
+        Some(settings)
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let args: Vec<&str> = args.iter().skip(1).map(|arg| arg.as_str()).collect();
+
+    match Settings::from_args(&args) {
+        Some(settings) => tune(&settings),
+        None => println!("{}", USAGE),
+    }
+}
+
+fn tune(settings: &Settings) {
+    // Keep any difficulties that aren't being tuned this time, and any
+    // notes about them
+    let existing = fs::read_to_string(&settings.output).unwrap_or_default();
+    let mut sets = parse_param_sets(&existing).expect("Output file has invalid parameters");
+    let notes: String = existing
+        .lines()
+        .take_while(|line| line.starts_with('#'))
+        .map(|line| format!("{}\n", line))
+        .collect();
+    let header = if notes.is_empty() { HEADER } else { &notes };
+
+    for difficulty in settings.difficulties.iter() {
+        let params = tune_difficulty(*difficulty, settings);
+        sets.retain(|(set_difficulty, _)| set_difficulty != difficulty);
+        sets.push((*difficulty, params));
+    }
+
+    sets.sort_by_key(|(difficulty, _)| {
+        Difficulty::ALL
+            .iter()
+            .position(|d| d == difficulty)
+            .unwrap_or(0)
+    });
+    let text = format!("{}{}", header, format_param_sets(&sets));
+    fs::write(&settings.output, text).expect("Failed to write output file");
+    println!("Written to {}", settings.output);
+}
+
+fn tune_difficulty(difficulty: Difficulty, settings: &Settings) -> AiParams {
+    // The same tracks are raced every generation so the scores can be
+    // compared
+    let mut rng = Rng::new(settings.seed);
+    let seeds: Vec<u32> = (0..settings.races).map(|_| rng.next_u32()).collect();
+    let tracks = tuning_tracks();
+
+    let start = to_genome(&AiParams::for_difficulty(difficulty));
+    let mut evolution = Evolution::new(settings.seed, start, settings.population);
+
+    println!("Tuning {}", difficulty);
+    for generation in 0..settings.generations {
+        let scores: Vec<f64> = evolution
+            .get_population()
+            .iter()
+            .map(|genome| evaluate(&from_genome(genome), difficulty, &seeds, &tracks))
+            .collect();
+        evolution.next_generation(&scores);
+
+        let (_, best_score) = evolution.get_best().expect("No generations scored");
+        println!(
+            "  Generation {}: best score {:.2}",
+            generation + 1,
+            best_score
+        );
+    }
+
+    match evolution.get_best() {
+        Some((genome, score)) if !is_rejected(score) => from_genome(genome),
+        _ => {
+            println!("  Every set of parameters was rejected, keeping the current ones");
+            AiParams::for_difficulty(difficulty)
+        }
+    }
+}
@@ -0,0 +1,52 @@
+use swoop_tuner::evolution::Evolution;
+use swoop_tuner::fitness::{evaluate, from_genome, is_rejected, to_genome, tuning_tracks};
+use swoop_win_condition::ai_params::AiParams;
+use swoop_win_condition::difficulty::Difficulty;
+
+/// How far a genome is from the middle of every gene
+fn distance_from_middle(genome: &[f32]) -> f64 {
+    genome.iter().map(|gene| (*gene as f64 - 0.5).powi(2)).sum()
+}
+
+#[test]
+fn evolution_finds_a_minimum() {
+    let mut evolution = Evolution::new(1, vec![0.0; 4], 16);
+    for _ in 0..40 {
+        let scores: Vec<f64> = evolution
+            .get_population()
+            .iter()
+            .map(|genome| distance_from_middle(genome))
+            .collect();
+        evolution.next_generation(&scores);
+    }
+
+    let (genome, score) = evolution.get_best().unwrap();
+    assert!(score < 0.01, "Best score {} for {:?}", score, genome);
+}
+
+#[test]
+fn genomes_convert_to_params() {
+    let params = AiParams::defaults();
+    let converted = from_genome(&to_genome(&params));
+    for (before, after) in params.to_values().iter().zip(converted.to_values().iter()) {
+        assert!((before - after).abs() < 1e-4, "{} != {}", before, after);
+    }
+}
+
+#[test]
+fn tuning_races_on_tracks_with_features_and_widths() {
+    let tracks = tuning_tracks();
+    let names: Vec<&str> = tracks.iter().map(|track| track.name.as_str()).collect();
+    assert!(names.contains(&"Narrows"), "{:?}", names);
+    assert!(names.contains(&"Stunts"), "{:?}", names);
+    assert!(!names.contains(&"Oval"), "{:?}", names);
+}
+
+#[test]
+fn ai_that_cant_finish_is_rejected() {
+    // Without any steering the AI drives straight into the wall
+    let mut params = AiParams::defaults();
+    params.steering_gain = 0.0;
+    let score = evaluate(&params, Difficulty::Normal, &[1], &[]);
+    assert!(is_rejected(score), "Score {}", score);
+}
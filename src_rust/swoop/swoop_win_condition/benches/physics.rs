@@ -0,0 +1,88 @@
+//! Times the ship physics with big fields of ships, as in a
+//! battle-royale race. Run with `cargo bench`.
+//!
+//! The ships start packed onto the track a few to a row and drive flat
+//! out without steering, so they pile into each other and the walls.
+use std::time::Instant;
+
+use swoop_win_condition::gameplay::SIM_TIME_STEP;
+use swoop_win_condition::headless::Simulation;
+use swoop_win_condition::map::Map;
+use swoop_win_condition::physics::{calc_ship_physics, Broadphase};
+use swoop_win_condition::ship::Ship;
+use swoop_win_condition::transform::normalize;
+
+const FIELD_SIZES: [usize; 4] = [4, 64, 128, 256];
+
+/// How much racing to simulate for each field
+const RACE_TIME: f64 = 20.0;
+
+const SHIPS_PER_ROW: usize = 4;
+const SHIP_SPACING: f32 = 0.12;
+/// How far around the lap each row is from the one in front
+const ROW_FRACTION: f32 = 0.004;
+
+fn main() {
+    let map = Simulation::new(0).gameplay.map;
+    let num_steps = (RACE_TIME / SIM_TIME_STEP) as usize;
+
+    for num_ships in FIELD_SIZES.iter() {
+        let mut ships = make_field(&map, *num_ships);
+        let mut broadphase = Broadphase::new(*num_ships);
+        let mut collisions = vec![];
+        let mut num_checks = 0;
+        let mut num_collisions = 0;
+
+        let start = Instant::now();
+        for _ in 0..num_steps {
+            collisions.clear();
+            calc_ship_physics(
+                &mut ships,
+                &map,
+                SIM_TIME_STEP as f32,
+                &mut broadphase,
+                &mut collisions,
+            );
+            num_checks += broadphase.num_checks;
+            num_collisions += collisions.len();
+        }
+        let elapsed = start.elapsed();
+
+        println!(
+            "{:>4} ships: {:>10.2?} per step, {:>8.1} pairs checked and {:>6.1} bumps per step ({} pairs in total)",
+            num_ships,
+            elapsed / num_steps as u32,
+            num_checks as f64 / num_steps as f64,
+            num_collisions as f64 / num_steps as f64,
+            num_ships * (num_ships - 1) / 2,
+        );
+    }
+}
+
+/// Lines the ships up in rows going backwards around the track from
+/// the start line, all moving along the track
+fn make_field(map: &Map, num_ships: usize) -> Vec<Ship> {
+    (0..num_ships)
+        .map(|id| {
+            let row = id / SHIPS_PER_ROW;
+            let column = (id % SHIPS_PER_ROW) as f32 - (SHIPS_PER_ROW - 1) as f32 * 0.5;
+            let fraction = 1.0 - row as f32 * ROW_FRACTION;
+            let (center, across) = map.get_track_point(fraction % 1.0);
+            let (ahead, _) = map.get_track_point((fraction + ROW_FRACTION) % 1.0);
+            let direction = normalize((ahead.0 - center.0, ahead.1 - center.1));
+
+            let mut ship = Ship::new((1.0, 1.0, 1.0, 1.0));
+            ship.position.x = center.0 + across.0 * column * SHIP_SPACING;
+            ship.position.y = center.1 + across.1 * column * SHIP_SPACING;
+            ship.position.rot = f32::atan2(-direction.0, direction.1);
+            ship.prev_position = ship.position;
+            // Faster ships a few rows back catch up with the ones in
+            // front of them
+            let speed = 1.0 + (row % 3) as f32;
+            ship.velocity.x = direction.0 * speed;
+            ship.velocity.y = direction.1 * speed;
+            ship.linear_thrust = 1.0;
+            ship
+        })
+        .collect()
+}
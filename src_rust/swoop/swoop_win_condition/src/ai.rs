@@ -1,59 +1,230 @@
+use super::ai_params::AiParams;
 use super::map::Map;
-use super::ship::Ship;
-use super::transform::{PolarCoordinate, Vec2};
+use super::racing_line::RacingLine;
+use super::ship::{Ship, ENGINE_THRUST, LINEAR_DAMPING};
+use super::transform::{length, normalize, Vec2};
 use std::f32::consts::PI;
 
+/// Other ships closer than this along the track are raced against
+const AWARENESS_RANGE: f32 = 2.0;
+
+/// How close to the edge of the track the AI will go to get past
+const EDGE_MARGIN: f32 = 0.1;
+
+/// How finely the AI looks for the edges of the track
+const ROOM_STEP: f32 = 0.05;
+
+/// AI at least this good will move across to block a ship that is
+/// catching it
+const DEFEND_SKILL: f32 = 0.5;
+/// How far from the racing line a defending ship will go
+const MAX_DEFEND_OFFSET: f32 = 0.25;
+
+/// Where another ship is compared to the one being driven, measured
+/// along and across the racing line
+struct NearbyShip {
+    /// Positive when the other ship is in front
+    along: f32,
+    /// How far the other ship is to the side of the racing line
+    side: f32,
+    /// How fast the other ship is going along the track
+    speed: f32,
+}
+
 /// Drives a ship around a map using AI. The skill parameter should range
 /// from 0 to 1 and controls how good the player is from pretty average
-/// to inhumanly good.
-pub fn calc_ai_control(ship: &mut Ship, skill: f32, map: &Map) {
-    let mut steering = 0.0;
-    let mut thrust = 0.0;
+/// to inhumanly good. The other ships are overtaken (or followed if
+/// there is no room to get past) and good drivers defend against them.
+/// The other ships are taken from `all_ships`, skipping the one at
+/// `ship_id`, which is this ship before it was steered.
+/// The rest of how the AI drives is controlled by the `params`.
+#[allow(clippy::too_many_arguments)]
+pub fn calc_ai_control(
+    ship: &mut Ship,
+    skill: f32,
+    params: &AiParams,
+    map: &Map,
+    racing_line: &RacingLine,
+    all_ships: &[Ship],
+    ship_id: usize,
+) {
+    let position = (ship.position.x, ship.position.y);
+    let velocity = (ship.velocity.x, ship.velocity.y);
+    let speed = length(&velocity);
+    let index = racing_line.get_index(map, position);
+
+    let (offset, speed_limit) =
+        plan_around_traffic(ship, skill, params, map, racing_line, all_ships, ship_id);
+
+    let speed_scale = calc_speed_scale(skill, params);
+    let speed_index = racing_line.get_index_ahead(index, speed * params.reaction_time);
+    let mut target_speed = racing_line.points[speed_index].target_speed * speed_scale;
+    if let Some(speed_limit) = speed_limit {
+        target_speed = f32::min(target_speed, speed_limit);
+    }
 
-    let lookahead_mul = skill;
+    let target_index = racing_line.get_index_ahead(
+        index,
+        params.lookahead_distance + speed * params.lookahead_time,
+    );
+    let target_sideways = sideways(racing_line.get_direction(target_index));
+    let target = racing_line.points[target_index].position;
+    let target = (
+        target.0 + target_sideways.0 * offset,
+        target.1 + target_sideways.1 * offset,
+    );
+    let to_target = normalize((target.0 - position.0, target.1 - position.1));
 
-    steering += calc_steering_input(&ship, &map, 1.0 * lookahead_mul) * 0.15;
-    steering += calc_steering_input(&ship, &map, 0.5 * lookahead_mul) * 0.45;
-    steering += calc_steering_input(&ship, &map, 0.2 * lookahead_mul) * 0.4;
+    // The acceleration that would give the velocity the AI wants,
+    // including enough to overcome the damping once it gets there.
+    // Boost pads do some of the work and sticky zones add damping.
+    let surface = map.get_surface(position);
+    let damping = LINEAR_DAMPING + surface.damping;
+    let desired_velocity = (to_target.0 * target_speed, to_target.1 * target_speed);
+    let acceleration = (
+        (desired_velocity.0 - velocity.0) * params.velocity_gain + desired_velocity.0 * damping
+            - surface.acceleration.0,
+        (desired_velocity.1 - velocity.1) * params.velocity_gain + desired_velocity.1 * damping
+            - surface.acceleration.1,
+    );
 
-    thrust += 1.0;
+    // The engine only pushes along the nose, so point the nose where
+    // the acceleration is needed. When braking, keep facing forwards
+    // and use reverse thrust instead.
+    let braking = dot(acceleration, to_target) < 0.0;
+    let heading = if braking { to_target } else { acceleration };
+    let target_angle = f32::atan2(-heading.0, heading.1);
+    let angular_error = wrap_angle(target_angle - ship.position.rot);
+    let steering =
+        angular_error * params.steering_gain - ship.velocity.rot * params.steering_damping;
+
+    let forwards = (-f32::sin(ship.position.rot), f32::cos(ship.position.rot));
+    let thrust = dot(forwards, acceleration) / ENGINE_THRUST;
 
-    ship.angular_thrust = f32::max(f32::min(steering, 1.0), -1.0);
-    ship.linear_thrust = f32::max(f32::min(thrust, 1.0), -1.0);
+    ship.angular_thrust = steering.clamp(-1.0, 1.0);
+    ship.linear_thrust = thrust.clamp(-1.0, 1.0);
 }
 
-/// Computes a steering input to try avoid collisions at the ships
-/// position at the provided time in the future.
-/// This is done by looking at the position in the track (ie is the
-/// ship near the edge of the track) and if the ship is facing in the
-/// direction of travel of the track.
-fn calc_steering_input(ship: &Ship, map: &Map, lookahead_time: f32) -> f32 {
-    let polar_position = PolarCoordinate::from_cartesian(predict_position(ship, lookahead_time));
+/// Decides how far to the side of the racing line to drive to get
+/// around the other ships, and how fast to go when stuck behind one.
+fn plan_around_traffic(
+    ship: &Ship,
+    skill: f32,
+    params: &AiParams,
+    map: &Map,
+    racing_line: &RacingLine,
+    all_ships: &[Ship],
+    ship_id: usize,
+) -> (f32, Option<f32>) {
+    let position = (ship.position.x, ship.position.y);
+    let index = racing_line.get_index(map, position);
+    let line_point = racing_line.points[index].position;
+    let forwards = racing_line.get_direction(index);
+    let sideways = sideways(forwards);
 
-    let track_direction_here = map.get_track_direction(polar_position.angle);
-    let track_radius_here = map.track_radius(polar_position.angle);
+    let relative_to_line = |point: Vec2| (point.0 - line_point.0, point.1 - line_point.1);
+    let own_side = dot(relative_to_line(position), sideways);
+    let own_speed = dot((ship.velocity.x, ship.velocity.y), forwards);
+    let track_width = map.calc_track_width(line_point);
 
-    let mut steering = 0.0;
+    let nearby = || {
+        all_ships
+            .iter()
+            .enumerate()
+            .filter(|(other_id, _)| *other_id != ship_id)
+            .map(|(_, other)| {
+                let offset = relative_to_line((other.position.x, other.position.y));
+                NearbyShip {
+                    along: dot(offset, forwards) - dot(relative_to_line(position), forwards),
+                    side: dot(offset, sideways),
+                    speed: dot((other.velocity.x, other.velocity.y), forwards),
+                }
+            })
+            .filter(|other| {
+                // Ships on another part of the track can be close by but
+                // aren't in the way
+                other.along.abs() < AWARENESS_RANGE && other.side.abs() < track_width * 2.0
+            })
+    };
 
-    let radius_error = track_radius_here - polar_position.radius;
-    let radius_steering_input = f32::max(f32::min(radius_error, PI / 2.0), -PI / 2.0);
+    // How far the ship can go to each side of the line
+    let has_room = |offset: f32| {
+        let point = (
+            line_point.0 + sideways.0 * offset,
+            line_point.1 + sideways.1 * offset,
+        );
+        map.distance_field(point) < -EDGE_MARGIN
+    };
+    let find_edge = |direction: f32| {
+        let mut edge = 0.0;
+        while edge < track_width * 2.0 && has_room(edge + direction * ROOM_STEP) {
+            edge += direction * ROOM_STEP;
+        }
+        edge
+    };
+    let (right_edge, left_edge) = (find_edge(-1.0), find_edge(1.0));
 
-    let mut target_angle = 0.0;
-    target_angle += track_direction_here; // Face direction of track
-    target_angle += radius_steering_input; // Fly towards track center
+    // The closest ship in front that is being caught up, or would be
+    // if it wasn't holding this one back. Otherwise a ship that has
+    // slowed down to follow never tries to get past.
+    let wanted_speed = f32::max(
+        own_speed,
+        racing_line.points[index].target_speed * calc_speed_scale(skill, params),
+    );
+    let ahead = nearby()
+        .filter(|other| other.along > 0.0 && other.speed < wanted_speed)
+        .min_by(|a, b| a.along.partial_cmp(&b.along).unwrap());
+    // The closest ship behind that is catching up
+    let behind = nearby()
+        .filter(|other| other.along < 0.0 && other.speed > own_speed)
+        .max_by(|a, b| a.along.partial_cmp(&b.along).unwrap());
 
-    let angular_error = wrap_angle(target_angle - ship.position.rot);
-    steering += angular_error;
+    let mut offset = 0.0;
+    if let Some(ahead) = ahead {
+        // Go past on whichever side the ship is already on, unless
+        // there's more room on the other side
+        let side = if own_side >= ahead.side { 1.0 } else { -1.0 };
+        let preferred = (ahead.side + side * params.passing_gap).clamp(right_edge, left_edge);
+        let other = (ahead.side - side * params.passing_gap).clamp(right_edge, left_edge);
+        offset = if (preferred - ahead.side).abs() >= (other - ahead.side).abs() {
+            preferred
+        } else {
+            other
+        };
+    } else if let Some(behind) = behind {
+        if skill >= DEFEND_SKILL {
+            // Sit in front of the ship that is catching up
+            offset = behind
+                .side
+                .clamp(-MAX_DEFEND_OFFSET, MAX_DEFEND_OFFSET)
+                .clamp(right_edge, left_edge);
+        }
+    }
+
+    // Don't drive into the back of anyone still in the way
+    let speed_limit = nearby()
+        .filter(|other| other.along > 0.0 && (other.side - offset).abs() < params.passing_gap * 0.8)
+        .map(|other| other.speed + (other.along - params.follow_distance) * params.follow_gain)
+        .fold(None, |slowest: Option<f32>, speed| match slowest {
+            Some(slowest) => Some(f32::min(slowest, speed)),
+            None => Some(speed),
+        });
+
+    (offset, speed_limit)
+}
+
+/// Worse drivers take every corner a bit slower
+fn calc_speed_scale(skill: f32, params: &AiParams) -> f32 {
+    params.min_speed_scale + (1.0 - params.min_speed_scale) * skill
+}
 
-    steering
+/// Points to the left of a direction
+fn sideways(direction: Vec2) -> Vec2 {
+    (-direction.1, direction.0)
 }
 
-/// Extrapolate the ships position based on it's current velocity.
-fn predict_position(ship: &Ship, time: f32) -> Vec2 {
-    (
-        ship.position.x + ship.velocity.x * time,
-        ship.position.y + ship.velocity.y * time,
-    )
+fn dot(a: Vec2, b: Vec2) -> f32 {
+    a.0 * b.0 + a.1 * b.1
 }
 
 /// Ensure a number is between pi and -pi
@@ -63,5 +234,5 @@ fn wrap_angle(angle: f32) -> f32 {
     let sig = f32::signum(angle);
     let mag = f32::abs(angle) % (2.0 * PI);
 
-    return sig * (mag - PI);
+    sig * (mag - PI)
 }
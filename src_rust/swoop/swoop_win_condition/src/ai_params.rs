@@ -0,0 +1,211 @@
+//! The numbers that control how the AI drives. There is a set for each
+//! difficulty, found by racing the AI against itself with the
+//! `swoop_tuner` tool. The sets are stored as text, eg:
+//! ```text
+//! [Normal]
+//! lookahead_distance=0.6
+//! lookahead_time=0.25
+//! ```
+use std::fmt;
+use std::str::FromStr;
+
+use super::difficulty::Difficulty;
+
+/// The parameter sets built into the game. This is the output of
+/// `swoop_tuner`.
+const TUNED_PARAMS: &str = include_str!("resources/ai_params.txt");
+
+pub const NUM_AI_PARAMS: usize = 10;
+
+/// The name of each parameter, in the same order as `to_values`
+pub const AI_PARAM_NAMES: [&str; NUM_AI_PARAMS] = [
+    "lookahead_distance",
+    "lookahead_time",
+    "reaction_time",
+    "velocity_gain",
+    "steering_gain",
+    "steering_damping",
+    "passing_gap",
+    "follow_distance",
+    "follow_gain",
+    "min_speed_scale",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AiParams {
+    /// How far ahead along the racing line the AI aims, at a
+    /// standstill and for each unit of speed
+    pub lookahead_distance: f32,
+    pub lookahead_time: f32,
+
+    /// How far ahead (in seconds) the AI reads the speed profile, so
+    /// it starts braking slightly before it has to
+    pub reaction_time: f32,
+
+    /// How hard the AI tries to correct the difference between the
+    /// velocity it wants and the one it has
+    pub velocity_gain: f32,
+
+    /// How the steering responds to the ship pointing the wrong way,
+    /// and how much it resists the ship spinning
+    pub steering_gain: f32,
+    pub steering_damping: f32,
+
+    /// How far to the side of another ship the AI aims when going past
+    /// it. Ships closer together than this sideways are in each
+    /// other's way.
+    pub passing_gap: f32,
+
+    /// When stuck behind another ship, the AI closes the gap down to
+    /// this distance, slowing down more the closer it gets
+    pub follow_distance: f32,
+    pub follow_gain: f32,
+
+    /// How fast (compared to the speed profile) an AI with no skill
+    /// takes the corners. An AI with full skill goes at the speed in
+    /// the profile.
+    pub min_speed_scale: f32,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum AiParamsError {
+    /// A line doesn't name one of the parameters
+    UnknownParam(String),
+    /// A parameter's value isn't a number
+    InvalidValue(String),
+    /// A `[section]` isn't the name of a difficulty
+    UnknownDifficulty(String),
+    /// A parameter comes before any `[section]`
+    MissingDifficulty(String),
+}
+
+impl AiParams {
+    /// The parameters used before any tuning was done
+    pub fn defaults() -> Self {
+        Self {
+            lookahead_distance: 0.6,
+            lookahead_time: 0.25,
+            reaction_time: 0.1,
+            velocity_gain: 4.0,
+            steering_gain: 2.0,
+            steering_damping: 0.15,
+            passing_gap: 0.3,
+            follow_distance: 0.3,
+            follow_gain: 3.0,
+            min_speed_scale: 0.8,
+        }
+    }
+
+    /// The built in parameters for a difficulty. Difficulties that
+    /// haven't been tuned use the defaults.
+    pub fn for_difficulty(difficulty: Difficulty) -> Self {
+        let sets = parse_param_sets(TUNED_PARAMS).expect("Invalid built in AI parameters");
+        sets.into_iter()
+            .find(|(set_difficulty, _)| *set_difficulty == difficulty)
+            .map(|(_, params)| params)
+            .unwrap_or_else(Self::defaults)
+    }
+
+    pub fn to_values(&self) -> [f32; NUM_AI_PARAMS] {
+        [
+            self.lookahead_distance,
+            self.lookahead_time,
+            self.reaction_time,
+            self.velocity_gain,
+            self.steering_gain,
+            self.steering_damping,
+            self.passing_gap,
+            self.follow_distance,
+            self.follow_gain,
+            self.min_speed_scale,
+        ]
+    }
+
+    pub fn from_values(values: [f32; NUM_AI_PARAMS]) -> Self {
+        Self {
+            lookahead_distance: values[0],
+            lookahead_time: values[1],
+            reaction_time: values[2],
+            velocity_gain: values[3],
+            steering_gain: values[4],
+            steering_damping: values[5],
+            passing_gap: values[6],
+            follow_distance: values[7],
+            follow_gain: values[8],
+            min_speed_scale: values[9],
+        }
+    }
+
+    /// Changes a parameter from a `name=value` line
+    fn set_from_line(&mut self, line: &str) -> Result<(), AiParamsError> {
+        let mut parts = line.splitn(2, '=');
+        let name = parts.next().unwrap_or("").trim();
+        let value = parts.next().unwrap_or("").trim();
+
+        let index = AI_PARAM_NAMES
+            .iter()
+            .position(|param| *param == name)
+            .ok_or_else(|| AiParamsError::UnknownParam(line.to_string()))?;
+        let mut values = self.to_values();
+        values[index] = value
+            .parse()
+            .map_err(|_| AiParamsError::InvalidValue(line.to_string()))?;
+        *self = Self::from_values(values);
+        Ok(())
+    }
+}
+
+/// Reads the parameter sets for each difficulty. Any parameters that
+/// aren't listed for a difficulty are left at their defaults.
+pub fn parse_param_sets(text: &str) -> Result<Vec<(Difficulty, AiParams)>, AiParamsError> {
+    let mut sets: Vec<(Difficulty, AiParams)> = vec![];
+    for line in text.lines().map(|line| line.trim()) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            let difficulty = name
+                .parse()
+                .map_err(|_| AiParamsError::UnknownDifficulty(name.to_string()))?;
+            sets.push((difficulty, AiParams::defaults()));
+        } else {
+            match sets.last_mut() {
+                Some((_, params)) => params.set_from_line(line)?,
+                None => return Err(AiParamsError::MissingDifficulty(line.to_string())),
+            }
+        }
+    }
+    Ok(sets)
+}
+
+/// Writes parameter sets in the format read by `parse_param_sets`
+pub fn format_param_sets(sets: &[(Difficulty, AiParams)]) -> String {
+    sets.iter()
+        .map(|(difficulty, params)| format!("[{}]\n{}\n", difficulty, params))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// A `name=value` line for each parameter
+impl fmt::Display for AiParams {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let lines: Vec<String> = AI_PARAM_NAMES
+            .iter()
+            .zip(self.to_values().iter())
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect();
+        write!(f, "{}", lines.join("\n"))
+    }
+}
+
+impl FromStr for AiParams {
+    type Err = AiParamsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut params = Self::defaults();
+        for line in s.lines().filter(|line| !line.trim().is_empty()) {
+            params.set_from_line(line)?;
+        }
+        Ok(params)
+    }
+}
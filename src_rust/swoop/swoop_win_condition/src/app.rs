@@ -1,15 +1,37 @@
+use js_sys::Math::random;
 use wasm_bindgen::prelude::wasm_bindgen;
 
-use web_sys::{window, HtmlCanvasElement, KeyboardEvent, MouseEvent};
+use web_sys::{window, HtmlCanvasElement, KeyboardEvent, MouseEvent, TouchEvent};
 
+use super::connection::Connection;
+use super::controls_screen::{ControlsEvent, ControlsScreen};
+use super::editor::{EditorEvent, TrackEditor};
+use super::gamepad::Gamepads;
 use super::gameplay::GamePlay;
+use super::high_score_screen::HighScoreScreen;
 use super::keymap::{KeyMap, KeyState};
 use super::main_menu::MainMenu;
+use super::online::{OnlineRace, OnlineState};
+use super::options::GameOptions;
+use super::records::{NewRecords, Records};
+use super::replay::Replay;
 use super::score_screen::ScoreScreen;
+use super::storage::{LocalStorage, Storage};
+use super::touch::{TouchControls, TouchZone};
+use super::track::Track;
 
 use super::renderer::Renderer;
 use super::transform::Transform2d;
 
+/// Where the key bindings are kept in local storage
+const BINDINGS_STORAGE_KEY: &str = "bindings";
+
+/// How big the editor's handles are drawn, in the same units as a
+/// TouchZone
+const HANDLE_RADIUS: f32 = 0.03;
+const HANDLE_COLOR: [f32; 4] = [0.0, 0.5, 0.7, 0.8];
+const SELECTED_HANDLE_COLOR: [f32; 4] = [0.0, 1.0, 0.7, 1.0];
+
 // Pull in the console.log function so we can debug things more easily
 #[wasm_bindgen]
 extern "C" {
@@ -21,44 +43,126 @@ enum GameState {
     Menu,
     Playing,
     ScoreScreen,
+    Replay,
+    Online,
+    Controls,
+    HighScores,
+    Editor,
+    /// Racing on the track from the editor
+    TestDrive,
 }
 
 pub struct App {
     renderer: Renderer,
 
     key_map: KeyMap,
+    gamepads: Gamepads,
+    touch_controls: TouchControls,
 
     main_menu: MainMenu,
     gameplay: GamePlay,
     score_screen: ScoreScreen,
+    controls_screen: ControlsScreen,
+    high_score_screen: HighScoreScreen,
+    editor: TrackEditor,
 
     prev_time: f64,
 
     game_state: GameState,
+    options: GameOptions,
+
+    /// The most recently completed race
+    last_replay: Option<Replay>,
+
+    storage: LocalStorage,
+    /// The player's personal bests on each track
+    records: Records,
+
+    /// The connection to the relay server and the race on it, when
+    /// racing online
+    online: Option<(Connection, OnlineRace)>,
 }
 
 impl App {
-    pub fn new(canvas: HtmlCanvasElement, _options: String) -> Self {
+    pub fn new(canvas: HtmlCanvasElement, options: String) -> Self {
+        let touch_controls = TouchControls::new(canvas.clone());
         let renderer = Renderer::new(canvas).expect("Failed to create renderer");
 
         let now = window().unwrap().performance().unwrap().now();
         let prev_time = now / 1000.0;
 
+        let options = GameOptions::parse(&options);
+        let mut key_map = KeyMap::new();
+        if let Some(players) = options.players {
+            key_map.num_players = players;
+        }
+        let storage = LocalStorage;
+        if let Some(saved) = storage.get_item(BINDINGS_STORAGE_KEY) {
+            match saved.parse() {
+                Ok(bindings) => key_map.bindings = bindings,
+                Err(err) => log(&format!("Ignoring saved key bindings: {:?}", err)),
+            }
+        }
+        let records = match Records::load(&storage) {
+            Ok(records) => records,
+            Err(err) => {
+                log(&format!("Ignoring saved records: {:?}", err));
+                Records::empty()
+            }
+        };
+        let mut gameplay = GamePlay::new();
+        if let Some(difficulty) = options.difficulty {
+            gameplay.difficulty = difficulty;
+        }
+        if let Some(catch_up) = options.catch_up {
+            gameplay.catch_up = catch_up;
+        }
+        let mut main_menu = MainMenu::new();
+        main_menu.set_num_players(key_map.num_players);
+        main_menu.set_difficulty(gameplay.difficulty, gameplay.catch_up);
+
+        let editor = TrackEditor::new(gameplay.map.clone());
+
         let mut game = Self {
             renderer,
-            main_menu: MainMenu::new(),
-            key_map: KeyMap::new(),
-            gameplay: GamePlay::new(),
+            main_menu,
+            key_map,
+            gamepads: Gamepads::new(),
+            touch_controls,
+            gameplay,
             score_screen: ScoreScreen::new(),
+            controls_screen: ControlsScreen::new(),
+            high_score_screen: HighScoreScreen::new(),
+            editor,
             prev_time,
             game_state: GameState::Menu,
+            options,
+            last_replay: None,
+            storage,
+            records,
+            online: None,
         };
         game.reset();
+
+        if let Some(replay) = game.options.replay.take() {
+            game.start_replay(replay);
+        }
         game
     }
 
     fn reset(&mut self) {
-        self.gameplay.reset();
+        self.gameplay.set_num_players(self.key_map.num_players);
+        if let Some(track) = &self.options.track {
+            self.gameplay.reset_on_track(track);
+        } else {
+            // Use the seed from the options if there is one so that the
+            // same track can be raced again and again.
+            let seed = match self.options.seed {
+                Some(seed) => seed,
+                None => (random() * u32::MAX as f64) as u32,
+            };
+            self.gameplay.reset(seed);
+        }
 
         // TODO: this is a bit dodgy
         self.renderer
@@ -67,22 +171,172 @@ impl App {
     }
 
     pub fn play_game(&mut self, dt: f64) {
+        self.update_race(dt);
+
+        // If the game is finished, show the score screen
+        if self.gameplay.game_complete() {
+            self.last_replay = Some(self.gameplay.recording.clone());
+
+            // The first player is always a person
+            let new_records = self.record_race(0);
+            self.show_score_screen(Some(&new_records));
+        }
+    }
+
+    /// Steps the race on and draws it
+    fn update_race(&mut self, dt: f64) {
         self.gameplay.update(dt, &self.key_map);
-        let ship_entity_refs = self.gameplay.ship_entities.iter().collect();
-        let trail_entity_refs = self.gameplay.trails.iter().collect();
+        let render_ships = self.gameplay.get_render_ships();
+        let ship_entity_refs = render_ships.iter().collect();
+        let trail_entity_refs = self.gameplay.get_render_trails();
 
-        self.renderer.render(
-            &self.gameplay.camera.get_camera_matrix(),
+        self.renderer.render_split(
+            &self.gameplay.get_camera_matrices(),
             ship_entity_refs,
             trail_entity_refs,
             self.gameplay.get_text_entities(),
         );
+        self.render_touch_controls();
+    }
 
-        // If the game is finished, show the score screen
-        if self.gameplay.game_complete() {
-            self.game_state = GameState::ScoreScreen;
-            self.score_screen
-                .populate_scores(&self.gameplay.ship_entities, &self.gameplay.scores)
+    fn show_score_screen(&mut self, new_records: Option<&NewRecords>) {
+        self.game_state = GameState::ScoreScreen;
+        self.score_screen.populate_scores(
+            &self.gameplay.ship_entities,
+            &self.gameplay.scores,
+            &self.gameplay.get_track_name(),
+            new_records,
+        )
+    }
+
+    /// Adds the race that has just finished to the player's records
+    fn record_race(&mut self, player: usize) -> NewRecords {
+        let new_records = self.records.record_race(&self.gameplay, player);
+        if !self.records.save(&mut self.storage) {
+            log("Unable to save records");
+        }
+        new_records
+    }
+
+    /// Play back a recorded race.
+    pub fn start_replay(&mut self, replay: Replay) {
+        self.gameplay.start_playback(replay);
+        self.renderer
+            .map_sprite
+            .set_to_map(&self.renderer.gl, &self.gameplay.map);
+        self.game_state = GameState::Replay;
+    }
+
+    /// Returns the most recently completed race as a string that can
+    /// be passed back in through the `replay` option.
+    pub fn get_replay(&self) -> Option<String> {
+        self.last_replay.as_ref().map(|replay| replay.to_string())
+    }
+
+    pub fn show_replay(&mut self, dt: f64) {
+        // Each of the replay cameras can be switched between ships
+        let num_ships = self.gameplay.ship_entities.len();
+        for (target, keys) in self
+            .gameplay
+            .camera_targets
+            .iter_mut()
+            .zip(self.key_map.players.iter())
+        {
+            if keys.turn_left == KeyState::JustPressed {
+                *target = (*target + num_ships - 1) % num_ships;
+            }
+            if keys.turn_right == KeyState::JustPressed {
+                *target = (*target + 1) % num_ships;
+            }
+        }
+
+        self.gameplay.update(dt, &self.key_map);
+        let render_ships = self.gameplay.get_render_ships();
+        let ship_entity_refs = render_ships.iter().collect();
+        let trail_entity_refs = self.gameplay.get_render_trails();
+
+        self.renderer.render_split(
+            &self.gameplay.get_camera_matrices(),
+            ship_entity_refs,
+            trail_entity_refs,
+            self.gameplay.get_text_entities(),
+        );
+
+        if self.gameplay.playback_finished()
+            || self.gameplay.game_complete()
+            || self.key_map.start_game == KeyState::JustReleased
+        {
+            self.show_score_screen(None);
+        }
+    }
+
+    /// Connects to the relay server and waits for the race in the room
+    /// to start.
+    fn start_online(&mut self, server: &str) {
+        match Connection::new(server) {
+            Ok(connection) => {
+                let online_race = OnlineRace::new(&self.options.room, self.options.room_size);
+                self.online = Some((connection, online_race));
+                self.game_state = GameState::Online;
+            }
+            Err(err) => {
+                log(&format!("Failed to connect to {}: {:?}", server, err));
+                self.game_state = GameState::Playing;
+            }
+        }
+    }
+
+    pub fn play_online(&mut self, dt: f64) {
+        let (connection, online_race) = match &mut self.online {
+            Some(online) => online,
+            None => return,
+        };
+
+        let was_waiting = matches!(online_race.state, OnlineState::Waiting { .. });
+        for message in connection.take_received() {
+            if let Err(err) = online_race.receive(&mut self.gameplay, &message) {
+                log(&format!("Bad message from server: {:?}", err));
+            }
+        }
+        if was_waiting && matches!(online_race.state, OnlineState::Racing) {
+            // The server picked the track
+            self.renderer
+                .map_sprite
+                .set_to_map(&self.renderer.gl, &self.gameplay.map);
+        }
+
+        online_race.update(&mut self.gameplay, dt, &self.key_map);
+        for message in online_race.take_outbox() {
+            connection.send(message.to_string());
+        }
+        connection.flush();
+
+        let render_ships = online_race.get_render_ships(&self.gameplay);
+        let ship_entity_refs = render_ships.iter().collect();
+        let trail_entity_refs = self.gameplay.get_render_trails();
+
+        self.renderer.render_split(
+            &self.gameplay.get_camera_matrices(),
+            ship_entity_refs,
+            trail_entity_refs,
+            online_race.get_text_entities(&self.gameplay),
+        );
+        if self.touch_controls.detected() {
+            self.renderer
+                .render_touch_zones(&self.touch_controls.get_zones());
+        }
+
+        if connection.is_closed() {
+            log("Lost connection to the server");
+            self.online = None;
+            self.game_state = GameState::Menu;
+            self.reset();
+        } else if self.gameplay.game_complete() {
+            self.online = None;
+            self.last_replay = Some(self.gameplay.recording.clone());
+            let player = self.gameplay.online_player.unwrap_or(0);
+            let new_records = self.record_race(player);
+            self.show_score_screen(Some(&new_records));
         }
     }
 
@@ -92,6 +346,15 @@ impl App {
         let dt = time - self.prev_time;
         self.prev_time = time;
 
+        self.gamepads.poll(&mut self.key_map);
+
+        // Outside of a race, tapping the screen is the same as [ENTER]
+        let allow_taps = !matches!(
+            self.game_state,
+            GameState::Playing | GameState::Online | GameState::Editor | GameState::TestDrive
+        );
+        self.touch_controls.poll(&mut self.key_map, allow_taps);
+
         match self.game_state {
             GameState::Menu => {
                 self.show_logo(dt);
@@ -102,6 +365,24 @@ impl App {
             GameState::ScoreScreen => {
                 self.show_scores(dt);
             }
+            GameState::Replay => {
+                self.show_replay(dt);
+            }
+            GameState::Online => {
+                self.play_online(dt);
+            }
+            GameState::Controls => {
+                self.show_controls();
+            }
+            GameState::HighScores => {
+                self.show_high_scores();
+            }
+            GameState::Editor => {
+                self.show_editor();
+            }
+            GameState::TestDrive => {
+                self.test_drive(dt);
+            }
         }
 
         self.key_map.update();
@@ -109,11 +390,12 @@ impl App {
 
     pub fn show_scores(&mut self, dt: f64) {
         self.gameplay.update(dt * 0.1, &self.key_map);
-        let ship_entity_refs = self.gameplay.ship_entities.iter().collect();
-        let trail_entity_refs = self.gameplay.trails.iter().collect();
+        let render_ships = self.gameplay.get_render_ships();
+        let ship_entity_refs = render_ships.iter().collect();
+        let trail_entity_refs = self.gameplay.get_render_trails();
 
-        self.renderer.render(
-            &self.gameplay.camera.get_camera_matrix(),
+        self.renderer.render_split(
+            &self.gameplay.get_camera_matrices(),
             ship_entity_refs,
             trail_entity_refs,
             self.score_screen.get_text_entities(),
@@ -123,14 +405,59 @@ impl App {
         if self.key_map.start_game == KeyState::JustReleased {
             self.game_state = GameState::Menu;
             self.reset();
+        } else if self.key_map.watch_replay == KeyState::JustReleased {
+            if let Some(replay) = self.last_replay.clone() {
+                self.start_replay(replay);
+            }
         }
     }
 
     pub fn show_logo(&mut self, dt: f64) {
         if self.key_map.start_game == KeyState::JustReleased {
-            self.game_state = GameState::Playing;
+            match self.options.server.clone() {
+                Some(server) => self.start_online(&server),
+                None => self.game_state = GameState::Playing,
+            }
             return;
         }
+        if self.key_map.show_controls == KeyState::JustPressed {
+            self.controls_screen.open(&self.key_map.bindings);
+            self.game_state = GameState::Controls;
+            return;
+        }
+        if self.key_map.show_high_scores == KeyState::JustPressed {
+            self.high_score_screen.open(&self.records);
+            self.game_state = GameState::HighScores;
+            return;
+        }
+        if self.key_map.show_editor == KeyState::JustPressed {
+            self.editor.open(self.gameplay.map.clone());
+            self.game_state = GameState::Editor;
+            return;
+        }
+        let selected = self
+            .key_map
+            .select_players
+            .iter()
+            .position(|key| *key == KeyState::JustPressed);
+        if let Some(selected) = selected {
+            self.key_map.num_players = selected + 1;
+            self.main_menu.set_num_players(self.key_map.num_players);
+            self.reset();
+        }
+        let change_difficulty = self.key_map.change_difficulty == KeyState::JustPressed;
+        let toggle_catch_up = self.key_map.toggle_catch_up == KeyState::JustPressed;
+        if change_difficulty || toggle_catch_up {
+            if change_difficulty {
+                self.gameplay.difficulty = self.gameplay.difficulty.next();
+            }
+            if toggle_catch_up {
+                self.gameplay.catch_up = !self.gameplay.catch_up;
+            }
+            self.main_menu
+                .set_difficulty(self.gameplay.difficulty, self.gameplay.catch_up);
+            self.reset();
+        }
         self.main_menu.update(dt);
 
         let world_to_camera = Transform2d::new(0.0, -0.7, 0.0, 3.0);
@@ -142,20 +469,210 @@ impl App {
             &world_to_camera,
             ship_entity_refs,
             trail_entity_refs,
-            vec![&self.main_menu.text],
+            vec![
+                &self.main_menu.text,
+                &self.main_menu.players_text,
+                &self.main_menu.controls_text,
+                &self.main_menu.difficulty_text,
+                &self.main_menu.catch_up_text,
+                &self.main_menu.editor_text,
+            ],
         );
     }
 
-    pub fn mouse_event(&mut self, _event: MouseEvent) {
-        //log(&format!("Mouse Event {:?}", event));
+    pub fn show_controls(&mut self) {
+        let world_to_camera = Transform2d::new(0.0, -0.7, 0.0, 3.0);
+        self.renderer.render(
+            &world_to_camera,
+            vec![],
+            vec![],
+            self.controls_screen.get_text_entities(),
+        );
+    }
+
+    pub fn show_high_scores(&mut self) {
+        if self.key_map.start_game == KeyState::JustReleased
+            || self.key_map.show_high_scores == KeyState::JustPressed
+        {
+            self.game_state = GameState::Menu;
+            return;
+        }
+
+        let world_to_camera = Transform2d::new(0.0, -0.7, 0.0, 3.0);
+        self.renderer.render(
+            &world_to_camera,
+            vec![],
+            vec![],
+            self.high_score_screen.get_text_entities(),
+        );
+    }
+
+    pub fn show_editor(&mut self) {
+        if self.editor.take_changed() {
+            self.renderer
+                .map_sprite
+                .set_to_map(&self.renderer.gl, self.editor.get_map());
+        }
+
+        let world_to_camera = self.editor.get_camera(self.renderer.get_aspect_ratio());
+        self.renderer.render(
+            &world_to_camera,
+            vec![],
+            vec![],
+            vec![&self.editor.help_text],
+        );
+
+        let selected = self.editor.get_selected();
+        let zones: Vec<TouchZone> = self
+            .editor
+            .get_handles()
+            .into_iter()
+            .map(|(handle, position)| TouchZone {
+                center: self.renderer.world_to_screen(&world_to_camera, position),
+                knob: (0.0, 0.0),
+                radius: HANDLE_RADIUS,
+                color: if Some(handle) == selected {
+                    SELECTED_HANDLE_COLOR
+                } else {
+                    HANDLE_COLOR
+                },
+            })
+            .collect();
+        self.renderer.render_touch_zones(&zones);
     }
+
+    /// Races on the track in the editor. Test drives don't count
+    /// towards the player's records.
+    fn start_test_drive(&mut self) {
+        self.gameplay.set_num_players(self.key_map.num_players);
+        self.gameplay.reset_on_track(&self.get_editor_track());
+        self.renderer
+            .map_sprite
+            .set_to_map(&self.renderer.gl, &self.gameplay.map);
+        self.game_state = GameState::TestDrive;
+    }
+
+    pub fn test_drive(&mut self, dt: f64) {
+        self.update_race(dt);
+        if self.gameplay.game_complete() {
+            self.game_state = GameState::Editor;
+        }
+    }
+
+    /// The track in the editor. It keeps the name and laps of the
+    /// track from the options if there is one.
+    fn get_editor_track(&self) -> Track {
+        let map = self.editor.get_map().clone();
+        match &self.options.track {
+            Some(track) => Track {
+                map,
+                ..track.clone()
+            },
+            None => Track::new("Custom", "Anonymous", map),
+        }
+    }
+
+    /// Returns the track in the editor as a string that can be passed
+    /// back in through the `track` option.
+    pub fn get_track(&self) -> String {
+        self.get_editor_track().to_string()
+    }
+
+    /// Races on a track from a string created by `get_track` from now
+    /// on, instead of random tracks
+    pub fn load_track(&mut self, track: Track) {
+        self.options.track = Some(track);
+        self.game_state = GameState::Menu;
+        self.reset();
+    }
+
+    fn editor_key_pressed(&mut self, code: &str) {
+        match self.editor.key_pressed(code) {
+            EditorEvent::None => (),
+            EditorEvent::TestDrive => self.start_test_drive(),
+            EditorEvent::Export => log(&format!("Track: {}", self.get_track())),
+            EditorEvent::Exit => {
+                self.game_state = GameState::Menu;
+                self.reset();
+            }
+        }
+    }
+
+    /// Draws the virtual steering and throttle, but only for players
+    /// who have used the touch screen
+    fn render_touch_controls(&mut self) {
+        if self.touch_controls.detected() {
+            self.renderer
+                .render_touch_zones(&self.touch_controls.get_zones());
+        }
+    }
+
+    pub fn touch_event(&mut self, event: TouchEvent) {
+        if !self.touch_controls.detected() {
+            self.main_menu.set_touch_detected(true);
+            self.score_screen.set_touch_detected(true);
+            self.high_score_screen.set_touch_detected(true);
+        }
+        self.touch_controls.handle_event(&event);
+    }
+
+    pub fn mouse_event(&mut self, event: MouseEvent) {
+        if !matches!(self.game_state, GameState::Editor) {
+            return;
+        }
+        let world_to_camera = self.editor.get_camera(self.renderer.get_aspect_ratio());
+        let pixel = (event.offset_x() as f32, event.offset_y() as f32);
+        let position = self.renderer.canvas_to_world(&world_to_camera, pixel);
+        match event.type_().as_str() {
+            "mousedown" => self.editor.pointer_down(position),
+            "mousemove" => self.editor.pointer_move(position),
+            "mouseup" | "mouseleave" => self.editor.pointer_up(),
+            _ => (),
+        }
+    }
+
     pub fn keydown_event(&mut self, event: KeyboardEvent) {
+        match self.game_state {
+            GameState::Controls => {
+                if !event.repeat() {
+                    self.controls_key_pressed(&event.code());
+                }
+                return;
+            }
+            GameState::Editor => {
+                if !event.repeat() {
+                    self.editor_key_pressed(&event.code());
+                }
+                return;
+            }
+            GameState::TestDrive if event.code() == "Escape" => {
+                self.game_state = GameState::Editor;
+                return;
+            }
+            _ => (),
+        }
         if !event.repeat() {
             self.key_map
                 .set_state_from_str(&event.code(), KeyState::JustPressed);
         }
     }
 
+    fn controls_key_pressed(&mut self, code: &str) {
+        match self
+            .controls_screen
+            .key_pressed(code, &mut self.key_map.bindings)
+        {
+            ControlsEvent::None => (),
+            ControlsEvent::Changed => {
+                let bindings = self.key_map.bindings.to_string();
+                if !self.storage.set_item(BINDINGS_STORAGE_KEY, &bindings) {
+                    log("Unable to save key bindings");
+                }
+            }
+            ControlsEvent::Exit => self.game_state = GameState::Menu,
+        }
+    }
+
     pub fn keyup_event(&mut self, event: KeyboardEvent) {
         self.key_map
             .set_state_from_str(&event.code(), KeyState::JustReleased);
@@ -34,6 +34,12 @@ pub struct Camera {
     pub target_velocity: Vec2,
 }
 
+impl Default for Camera {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Camera {
     pub fn new() -> Self {
         Self {
@@ -0,0 +1,66 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use wasm_bindgen::prelude::Closure;
+use wasm_bindgen::{JsCast, JsValue};
+use web_sys::{MessageEvent, WebSocket};
+
+/// A WebSocket connection to the relay server. Messages that arrive
+/// are stored until the game asks for them, and messages sent before
+/// the connection is open are held until it is.
+pub struct Connection {
+    socket: WebSocket,
+    received: Rc<RefCell<Vec<String>>>,
+    unsent: Vec<String>,
+
+    // Held so that the callback lives as long as the socket
+    _on_message: Closure<dyn FnMut(MessageEvent)>,
+}
+
+impl Connection {
+    pub fn new(url: &str) -> Result<Self, JsValue> {
+        let socket = WebSocket::new(url)?;
+        let received = Rc::new(RefCell::new(vec![]));
+
+        let callback_received = received.clone();
+        let on_message = Closure::wrap(Box::new(move |event: MessageEvent| {
+            if let Some(text) = event.data().as_string() {
+                callback_received.borrow_mut().push(text);
+            }
+        }) as Box<dyn FnMut(_)>);
+        socket.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+
+        Ok(Self {
+            socket,
+            received,
+            unsent: vec![],
+            _on_message: on_message,
+        })
+    }
+
+    /// Sends a message, or holds onto it if the connection has not
+    /// opened yet.
+    pub fn send(&mut self, message: String) {
+        self.unsent.push(message);
+        self.flush();
+    }
+
+    /// Sends any messages that were held while the connection opened
+    pub fn flush(&mut self) {
+        if self.socket.ready_state() == WebSocket::OPEN {
+            for message in self.unsent.drain(..) {
+                self.socket.send_with_str(&message).ok();
+            }
+        }
+    }
+
+    /// Takes all the messages received since the last call
+    pub fn take_received(&mut self) -> Vec<String> {
+        std::mem::take(&mut *self.received.borrow_mut())
+    }
+
+    /// True if the connection has failed or been closed
+    pub fn is_closed(&self) -> bool {
+        self.socket.ready_state() == WebSocket::CLOSED
+    }
+}
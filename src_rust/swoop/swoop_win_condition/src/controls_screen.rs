@@ -0,0 +1,175 @@
+use super::keymap::{BindingError, Bindings, ACTION_NAMES, MAX_PLAYERS};
+use super::text_box::TextBox;
+
+const TEXT_COLOR: [f32; 3] = [0.0, 0.7, 1.0];
+const HIGHLIGHT_COLOR: [f32; 3] = [0.0, 1.0, 0.7];
+const LABEL_COLOR: [f32; 3] = [0.5, 0.5, 0.5];
+const ERROR_COLOR: [f32; 3] = [1.0, 0.3, 0.0];
+
+/// What happened as a result of a key press on the controls screen
+pub enum ControlsEvent {
+    None,
+    /// The bindings were changed and should be saved
+    Changed,
+    /// The player wants to go back to the menu
+    Exit,
+}
+
+/// A screen listing each player's keys. A key is changed by selecting
+/// it with the arrow keys, pressing [ENTER] and then pressing the new
+/// key.
+pub struct ControlsScreen {
+    title: TextBox,
+    /// One line for each player's action. Each line is its own text box
+    /// as a text box can only hold 128 characters.
+    rows: Vec<TextBox>,
+    status: TextBox,
+    instructions: TextBox,
+
+    /// Which row is highlighted
+    selected: usize,
+    /// Whether the next key pressed will be bound to the selected row
+    waiting_for_key: bool,
+}
+
+impl ControlsScreen {
+    pub fn new() -> Self {
+        let mut title = TextBox::new((8, 1), 0.08, (0.0, 0.75));
+        title.append_string("Controls", &TEXT_COLOR);
+
+        let num_rows = MAX_PLAYERS * ACTION_NAMES.len();
+        let rows = (0..num_rows)
+            .map(|row| TextBox::new((26, 1), 0.03, (0.0, 0.5 - row as f32 * 0.06)))
+            .collect();
+
+        let mut instructions = TextBox::new((30, 2), 0.03, (0.0, -0.7));
+        instructions.append_string("[UP] [DOWN]", &HIGHLIGHT_COLOR);
+        instructions.append_string(" select ", &TEXT_COLOR);
+        instructions.append_string("[ENTER]", &HIGHLIGHT_COLOR);
+        instructions.append_string(" set", &TEXT_COLOR);
+        instructions.append_string("[BACKSPACE]", &HIGHLIGHT_COLOR);
+        instructions.append_string(" reset ", &TEXT_COLOR);
+        instructions.append_string("[ESC]", &HIGHLIGHT_COLOR);
+        instructions.append_string(" back  ", &TEXT_COLOR);
+
+        Self {
+            title,
+            rows,
+            status: TextBox::new((36, 1), 0.03, (0.0, -0.52)),
+            instructions,
+            selected: 0,
+            waiting_for_key: false,
+        }
+    }
+
+    /// Called when the screen is shown
+    pub fn open(&mut self, bindings: &Bindings) {
+        self.selected = 0;
+        self.waiting_for_key = false;
+        self.status.clear();
+        self.update_rows(bindings);
+    }
+
+    /// Handles a key press. The code is from `KeyboardEvent.code()`.
+    /// Keys are handled directly rather than through the key map as
+    /// any key (even one that steers) can be chosen.
+    pub fn key_pressed(&mut self, code: &str, bindings: &mut Bindings) -> ControlsEvent {
+        let player = self.selected / ACTION_NAMES.len();
+        let action = self.selected % ACTION_NAMES.len();
+
+        let mut event = ControlsEvent::None;
+        if self.waiting_for_key {
+            self.waiting_for_key = false;
+            self.status.clear();
+            match code {
+                "Escape" => (),
+                _ => match bindings.bind(player, action, code) {
+                    Ok(()) => {
+                        self.status.append_string(
+                            &format!("P{} {} is now ", player + 1, ACTION_NAMES[action]),
+                            &TEXT_COLOR,
+                        );
+                        self.status.append_string(&key_name(code), &HIGHLIGHT_COLOR);
+                        event = ControlsEvent::Changed;
+                    }
+                    Err(BindingError::AlreadyBound { player, action }) => {
+                        self.status.append_string(&key_name(code), &ERROR_COLOR);
+                        self.status.append_string(
+                            &format!(" is used by P{} {}", player + 1, ACTION_NAMES[action]),
+                            &ERROR_COLOR,
+                        );
+                    }
+                    Err(_) => {
+                        self.status.append_string(&key_name(code), &ERROR_COLOR);
+                        self.status
+                            .append_string(" is used by the menus", &ERROR_COLOR);
+                    }
+                },
+            }
+        } else {
+            match code {
+                "ArrowUp" => {
+                    self.selected = (self.selected + self.rows.len() - 1) % self.rows.len();
+                }
+                "ArrowDown" => {
+                    self.selected = (self.selected + 1) % self.rows.len();
+                }
+                "Enter" => {
+                    self.waiting_for_key = true;
+                    self.status.clear();
+                }
+                "Backspace" => {
+                    *bindings = Bindings::defaults();
+                    self.status.clear();
+                    self.status
+                        .append_string("Reset to the default keys", &TEXT_COLOR);
+                    event = ControlsEvent::Changed;
+                }
+                "Escape" => event = ControlsEvent::Exit,
+                _ => (),
+            }
+        }
+
+        self.update_rows(bindings);
+        event
+    }
+
+    pub fn get_text_entities(&self) -> Vec<&TextBox> {
+        let mut text_boxes = vec![&self.title, &self.status, &self.instructions];
+        text_boxes.extend(self.rows.iter());
+        text_boxes
+    }
+
+    fn update_rows(&mut self, bindings: &Bindings) {
+        for (row_id, row) in self.rows.iter_mut().enumerate() {
+            let player = row_id / ACTION_NAMES.len();
+            let action = row_id % ACTION_NAMES.len();
+            let selected = row_id == self.selected;
+            let color = if selected {
+                HIGHLIGHT_COLOR
+            } else {
+                TEXT_COLOR
+            };
+
+            let key = if selected && self.waiting_for_key {
+                "press a key".to_string()
+            } else {
+                format!("[{}]", key_name(&bindings.keys[player][action]))
+            };
+
+            row.clear();
+            row.append_string(&format!("P{} ", player + 1), &LABEL_COLOR);
+            row.append_string(&format!("{:<10}{:>13}", ACTION_NAMES[action], key), &color);
+        }
+    }
+}
+
+/// A shorter name for a key code that fits in a text box, eg `KeyW`
+/// becomes `W`
+fn key_name(code: &str) -> String {
+    let name = code
+        .strip_prefix("Key")
+        .or_else(|| code.strip_prefix("Digit"))
+        .unwrap_or(code);
+    name.chars().take(11).collect()
+}
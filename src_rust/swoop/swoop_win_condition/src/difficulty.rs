@@ -0,0 +1,105 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// How far (in laps) the AI has to be from the leading player for
+/// catch-up to have its full effect
+const CATCH_UP_RANGE: f64 = 0.25;
+
+/// The most that catch-up changes an AI's skill by
+const MAX_CATCH_UP: f32 = 0.3;
+
+/// How good the AI ships are
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+    Insane,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum DifficultyError {
+    /// The string is not the name of a difficulty
+    Unknown(String),
+}
+
+impl Difficulty {
+    pub const ALL: [Difficulty; 4] = [
+        Difficulty::Easy,
+        Difficulty::Normal,
+        Difficulty::Hard,
+        Difficulty::Insane,
+    ];
+
+    /// The next difficulty up, going back to Easy after Insane
+    pub fn next(self) -> Self {
+        let index = Self::ALL.iter().position(|d| *d == self).unwrap_or(0);
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    /// The skill (see `calc_ai_control`) of the slowest and the fastest
+    /// AI ships. The others are spread evenly between them.
+    pub fn get_skill_range(self) -> (f32, f32) {
+        match self {
+            Difficulty::Easy => (0.0, 0.3),
+            Difficulty::Normal => (0.2, 0.7),
+            Difficulty::Hard => (0.5, 0.9),
+            Difficulty::Insane => (0.85, 1.0),
+        }
+    }
+
+    /// The skill of the `ai_index`th of `num_ai` AI ships, counting
+    /// from the slowest
+    pub fn get_ai_skill(self, ai_index: usize, num_ai: usize) -> f32 {
+        let (min_skill, max_skill) = self.get_skill_range();
+        let fraction = ai_index as f32 / usize::max(num_ai.saturating_sub(1), 1) as f32;
+        min_skill + fraction * (max_skill - min_skill)
+    }
+
+    /// A single letter used to store the difficulty in a replay
+    pub fn to_letter(self) -> char {
+        match self {
+            Difficulty::Easy => 'E',
+            Difficulty::Normal => 'N',
+            Difficulty::Hard => 'H',
+            Difficulty::Insane => 'I',
+        }
+    }
+
+    pub fn from_letter(letter: char) -> Option<Self> {
+        Self::ALL.iter().find(|d| d.to_letter() == letter).cloned()
+    }
+}
+
+/// Changes an AI's skill when catch-up is turned on. AI ships ahead of
+/// the leading player slow down and ones behind speed up. `gap` is how
+/// many laps the AI is ahead of the leading player.
+pub fn calc_catch_up_skill(skill: f32, gap: f64) -> f32 {
+    let amount = (gap / CATCH_UP_RANGE).clamp(-1.0, 1.0) as f32;
+    (skill - amount * MAX_CATCH_UP).clamp(0.0, 1.0)
+}
+
+impl fmt::Display for Difficulty {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Difficulty::Easy => "Easy",
+            Difficulty::Normal => "Normal",
+            Difficulty::Hard => "Hard",
+            Difficulty::Insane => "Insane",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Difficulties are read from their names in any case, eg `hard`
+impl FromStr for Difficulty {
+    type Err = DifficultyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .iter()
+            .find(|d| d.to_string().eq_ignore_ascii_case(s))
+            .cloned()
+            .ok_or_else(|| DifficultyError::Unknown(s.to_string()))
+    }
+}
@@ -0,0 +1,379 @@
+//! Changes the shape of a track by dragging handles around with the
+//! mouse. Spline tracks have a handle on each control point, and polar
+//! tracks have a handle for each of the waves that make up the shape.
+//! Both have handles for the start line and the width of the track.
+use super::map::{Map, MAX_TRACK_WIDTH, MIN_TRACK_WIDTH};
+use super::spline::{Spline, MAX_SPLINE_POINTS};
+use super::text_box::TextBox;
+use super::transform::{length, Transform2d, Vec2};
+
+/// How close (in world units) the pointer has to be to a handle to
+/// pick it up
+const GRAB_DISTANCE: f32 = 0.8;
+
+/// How far the handle of each wave of a polar track is from the base
+/// radius for each unit of the wave's amplitude
+const AMPLITUDE_SCALE: f32 = 2.0;
+
+/// How many control points a polar track gets when it is turned into a
+/// spline
+const CONVERTED_SPLINE_POINTS: usize = 16;
+
+/// How much room to leave around the track when fitting it on screen
+const VIEW_MARGIN: f32 = 2.0;
+
+const TEXT_COLOR: [f32; 3] = [0.0, 0.7, 1.0];
+const HIGHLIGHT_COLOR: [f32; 3] = [0.0, 1.0, 0.7];
+
+/// Something on the track that can be dragged around
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Handle {
+    /// One of the control points of a spline track
+    ControlPoint(usize),
+    /// One of the sin/cos pairs of a polar track. The handle sits on
+    /// a peak of the wave, further out the bigger the wave is.
+    Wave(usize),
+    /// The middle of the start line
+    StartLine,
+    /// The edge of the track next to the start line
+    Width,
+}
+
+/// What the app needs to do after a key is pressed in the editor
+#[derive(Debug, PartialEq)]
+pub enum EditorEvent {
+    None,
+    /// Race on the track as it is now
+    TestDrive,
+    /// Share the track as a string
+    Export,
+    /// Go back to the main menu
+    Exit,
+}
+
+pub struct TrackEditor {
+    map: Map,
+
+    /// The handle that was last clicked on
+    selected: Option<Handle>,
+    dragging: bool,
+
+    /// Set whenever the map changes, so the app knows to send it to
+    /// the map sprite again
+    changed: bool,
+
+    pub help_text: TextBox,
+}
+
+impl TrackEditor {
+    pub fn new(map: Map) -> Self {
+        let mut help_text = TextBox::new((48, 2), 0.035, (0.0, -0.85));
+        for line in [
+            [
+                ("[DRAG]", " move  "),
+                ("[P]", " spline  "),
+                ("[A]", " add  "),
+                ("[X]", " delete"),
+            ],
+            [
+                ("[ENTER]", " test drive  "),
+                ("[S]", " share  "),
+                ("[ESC]", " menu"),
+                ("", ""),
+            ],
+        ]
+        .iter()
+        {
+            let mut line_length = 0;
+            for (key, action) in line.iter() {
+                help_text.append_string(key, &HIGHLIGHT_COLOR);
+                help_text.append_string(action, &TEXT_COLOR);
+                line_length += key.len() + action.len();
+            }
+            help_text.append_string(&" ".repeat(48 - line_length), &TEXT_COLOR);
+        }
+
+        Self {
+            map,
+            selected: None,
+            dragging: false,
+            changed: true,
+            help_text,
+        }
+    }
+
+    /// Starts editing a different map
+    pub fn open(&mut self, map: Map) {
+        self.map = map;
+        self.selected = None;
+        self.dragging = false;
+        self.changed = true;
+    }
+
+    pub fn get_map(&self) -> &Map {
+        &self.map
+    }
+
+    pub fn get_selected(&self) -> Option<Handle> {
+        self.selected
+    }
+
+    /// Returns true (once) if the map has changed since the last call
+    pub fn take_changed(&mut self) -> bool {
+        std::mem::replace(&mut self.changed, false)
+    }
+
+    /// A camera that fits the whole track on the screen. The aspect
+    /// ratio is the screen's height over its width.
+    pub fn get_camera(&self, aspect_ratio: f32) -> Transform2d {
+        let extent = (0..64)
+            .map(|i| length(&self.map.get_track_point(i as f32 / 64.0).0))
+            .chain(
+                self.get_handles()
+                    .iter()
+                    .map(|(_, position)| length(position)),
+            )
+            .fold(0.0, f32::max)
+            + self.get_max_width()
+            + VIEW_MARGIN;
+        Transform2d::new(0.0, 0.0, 0.0, extent / aspect_ratio.min(1.0))
+    }
+
+    /// Where each of the handles is in the world
+    pub fn get_handles(&self) -> Vec<(Handle, Vec2)> {
+        let mut handles = vec![];
+        match &self.map.spline {
+            Some(spline) => {
+                for (i, point) in spline.get_points().iter().enumerate() {
+                    handles.push((Handle::ControlPoint(i), *point));
+                }
+            }
+            None => {
+                for i in 0..self.map.sin_consts.len() {
+                    handles.push((Handle::Wave(i), self.get_wave_handle(i)));
+                }
+            }
+        }
+
+        let (start, across) = self.map.get_track_point(0.0);
+        let start_width = self.map.calc_track_width(start);
+        handles.push((Handle::StartLine, start));
+        handles.push((
+            Handle::Width,
+            (
+                start.0 + across.0 * start_width,
+                start.1 + across.1 * start_width,
+            ),
+        ));
+        handles
+    }
+
+    /// Picks up the closest handle to the pointer, if there's one close
+    /// enough
+    pub fn pointer_down(&mut self, position: Vec2) {
+        self.selected = self
+            .get_handles()
+            .into_iter()
+            .map(|(handle, handle_position)| (handle, distance(position, handle_position)))
+            .filter(|(_, handle_distance)| *handle_distance < GRAB_DISTANCE)
+            .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+            .map(|(handle, _)| handle);
+        self.dragging = self.selected.is_some();
+    }
+
+    /// Moves the handle that is being dragged
+    pub fn pointer_move(&mut self, position: Vec2) {
+        if !self.dragging {
+            return;
+        }
+        match self.selected {
+            Some(Handle::ControlPoint(index)) => {
+                if let Some(spline) = &self.map.spline {
+                    let mut points = spline.get_points().to_vec();
+                    let widths = spline.get_widths().to_vec();
+                    points[index] = position;
+                    self.set_spline(points, widths);
+                }
+            }
+            Some(Handle::Wave(index)) => self.set_wave(index, position),
+            Some(Handle::StartLine) => self.move_start_line(position),
+            Some(Handle::Width) => {
+                // The handle sits on the edge at the start line, but it
+                // sets the base width that the rest of the track varies
+                // around
+                let (start, _) = self.map.get_track_point(0.0);
+                let variation = self.map.calc_track_width(start) - self.map.track_width;
+                self.map.track_width =
+                    (distance(position, start) - variation).clamp(MIN_TRACK_WIDTH, MAX_TRACK_WIDTH);
+                self.changed = true;
+            }
+            None => (),
+        }
+    }
+
+    pub fn pointer_up(&mut self) {
+        self.dragging = false;
+    }
+
+    /// Handles a key press (a `KeyboardEvent.code()`)
+    pub fn key_pressed(&mut self, code: &str) -> EditorEvent {
+        match code {
+            "Enter" => return EditorEvent::TestDrive,
+            "KeyS" => return EditorEvent::Export,
+            "Escape" => return EditorEvent::Exit,
+            "KeyP" => self.convert_to_spline(),
+            "KeyA" => self.add_point(),
+            "KeyX" | "Delete" | "Backspace" => self.delete_point(),
+            _ => (),
+        }
+        EditorEvent::None
+    }
+
+    /// Replaces a polar track with a spline that follows the same
+    /// course, so it can be bent into shapes a polar track can't make
+    fn convert_to_spline(&mut self) {
+        if self.map.spline.is_some() {
+            return;
+        }
+        let points: Vec<Vec2> = (0..CONVERTED_SPLINE_POINTS)
+            .map(|i| {
+                let fraction = i as f32 / CONVERTED_SPLINE_POINTS as f32;
+                self.map.get_track_point(fraction).0
+            })
+            .collect();
+        let widths = points
+            .iter()
+            .map(|point| {
+                let angle = f32::atan2(point.1, point.0);
+                self.map.track_width_at_angle(angle) - self.map.track_width
+            })
+            .collect();
+        self.set_spline(points, widths);
+        self.selected = None;
+    }
+
+    /// Adds a control point half way between the selected one and the
+    /// next one
+    fn add_point(&mut self) {
+        let (spline, index) = match (&self.map.spline, self.selected) {
+            (Some(spline), Some(Handle::ControlPoint(index))) => (spline, index),
+            _ => return,
+        };
+        let mut points = spline.get_points().to_vec();
+        let mut widths = spline.get_widths().to_vec();
+        if points.len() >= MAX_SPLINE_POINTS {
+            return;
+        }
+        let next = (index + 1) % points.len();
+        let middle = (
+            (points[index].0 + points[next].0) * 0.5,
+            (points[index].1 + points[next].1) * 0.5,
+        );
+        let middle_width = (widths[index] + widths[next]) * 0.5;
+        points.insert(index + 1, middle);
+        widths.insert(index + 1, middle_width);
+        self.set_spline(points, widths);
+        self.selected = Some(Handle::ControlPoint(index + 1));
+    }
+
+    fn delete_point(&mut self) {
+        let (spline, index) = match (&self.map.spline, self.selected) {
+            (Some(spline), Some(Handle::ControlPoint(index))) => (spline, index),
+            _ => return,
+        };
+        let mut points = spline.get_points().to_vec();
+        let mut widths = spline.get_widths().to_vec();
+        points.remove(index);
+        widths.remove(index);
+        if self.set_spline(points, widths) {
+            self.selected = None;
+        }
+    }
+
+    /// Returns false (and leaves the map alone) if the points don't
+    /// make a valid spline
+    fn set_spline(&mut self, points: Vec<Vec2>, widths: Vec<f32>) -> bool {
+        match Spline::with_widths(points, widths) {
+            Ok(spline) => {
+                self.map.spline = Some(spline);
+                self.changed = true;
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// How far the widest part of the track reaches from its middle
+    fn get_max_width(&self) -> f32 {
+        (0..64)
+            .map(|i| {
+                let (point, _) = self.map.get_track_point(i as f32 / 64.0);
+                self.map.calc_track_width(point)
+            })
+            .fold(self.map.track_width, f32::max)
+    }
+
+    /// Where the handle for one of the waves of a polar track is
+    fn get_wave_handle(&self, index: usize) -> Vec2 {
+        let (amplitude, angle) = self.get_wave(index);
+        let radius = self.map.track_base_radius + amplitude * AMPLITUDE_SCALE;
+        (f32::cos(angle) * radius, f32::sin(angle) * radius)
+    }
+
+    /// The size of a wave and the angle of one of its peaks. Each wave
+    /// is `sin(angle * omega) * sin_const + cos(angle * omega) *
+    /// cos_const`, which peaks at the angle of the vector (cos_const,
+    /// sin_const) divided by omega.
+    fn get_wave(&self, index: usize) -> (f32, f32) {
+        let omega = (index + 1) as f32;
+        let sin_const = self.map.sin_consts[index];
+        let cos_const = self.map.cos_consts[index];
+        (
+            f32::hypot(sin_const, cos_const),
+            f32::atan2(sin_const, cos_const) / omega,
+        )
+    }
+
+    /// Moves the peak of a wave to the pointer
+    fn set_wave(&mut self, index: usize, position: Vec2) {
+        let omega = (index + 1) as f32;
+        let amplitude =
+            f32::max(length(&position) - self.map.track_base_radius, 0.0) / AMPLITUDE_SCALE;
+        let phase = f32::atan2(position.1, position.0) * omega;
+        self.map.sin_consts[index] = f32::sin(phase) * amplitude;
+        self.map.cos_consts[index] = f32::cos(phase) * amplitude;
+        self.changed = true;
+    }
+
+    /// Polar tracks can start at any angle. Spline tracks start at the
+    /// first control point, so the closest control point becomes the
+    /// first one.
+    fn move_start_line(&mut self, position: Vec2) {
+        match &self.map.spline {
+            Some(spline) => {
+                let mut points = spline.get_points().to_vec();
+                let mut widths = spline.get_widths().to_vec();
+                let closest = (0..points.len())
+                    .min_by(|a, b| {
+                        let a = distance(points[*a], position);
+                        let b = distance(points[*b], position);
+                        a.partial_cmp(&b).unwrap()
+                    })
+                    .unwrap_or(0);
+                if closest != 0 {
+                    points.rotate_left(closest);
+                    widths.rotate_left(closest);
+                    self.set_spline(points, widths);
+                }
+            }
+            None => {
+                self.map.start_angle = f32::atan2(position.1, position.0);
+                self.changed = true;
+            }
+        }
+    }
+}
+
+fn distance(a: Vec2, b: Vec2) -> f32 {
+    length(&(b.0 - a.0, b.1 - a.1))
+}
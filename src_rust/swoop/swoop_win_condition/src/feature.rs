@@ -0,0 +1,137 @@
+//! Things on the surface of a track that change how the ships drive
+//! over them. Boost pads push ships along the track, sticky zones slow
+//! them down, and jump gates throw them into the air so they skip over
+//! whatever comes next.
+//!
+//! Features are placed on a track by how far around the lap they are
+//! and how far across the track, so they stay in the same place on the
+//! course when the shape of the track changes.
+use std::fmt;
+use std::str::FromStr;
+
+use super::transform::Vec2;
+
+/// The most features a track can have. This must match MAX_FEATURES
+/// in map.frag.
+pub const MAX_FEATURES: usize = 16;
+
+/// How much a boost pad accelerates a ship along the track. This is
+/// more than the engine can manage, so a ship goes faster than its
+/// top speed for a while after going over one.
+pub const BOOST_ACCELERATION: f32 = 15.0;
+
+/// Extra damping in a sticky zone. It is added to the ship's own
+/// LINEAR_DAMPING.
+pub const STICKY_DAMPING: f32 = 4.0;
+
+/// How long a ship is in the air after going over a jump gate
+pub const JUMP_TIME: f32 = 0.5;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FeatureKind {
+    Boost,
+    Sticky,
+    Jump,
+}
+
+/// A rectangle on the track, lined up with the direction of the track
+/// at its middle
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrackFeature {
+    pub kind: FeatureKind,
+    /// How far around the lap (from 0 to 1) the middle of the feature is
+    pub fraction: f32,
+    /// How far the middle of the feature is from the middle of the
+    /// track, in the same direction as `Map::get_track_point` gives
+    pub offset: f32,
+    /// How long the feature is along the track
+    pub length: f32,
+    /// How far the feature reaches to either side of its middle
+    pub width: f32,
+}
+
+/// How the track affects a ship driving over it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Surface {
+    /// Acceleration from boost pads
+    pub acceleration: Vec2,
+    /// Damping on top of the ship's own LINEAR_DAMPING
+    pub damping: f32,
+    /// Set when the ship is on a jump gate
+    pub launch: bool,
+}
+
+impl Surface {
+    /// Adds the effect of a feature that is `forwards` (along the
+    /// track) from its middle
+    pub fn add_feature(&mut self, kind: FeatureKind, forwards: Vec2) {
+        match kind {
+            FeatureKind::Boost => {
+                self.acceleration.0 += forwards.0 * BOOST_ACCELERATION;
+                self.acceleration.1 += forwards.1 * BOOST_ACCELERATION;
+            }
+            FeatureKind::Sticky => self.damping += STICKY_DAMPING,
+            FeatureKind::Jump => self.launch = true,
+        }
+    }
+}
+
+impl TrackFeature {
+    /// Whether a position is on the feature, given where the middle of
+    /// the feature is and which way the track goes there
+    pub fn contains(&self, center: Vec2, forwards: Vec2, position: Vec2) -> bool {
+        let delta = (position.0 - center.0, position.1 - center.1);
+        let along = delta.0 * forwards.0 + delta.1 * forwards.1;
+        let side = delta.0 * forwards.1 - delta.1 * forwards.0;
+        along.abs() <= self.length * 0.5 && side.abs() <= self.width
+    }
+}
+
+/// Written as `kind,fraction,offset,length,width`, eg
+/// `boost,0.25,-0.3,1.5,0.3`
+impl fmt::Display for TrackFeature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kind = match self.kind {
+            FeatureKind::Boost => "boost",
+            FeatureKind::Sticky => "sticky",
+            FeatureKind::Jump => "jump",
+        };
+        write!(
+            f,
+            "{},{},{},{},{}",
+            kind, self.fraction, self.offset, self.length, self.width
+        )
+    }
+}
+
+impl FromStr for TrackFeature {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split(',');
+        let kind = match parts.next() {
+            Some("boost") => FeatureKind::Boost,
+            Some("sticky") => FeatureKind::Sticky,
+            Some("jump") => FeatureKind::Jump,
+            _ => return Err(()),
+        };
+        let values = parts
+            .map(|value| value.parse::<f32>().ok().filter(|value| value.is_finite()))
+            .collect::<Option<Vec<f32>>>()
+            .ok_or(())?;
+        match values.as_slice() {
+            [fraction, offset, length, width]
+                if (0.0..1.0).contains(fraction) && *length > 0.0 && *width > 0.0 =>
+            {
+                Ok(Self {
+                    kind,
+                    fraction: *fraction,
+                    offset: *offset,
+                    length: *length,
+                    width: *width,
+                })
+            }
+            _ => Err(()),
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use wasm_bindgen::JsCast;
+use web_sys::{window, Gamepad, GamepadButton};
+
+use super::keymap::{KeyMap, KeyState, MAX_PLAYERS};
+
+/// How far a stick has to move before it does anything. Sticks rarely
+/// rest exactly at zero.
+const STICK_DEADZONE: f32 = 0.15;
+/// Triggers are much more reliable at rest than sticks
+const TRIGGER_DEADZONE: f32 = 0.05;
+
+// Button and axis numbers from the "standard" gamepad mapping
+// https://w3c.github.io/gamepad/#remapping
+const BUTTON_A: u32 = 0;
+const BUTTON_LEFT_TRIGGER: u32 = 6;
+const BUTTON_RIGHT_TRIGGER: u32 = 7;
+const BUTTON_START: u32 = 9;
+const AXIS_LEFT_STICK_X: u32 = 0;
+const AXIS_LEFT_STICK_Y: u32 = 1;
+
+/// Reads the browser's gamepads each frame and applies them to the
+/// key map. The first gamepad drives the first player and so on.
+/// Throttle is the right trigger (or left trigger to reverse), or
+/// pushing the left stick forwards. Steering is the left stick.
+pub struct Gamepads {
+    /// Whether the start button on each gamepad was held last frame
+    start_held: Vec<bool>,
+}
+
+impl Gamepads {
+    pub fn new() -> Self {
+        Self {
+            start_held: vec![false; MAX_PLAYERS],
+        }
+    }
+
+    pub fn poll(&mut self, key_map: &mut KeyMap) {
+        for keys in key_map.players.iter_mut() {
+            keys.throttle = 0.0;
+            keys.steering = 0.0;
+        }
+
+        let gamepads = match window().unwrap().navigator().get_gamepads() {
+            Ok(gamepads) => gamepads,
+            Err(_) => return, // Gamepads not supported
+        };
+
+        for index in 0..MAX_PLAYERS {
+            let gamepad: Gamepad = match gamepads.get(index as u32).dyn_into() {
+                Ok(gamepad) => gamepad,
+                Err(_) => continue, // Null or undefined - no gamepad here
+            };
+            if !gamepad.connected() {
+                continue;
+            }
+
+            let player = if key_map.num_players == 1 { 0 } else { index };
+            let keys = &mut key_map.players[player];
+
+            let stick_x = get_axis(&gamepad, AXIS_LEFT_STICK_X);
+            let stick_y = get_axis(&gamepad, AXIS_LEFT_STICK_Y);
+            let stick_length = f32::hypot(stick_x, stick_y);
+            let stick_scale =
+                apply_deadzone(stick_length, STICK_DEADZONE) / stick_length.max(0.0001);
+
+            let triggers =
+                apply_deadzone(get_button(&gamepad, BUTTON_RIGHT_TRIGGER), TRIGGER_DEADZONE)
+                    - apply_deadzone(get_button(&gamepad, BUTTON_LEFT_TRIGGER), TRIGGER_DEADZONE);
+
+            // Up is negative on the stick. The stick only works forwards
+            // so that pulling down a bit while steering doesn't brake.
+            let stick_throttle = f32::max(-stick_y * stick_scale, 0.0);
+
+            keys.throttle = (keys.throttle + triggers + stick_throttle).clamp(-1.0, 1.0);
+            keys.steering = (keys.steering - stick_x * stick_scale).clamp(-1.0, 1.0);
+
+            let start =
+                get_button(&gamepad, BUTTON_START) > 0.5 || get_button(&gamepad, BUTTON_A) > 0.5;
+            if start && !self.start_held[index] {
+                key_map.start_game = KeyState::JustPressed;
+            } else if !start && self.start_held[index] {
+                key_map.start_game = KeyState::JustReleased;
+            }
+            self.start_held[index] = start;
+        }
+    }
+}
+
+/// Values smaller than the deadzone become zero and the rest of the
+/// range is stretched so that it still goes all the way to 1.0
+fn apply_deadzone(value: f32, deadzone: f32) -> f32 {
+    if value.abs() < deadzone {
+        0.0
+    } else {
+        value.signum() * (value.abs() - deadzone) / (1.0 - deadzone)
+    }
+}
+
+fn get_axis(gamepad: &Gamepad, axis: u32) -> f32 {
+    gamepad.axes().get(axis).as_f64().unwrap_or(0.0) as f32
+}
+
+fn get_button(gamepad: &Gamepad, button: u32) -> f32 {
+    match gamepad.buttons().get(button).dyn_into::<GamepadButton>() {
+        Ok(button) => button.value() as f32,
+        Err(_) => 0.0,
+    }
+}
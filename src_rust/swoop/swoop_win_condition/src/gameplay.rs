@@ -1,13 +1,21 @@
+use super::ai_params::AiParams;
 use super::camera::Camera;
-use super::keymap::KeyMap;
-use super::map::Map;
+use super::difficulty::{calc_catch_up_skill, Difficulty};
+use super::ghost::Ghost;
+use super::keymap::{KeyMap, ShipKeys, MAX_PLAYERS};
+use super::map::{Map, NUM_WIDTH_WAVES};
+use super::racing_line::RacingLine;
+use super::replay::Replay;
+use super::rng::to_track_code;
 use super::score::Score;
 use super::ship::Ship;
-use super::text_sprite::TextBox;
+use super::text_box::TextBox;
+use super::track::Track;
 use super::trail::Trail;
+use super::transform::Transform2d;
 
 use super::ai::calc_ai_control;
-use super::physics::calc_ship_physics;
+use super::physics::{calc_ship_physics, Broadphase, CollisionEvent};
 
 // Trail visuals
 const MAIN_TRAIL_WIDTH: f32 = 0.10;
@@ -18,11 +26,21 @@ const WINGTIP_TRAIL_BRIGHTNESS: f32 = 1.0;
 // Ship startline settings
 const SHIP_SPACING: f32 = 0.12;
 const NUM_START_COLUMNS: usize = 4;
+/// How much room to leave between the start grid and the edges of the
+/// track
+const START_GRID_MARGIN: f32 = 0.1;
 
-const NUM_LAPS_TO_WIN: usize = 6;
+pub const NUM_LAPS_TO_WIN: usize = 6;
 
 const COUNTDOWN_TIME: f64 = 4.0;
 
+/// How long each step of the simulation lasts (120Hz)
+pub const SIM_TIME_STEP: f64 = 1.0 / 120.0;
+/// If a frame takes a long time, the simulation will only attempt to
+/// catch up by this many steps. This prevents a long stall (eg the tab
+/// being hidden) from making the ships jump.
+const MAX_STEPS_PER_FRAME: u32 = 12;
+
 const YELLOW_SHIP: (f32, f32, f32, f32) = (1.0, 0.7, 0.0, 1.0);
 const PINK_SHIP: (f32, f32, f32, f32) = (1.0, 0.0, 0.7, 1.0);
 const PURPLE_SHIP: (f32, f32, f32, f32) = (0.7, 0.0, 1.0, 1.0);
@@ -34,15 +52,84 @@ const CYAN_SHIP: (f32, f32, f32, f32) = (0.0, 0.7, 1.0, 1.0);
 
 pub struct GamePlay {
     pub map: Map,
+    /// The named or shared track being raced on, or None when the map
+    /// was generated from its seed
+    pub track: Option<Track>,
+    /// The line the AI follows around the map
+    pub racing_line: RacingLine,
     pub ship_entities: Vec<Ship>,
     pub scores: Vec<Score>,
     pub trails: Vec<Trail>,
-    pub camera: Camera,
+    /// One camera for each player. Each player gets their own part of
+    /// the screen.
+    pub cameras: Vec<Camera>,
+    pub ghost: Ghost,
 
     pub countdown_text: TextBox,
     pub leaderboard_text: TextBox,
+    pub replay_text: TextBox,
 
     pub game_duration: f64,
+
+    /// Time that has passed but has not yet been simulated
+    time_accumulator: f64,
+
+    /// How many simulation steps have happened since the reset
+    pub tick: u64,
+
+    /// The players' inputs for this race so far
+    pub recording: Replay,
+
+    /// When set, the players' ships are driven by this replay rather
+    /// than by the keyboard.
+    pub playback: Option<Replay>,
+
+    /// How the AI drives in the current race. This is set from the
+    /// difficulty when the race is reset.
+    pub ai_params: AiParams,
+
+    /// How good the AI is. Takes effect from the next reset.
+    pub difficulty: Difficulty,
+    /// When set, the AI slows down when it is ahead of the players and
+    /// speeds up when it is behind them. Takes effect from the next
+    /// reset.
+    pub catch_up: bool,
+
+    /// How many of the ships are controlled by people. The first
+    /// `num_players` ships belong to players and the rest are AI.
+    pub num_players: usize,
+
+    /// How many times each ship has to cross the start line to finish
+    /// the race. The first crossing is at the start of the race, so
+    /// this is one more than the number of laps.
+    pub laps_to_win: usize,
+
+    /// Which ship each camera follows
+    pub camera_targets: Vec<usize>,
+
+    /// In an online race, which of the ships is driven by this
+    /// computer. Its input is taken from the first player's keys.
+    pub online_player: Option<usize>,
+
+    /// In an online race, the most recent inputs of every player as
+    /// reported by the server. These are used to predict where the
+    /// other players' ships are going.
+    pub remote_inputs: Vec<u8>,
+
+    /// The bumps between ships during the most recent step, for
+    /// sounds, sparks and the like
+    pub collisions: Vec<CollisionEvent>,
+    /// Kept between steps so the collision checks don't allocate
+    broadphase: Broadphase,
+    /// Where every ship was at the start of the step, for the AI to
+    /// react to. Kept between steps so steering doesn't allocate.
+    ai_snapshot: Vec<Ship>,
+}
+
+impl Default for GamePlay {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl GamePlay {
@@ -65,17 +152,17 @@ impl GamePlay {
             scores.push(Score::new());
 
             trails.push(Trail::new(
-                ship.color.clone(),
+                ship.color,
                 MAIN_TRAIL_WIDTH,
                 MAIN_TRAIL_BRIGHTNESS,
             ));
             trails.push(Trail::new(
-                ship.color.clone(),
+                ship.color,
                 WINGTIP_TRAIL_WIDTH,
                 WINGTIP_TRAIL_BRIGHTNESS,
             ));
             trails.push(Trail::new(
-                ship.color.clone(),
+                ship.color,
                 WINGTIP_TRAIL_WIDTH,
                 WINGTIP_TRAIL_BRIGHTNESS,
             ));
@@ -86,50 +173,109 @@ impl GamePlay {
             cos_consts: [0.0, -2.0, 0.0, 1.0, 0.0, 0.0, 0.5, 0.0],
             track_base_radius: 8.0,
             track_width: 0.7,
+            width_sin_consts: [0.0; NUM_WIDTH_WAVES],
+            width_cos_consts: [0.0; NUM_WIDTH_WAVES],
+            start_angle: std::f32::consts::PI / 2.0,
+            seed: 0,
+            spline: None,
+            features: vec![],
         };
 
-        let camera = Camera::new();
+        let racing_line = RacingLine::new(&map);
 
         let countdown_text = TextBox::new((3, 1), 0.2, (0.5, 0.5));
-        let leaderboard_text =
-            TextBox::new((7, (ship_entities.len() + 1) as i32), 0.05, (1.0, 0.5));
+        let leaderboard_text = TextBox::new(
+            (
+                leaderboard_width(NUM_LAPS_TO_WIN, ship_entities.len()),
+                (ship_entities.len() + 2) as i32,
+            ),
+            0.05,
+            (0.9, 0.5),
+        );
+
+        let mut replay_text = TextBox::new((22, 1), 0.05, (0.0, 0.85));
+        replay_text.append_string("Replay ", &[0.0, 0.7, 1.0]);
+        replay_text.append_string("[ENTER]", &[0.0, 1.0, 0.7]);
+        replay_text.append_string(" to exit", &[0.0, 0.7, 1.0]);
+
+        let broadphase = Broadphase::new(ship_entities.len());
 
         Self {
             map,
+            track: None,
+            racing_line,
             ship_entities,
             trails,
             scores,
-            camera,
+            cameras: vec![Camera::new()],
+            ghost: Ghost::new(),
             game_duration: -COUNTDOWN_TIME,
+            time_accumulator: 0.0,
+            tick: 0,
+            recording: Replay::new(0, 1),
+            playback: None,
+            ai_params: AiParams::for_difficulty(Difficulty::Normal),
+            difficulty: Difficulty::Normal,
+            catch_up: false,
+            num_players: 1,
+            laps_to_win: NUM_LAPS_TO_WIN,
+            camera_targets: vec![0],
+            online_player: None,
+            remote_inputs: vec![],
+            collisions: vec![],
+            broadphase,
+            ai_snapshot: vec![],
             countdown_text,
             leaderboard_text,
+            replay_text,
         }
     }
 
-    pub fn steer_ships(&mut self, key_map: &KeyMap) {
-        // Player Ship
-        let player_ship = &mut self.ship_entities[0];
-        player_ship.linear_thrust = 0.0;
-        player_ship.angular_thrust = 0.0;
-        if key_map.forwards.active() {
-            player_ship.linear_thrust += 1.0
-        }
-        if key_map.backwards.active() {
-            player_ship.linear_thrust -= 1.0
-        }
-        if key_map.turn_left.active() {
-            player_ship.angular_thrust += 1.0
-        }
-        if key_map.turn_right.active() {
-            player_ship.angular_thrust -= 1.0
+    /// Sets how many people are playing. Takes effect from the next
+    /// reset.
+    pub fn set_num_players(&mut self, num_players: usize) {
+        self.num_players = num_players.clamp(1, MAX_PLAYERS);
+    }
+
+    pub fn steer_ships(&mut self, player_keys: &[ShipKeys]) {
+        // Player Ships
+        for (player_ship, keys) in self.ship_entities.iter_mut().zip(player_keys) {
+            player_ship.linear_thrust = keys.get_linear_thrust();
+            player_ship.angular_thrust = keys.get_angular_thrust();
         }
 
-        // Ai Ships
-        let num_ships = self.ship_entities.len() - 2;
-        for (id, ship) in self.ship_entities[1..].iter_mut().enumerate() {
-            let mut skill = id as f32 / num_ships as f32;
-            skill = skill * 0.5 + 0.2;
-            calc_ai_control(ship, skill, &self.map);
+        // Ai Ships. They all react to where the other ships were at
+        // the start of the step, so the order they are steered in
+        // doesn't matter.
+        let num_ai = self.ship_entities.len() - self.num_players;
+        let leading_player = self.scores[..self.num_players]
+            .iter()
+            .map(|score| score.progress)
+            .fold(f64::MIN, f64::max);
+        self.ai_snapshot.clone_from(&self.ship_entities);
+        for (id, ship) in self
+            .ship_entities
+            .iter_mut()
+            .enumerate()
+            .skip(self.num_players)
+        {
+            let mut skill = self
+                .recording
+                .difficulty
+                .get_ai_skill(id - self.num_players, num_ai);
+            if self.recording.catch_up {
+                let gap = self.scores[id].progress - leading_player;
+                skill = calc_catch_up_skill(skill, gap);
+            }
+            calc_ai_control(
+                ship,
+                skill,
+                &self.ai_params,
+                &self.map,
+                &self.racing_line,
+                &self.ai_snapshot,
+                id,
+            );
         }
     }
 
@@ -152,26 +298,210 @@ impl GamePlay {
             self.trails[ship_id * 3 + 1].update(
                 dt as f32,
                 wingtip_positions.0,
-                f32::max(f32::min(left_slip, 1.0), 0.0),
+                left_slip.clamp(0.0, 1.0),
             );
             self.trails[ship_id * 3 + 2].update(
                 dt as f32,
                 wingtip_positions.1,
-                f32::max(f32::min(right_slip, 1.0), 0.0),
+                right_slip.clamp(0.0, 1.0),
             );
         }
     }
 
-    pub fn get_text_entities<'a>(&'a self) -> Vec<&'a TextBox> {
-        if self.game_duration < 1.0 {
+    pub fn get_text_entities(&self) -> Vec<&TextBox> {
+        let mut text_entities = if self.game_duration < 1.0 {
             vec![&self.countdown_text]
         } else {
             vec![&self.leaderboard_text]
+        };
+        if self.playback.is_some() {
+            text_entities.push(&self.replay_text);
         }
+        text_entities
     }
 
+    /// Advance the game by a frame lasting `dt` seconds. The physics
+    /// runs in fixed steps of SIM_TIME_STEP so that the game behaves
+    /// the same regardless of the frame rate. Any time left over is
+    /// carried through to the next frame.
     pub fn update(&mut self, dt: f64, key_map: &KeyMap) {
+        self.time_accumulator += dt;
+
+        let mut steps = 0;
+        while self.time_accumulator >= SIM_TIME_STEP {
+            if steps >= MAX_STEPS_PER_FRAME {
+                // We can't keep up (or the tab was in the background)
+                // so drop the time rather than trying to catch up.
+                self.time_accumulator %= SIM_TIME_STEP;
+                break;
+            }
+            self.step(key_map);
+            self.time_accumulator -= SIM_TIME_STEP;
+            steps += 1;
+        }
+
+        self.update_text();
+
+        let fraction = self.get_interpolation();
+        let camera_dt = f64::min(dt, MAX_STEPS_PER_FRAME as f64 * SIM_TIME_STEP) as f32;
+        for (camera, target) in self.cameras.iter_mut().zip(self.camera_targets.iter()) {
+            let ship = &self.ship_entities[*target];
+            let position = ship.get_interpolated_position(fraction);
+            camera.target_position.0 = position.x;
+            camera.target_position.1 = position.y;
+            camera.target_velocity.0 = ship.velocity.x;
+            camera.target_velocity.1 = ship.velocity.y;
+            camera.update(camera_dt);
+        }
+    }
+
+    /// The transform for each player's camera, in the order the
+    /// players' views should be laid out on the screen.
+    pub fn get_camera_matrices(&self) -> Vec<Transform2d> {
+        self.cameras
+            .iter()
+            .map(|camera| camera.get_camera_matrix())
+            .collect()
+    }
+
+    /// Advance the simulation by a single step of SIM_TIME_STEP
+    pub fn step(&mut self, key_map: &KeyMap) {
+        let dt = SIM_TIME_STEP;
+
+        // The players' inputs come from the replay when playing one
+        // back, or from the server for the other players in an online
+        // race. They are recorded until the race is over so it can be
+        // replayed.
+        let inputs: Vec<u8> = (0..self.num_players)
+            .map(|player| match (&self.playback, self.online_player) {
+                (Some(replay), _) => replay.get_input(player, self.tick),
+                (None, Some(online_player)) if online_player == player => {
+                    key_map.players[0].to_bits()
+                }
+                (None, Some(_)) => self.remote_inputs[player],
+                (None, None) => key_map.players[player].to_bits(),
+            })
+            .collect();
+        if !self.game_complete() {
+            self.recording.record(&inputs);
+        }
+
+        let player_keys: Vec<ShipKeys> = inputs.iter().map(|i| ShipKeys::from_bits(*i)).collect();
+        self.simulate(&player_keys);
+
+        if self.game_duration >= 0.0 && self.playback.is_none() {
+            let player = self.online_player.unwrap_or(0);
+            self.ghost
+                .update(dt, &self.ship_entities[player], &self.scores[player]);
+        }
+        self.update_trails(dt);
+    }
+
+    /// Advance the ships and scores by a single step of SIM_TIME_STEP
+    /// without recording anything or updating any visuals. This is
+    /// used directly when re-simulating steps in an online race.
+    pub fn simulate(&mut self, player_keys: &[ShipKeys]) {
+        let dt = SIM_TIME_STEP;
+        for ship in self.ship_entities.iter_mut() {
+            ship.prev_position = ship.position;
+        }
+
         self.game_duration += dt;
+        self.collisions.clear();
+        if self.game_duration >= 0.0 {
+            calc_ship_physics(
+                &mut self.ship_entities,
+                &self.map,
+                dt as f32,
+                &mut self.broadphase,
+                &mut self.collisions,
+            );
+
+            for (ship, score) in self.ship_entities.iter().zip(self.scores.iter_mut()) {
+                score.update(&self.map, ship, self.game_duration);
+            }
+        }
+        self.tick += 1;
+
+        self.steer_ships(player_keys);
+    }
+
+    /// Resets the game to the start of the replay and drives the
+    /// players' ships from it.
+    pub fn start_playback(&mut self, replay: Replay) {
+        self.set_num_players(replay.num_players());
+        match &replay.track {
+            Some(track) => self.reset_on_track(track),
+            None => self.reset(replay.seed),
+        }
+        self.set_ai(replay.difficulty, replay.catch_up);
+        self.playback = Some(replay);
+    }
+
+    /// Resets the game for an online race where this computer drives
+    /// the ship `player_id` and the other players' ships are driven by
+    /// inputs from the server. Every computer in the race simulates
+    /// the AI, so they all use the normal difficulty.
+    pub fn start_online(&mut self, seed: u32, num_players: usize, player_id: usize) {
+        self.set_num_players(num_players);
+        self.reset(seed);
+        self.set_ai(Difficulty::Normal, false);
+        self.online_player = Some(player_id);
+        self.cameras.truncate(1);
+        self.camera_targets = vec![player_id];
+    }
+
+    /// Chooses the AI for the current race. The settings are kept with
+    /// the recording so that a replay is raced against the same AI.
+    fn set_ai(&mut self, difficulty: Difficulty, catch_up: bool) {
+        self.recording.difficulty = difficulty;
+        self.recording.catch_up = catch_up;
+        self.ai_params = AiParams::for_difficulty(difficulty);
+    }
+
+    /// Returns true if a replay is playing and all of the recorded
+    /// inputs have been used.
+    pub fn playback_finished(&self) -> bool {
+        match &self.playback {
+            Some(replay) => self.tick >= replay.len(),
+            None => false,
+        }
+    }
+
+    /// How far (from 0.0 to 1.0) through the next simulation step
+    /// the current frame is.
+    pub fn get_interpolation(&self) -> f32 {
+        (self.time_accumulator / SIM_TIME_STEP) as f32
+    }
+
+    /// Copies of the ships positioned between the previous and the
+    /// current simulation step so that they move smoothly when drawn.
+    pub fn get_render_ships(&self) -> Vec<Ship> {
+        let fraction = self.get_interpolation();
+        let mut ships: Vec<&Ship> = self.ship_entities.iter().collect();
+        if self.ghost.visible {
+            ships.push(&self.ghost.ship);
+        }
+
+        ships
+            .iter()
+            .map(|ship| {
+                let mut render_ship = (*ship).clone();
+                render_ship.position = ship.get_interpolated_position(fraction);
+                render_ship
+            })
+            .collect()
+    }
+
+    pub fn get_render_trails(&self) -> Vec<&Trail> {
+        let mut trails: Vec<&Trail> = self.trails.iter().collect();
+        if self.ghost.visible {
+            trails.push(&self.ghost.trail);
+        }
+        trails
+    }
+
+    fn update_text(&mut self) {
         if self.game_duration < 0.0 {
             // Do the countdown!
             self.countdown_text.clear();
@@ -181,51 +511,94 @@ impl GamePlay {
 
             self.countdown_text
                 .append_string(&format!(" {} ", remaining as u8), &[0.0, diff as f32, 0.0]);
+        } else if self.game_duration < 1.0 {
+            self.countdown_text.clear();
+            self.countdown_text
+                .append_string("Go!", &[0.0, 1.0 - self.game_duration as f32, 0.0]);
         } else {
-            if self.game_duration < 1.0 {
-                self.countdown_text.clear();
-                self.countdown_text
-                    .append_string(&"Go!", &[0.0, 1.0 - self.game_duration as f32, 0.0]);
-            } else {
-                self.generate_leaderboard_text();
-            }
-            calc_ship_physics(&mut self.ship_entities, &self.map, dt as f32);
+            self.generate_leaderboard_text();
+        }
+    }
 
-            for (ship, score) in self.ship_entities.iter().zip(self.scores.iter_mut()) {
-                score.update(&self.map, ship, self.game_duration);
-            }
+    pub fn reset(&mut self, seed: u32) {
+        self.map.randomize(seed);
+        self.track = None;
+        self.laps_to_win = NUM_LAPS_TO_WIN;
+        self.restart();
+    }
+
+    /// What the track is known as in the records: the track code of a
+    /// generated map, or the whole track for a named or shared one
+    pub fn get_track_id(&self) -> String {
+        match &self.track {
+            Some(track) => track.to_string(),
+            None => to_track_code(self.map.seed),
         }
+    }
 
-        self.steer_ships(key_map);
-        self.update_trails(dt);
+    /// What the track is called on the score screen
+    pub fn get_track_name(&self) -> String {
+        match &self.track {
+            Some(track) => track.name.clone(),
+            None => to_track_code(self.map.seed),
+        }
+    }
 
-        self.camera.target_position.0 = self.ship_entities[0].position.x;
-        self.camera.target_position.1 = self.ship_entities[0].position.y;
-        self.camera.target_velocity.0 = self.ship_entities[0].velocity.x;
-        self.camera.target_velocity.1 = self.ship_entities[0].velocity.y;
-        self.camera.update(dt as f32);
+    /// Starts a new race on a map that wasn't generated from a seed,
+    /// such as one following a spline. It is raced as a custom track
+    /// with the normal number of laps.
+    pub fn reset_on_map(&mut self, map: Map) {
+        self.reset_on_track(&Track::new("Custom", "Anonymous", map));
     }
 
-    pub fn reset(&mut self) {
-        self.camera.reset();
-        self.map.randomize();
+    /// Starts a new race on a shared track, with as many laps as the
+    /// track asks for
+    pub fn reset_on_track(&mut self, track: &Track) {
+        self.map = track.map.clone();
+        self.track = Some(track.clone());
+        self.laps_to_win = track.laps + 1;
+        self.restart();
+    }
+
+    /// Puts everything back to the start of a race on the current map
+    fn restart(&mut self) {
+        let seed = self.map.seed;
+        self.racing_line = RacingLine::new(&self.map);
         self.game_duration = -COUNTDOWN_TIME;
+        self.time_accumulator = 0.0;
+        self.tick = 0;
+        self.recording = Replay::new(seed, self.num_players);
+        self.recording.track = self.track.clone();
+        self.set_ai(self.difficulty, self.catch_up);
+        self.playback = None;
+        self.cameras = (0..self.num_players).map(|_| Camera::new()).collect();
+        for camera in self.cameras.iter_mut() {
+            camera.reset();
+        }
+        self.camera_targets = (0..self.num_players).collect();
+        self.online_player = None;
+        self.remote_inputs = vec![0; self.num_players];
+        self.ghost.reset(&self.map);
+        self.leaderboard_text.box_dimensions.0 =
+            leaderboard_width(self.laps_to_win, self.ship_entities.len());
 
         {
             // Position the ships on the start line
 
-            let start_position = self.map.get_start_position();
-            let startline_angle = self.map.get_track_direction(start_position.angle);
+            let (ship_start_position, startline_angle) = self.map.get_start_line();
 
             let startline_tangent = (f32::cos(startline_angle), f32::sin(startline_angle));
             let startline_normal = (-f32::sin(startline_angle), f32::cos(startline_angle));
 
-            let ship_start_position = start_position.to_cartesian();
+            // A narrow start line has fewer ships on each row
+            let start_width = self.map.calc_track_width(ship_start_position);
+            let num_columns = ((start_width - START_GRID_MARGIN) * 2.0 / SHIP_SPACING) as usize;
+            let num_columns = num_columns.clamp(1, NUM_START_COLUMNS);
 
             for (id, ship) in self.ship_entities.iter_mut().enumerate() {
-                let row = id / NUM_START_COLUMNS;
-                let column = id % NUM_START_COLUMNS;
-                let column_offset = (column as f32) - ((NUM_START_COLUMNS - 1) as f32) * 0.5;
+                let row = id / num_columns;
+                let column = id % num_columns;
+                let column_offset = (column as f32) - ((num_columns - 1) as f32) * 0.5;
                 let row_offset = row as f32 + 0.5;
 
                 let offset_vec = (
@@ -238,11 +611,14 @@ impl GamePlay {
                 ship.position.x = ship_start_position.0 + offset_vec.0;
                 ship.position.y = ship_start_position.1 + offset_vec.1;
                 ship.position.rot = startline_angle;
+                ship.prev_position = ship.position;
 
                 ship.velocity.x = 0.0;
                 ship.velocity.y = 0.0;
                 ship.velocity.rot = 0.0;
+                ship.airborne = 0.0;
 
+                self.scores[id].laps_to_win = self.laps_to_win;
                 self.scores[id].reset(&self.map, ship);
             }
         }
@@ -252,40 +628,62 @@ impl GamePlay {
     }
     pub fn generate_leaderboard_text(&mut self) {
         self.leaderboard_text.clear();
+        // Each line is padded to the width of the box so the next one
+        // starts on a new row
+        let width = self.leaderboard_text.box_dimensions.0 as usize;
 
         let mut ship_and_score_refs: Vec<(&Ship, &Score)> =
             self.ship_entities.iter().zip(self.scores.iter()).collect();
-        ship_and_score_refs.sort_by(|a, b| a.1.cmp(b.1));
-        let winner_score = ship_and_score_refs.first().expect("No Ships").1;
+        ship_and_score_refs.sort_by(|a, b| a.1.cmp_position(b.1));
+        let leader_score = ship_and_score_refs.first().expect("No Ships").1;
 
         self.leaderboard_text.append_string(
             &format!(
-                "Lap {}/{}",
-                winner_score.laps.len() - 1,
-                NUM_LAPS_TO_WIN - 1
+                "{:<width$}",
+                format!(
+                    "Lap {}/{}",
+                    leader_score.laps.len().saturating_sub(1),
+                    self.laps_to_win - 1
+                ),
+                width = width
             ),
             &[0.5, 0.5, 0.5],
         );
-        for (ship, score) in ship_and_score_refs {
+
+        // Each ship's position and how far behind the ship in front of
+        // it they are
+        let mut ahead: Option<&Score> = None;
+        for (position, (ship, score)) in ship_and_score_refs.iter().enumerate() {
             let color = [ship.color.0, ship.color.1, ship.color.2];
-            if score.laps.len() == winner_score.laps.len() {
-                if let Some(winner_time) = winner_score.laps.last() {
-                    // Same lap - display time
-                    let time = score.laps.last().unwrap() - winner_time;
-                    let seconds = time as u32;
-                    let millis = (time.fract() * 100.0).floor() as u32;
-                    self.leaderboard_text
-                        .append_string(&format!("~ {:02}:{:02}", seconds, millis), &color);
-                } else {
-                    // No-one has any time yet
-                    self.leaderboard_text
-                        .append_string(&format!("~ --:--",), &color);
+            let gap = match ahead.and_then(|ahead| score.get_gap_to(ahead)) {
+                Some(gap) => {
+                    let gap = gap.clamp(0.0, 99.99);
+                    let seconds = gap as u32;
+                    let millis = (gap.fract() * 100.0).floor() as u32;
+                    format!("{:02}:{:02}", seconds, millis)
                 }
+                None => "--:--".to_string(),
+            };
+            let line = format!("{:<3} ~ {}", ordinal(position + 1), gap);
+            self.leaderboard_text
+                .append_string(&format!("{:<width$}", line, width = width), &color);
+            ahead = Some(score);
+        }
+
+        // How the player is doing compared to their best lap, as of the
+        // last checkpoint
+        if let Some(delta) = self.ghost.split_delta {
+            let (sign, color) = if delta < 0.0 {
+                ("-", [0.0, 1.0, 0.0])
             } else {
-                // This player is at least a lap behind
-                self.leaderboard_text
-                    .append_string(&format!("~ --:--",), &color);
-            }
+                (" ", [1.0, 0.0, 0.0])
+            };
+            let time = delta.abs();
+            let seconds = time as u32;
+            let millis = (time.fract() * 100.0).floor() as u32;
+            let line = format!("{}{:02}:{:02}", sign, seconds, millis);
+            self.leaderboard_text
+                .append_string(&format!("{:>width$}", line, width = width), &color);
         }
     }
 
@@ -294,10 +692,30 @@ impl GamePlay {
     /// done enough laps
     pub fn game_complete(&self) -> bool {
         for score in self.scores.iter() {
-            if score.laps.len() < NUM_LAPS_TO_WIN {
+            if score.laps.len() < self.laps_to_win {
                 return false;
             }
         }
-        return true;
+        true
     }
 }
+
+/// How many characters wide the leaderboard needs to be to fit the lap
+/// counter and every ship's line without wrapping
+fn leaderboard_width(laps_to_win: usize, num_ships: usize) -> i32 {
+    let laps = format!("Lap {0}/{0}", laps_to_win).len();
+    let ships = format!("{:<3} ~ --:--", ordinal(num_ships)).len();
+    laps.max(ships) as i32
+}
+
+/// Turns a race position into text, eg 1 into 1st
+fn ordinal(position: usize) -> String {
+    let suffix = match (position % 10, position % 100) {
+        (_, 11..=13) => "th",
+        (1, _) => "st",
+        (2, _) => "nd",
+        (3, _) => "rd",
+        _ => "th",
+    };
+    format!("{}{}", position, suffix)
+}
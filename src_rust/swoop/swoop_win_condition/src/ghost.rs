@@ -0,0 +1,166 @@
+use std::collections::HashMap;
+
+use super::map::Map;
+use super::score::Score;
+use super::ship::Ship;
+use super::trail::Trail;
+use super::transform::Transform2d;
+
+/// The ghost is drawn much dimmer than the normal ships
+const GHOST_COLOR: (f32, f32, f32, f32) = (0.0, 0.2, 0.3, 0.3);
+const GHOST_TRAIL_WIDTH: f32 = 0.05;
+const GHOST_TRAIL_BRIGHTNESS: f32 = 0.1;
+
+/// The path the player took around their best lap on a track
+pub struct GhostLap {
+    pub time: f64,
+    /// The time since the start of the lap at each checkpoint (see
+    /// `Score::get_lap_splits`)
+    pub splits: Vec<f64>,
+    /// The position of the player's ship on each simulation step
+    pub path: Vec<Transform2d>,
+}
+
+/// Shows the path the player took on their best lap as a faint ship
+/// racing alongside them.
+pub struct Ghost {
+    pub ship: Ship,
+    pub trail: Trail,
+
+    /// Whether the ghost should currently be shown
+    pub visible: bool,
+
+    /// The player's best lap on each track, by the track's map as a
+    /// string. Tracks that aren't generated from a seed (such as ones
+    /// made in the editor) all have a seed of 0, so the seed can't be
+    /// used to tell them apart.
+    best_laps: HashMap<String, GhostLap>,
+    track: String,
+
+    /// Positions of the player's ship during the lap in progress
+    current_lap: Vec<Transform2d>,
+    laps_seen: usize,
+
+    /// How far ahead (negative) or behind (positive) of their best lap
+    /// the player was at the last checkpoint
+    pub split_delta: Option<f64>,
+    splits_seen: usize,
+}
+
+impl Default for Ghost {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Ghost {
+    pub fn new() -> Self {
+        Self {
+            ship: Ship::new(GHOST_COLOR),
+            trail: Trail::new(GHOST_COLOR, GHOST_TRAIL_WIDTH, GHOST_TRAIL_BRIGHTNESS),
+            visible: false,
+            best_laps: HashMap::new(),
+            track: String::new(),
+            current_lap: vec![],
+            laps_seen: 0,
+            split_delta: None,
+            splits_seen: 0,
+        }
+    }
+
+    /// Prepare for a new race on a map. Any best laps from previous
+    /// races on this map are kept.
+    pub fn reset(&mut self, map: &Map) {
+        self.track = map.to_string();
+        self.visible = false;
+        self.current_lap.clear();
+        self.laps_seen = 0;
+        self.split_delta = None;
+        self.splits_seen = 0;
+        self.trail.reset();
+    }
+
+    /// The best lap on the current track, if the player has done one
+    pub fn get_best_lap(&self) -> Option<&GhostLap> {
+        self.best_laps.get(&self.track)
+    }
+
+    /// Record where the player is, check if they set a new best lap and
+    /// move the ghost along the best lap.
+    pub fn update(&mut self, dt: f64, player: &Ship, player_score: &Score) {
+        // This is compared with the best lap before it is replaced so
+        // that a new best lap shows how much faster it was
+        if player_score.splits.len() != self.splits_seen {
+            self.splits_seen = player_score.splits.len();
+            self.split_delta = match (player_score.get_latest_split(), self.get_best_lap()) {
+                (Some((sector, time)), Some(best)) => {
+                    best.splits.get(sector).map(|best_time| time - best_time)
+                }
+                _ => None,
+            };
+        }
+
+        let laps = player_score.laps.len();
+        if laps > self.laps_seen {
+            // The first crossing of the line is the start of the first
+            // timed lap rather than the end of a lap
+            if laps >= 2 {
+                let lap_time = player_score.laps[laps - 1] - player_score.laps[laps - 2];
+                let is_best = match self.get_best_lap() {
+                    Some(best) => lap_time < best.time,
+                    None => true,
+                };
+                if is_best {
+                    let path = std::mem::take(&mut self.current_lap);
+                    self.best_laps.insert(
+                        self.track.clone(),
+                        GhostLap {
+                            time: lap_time,
+                            splits: player_score.get_lap_splits(laps - 2),
+                            path,
+                        },
+                    );
+                }
+            }
+            self.current_lap.clear();
+            self.trail.reset();
+        } else if laps < self.laps_seen {
+            // The player went backwards over the line, so this lap
+            // can't be compared to the best lap.
+            self.current_lap.clear();
+        }
+        self.laps_seen = laps;
+
+        if laps == 0 {
+            // Not yet started the first timed lap
+            return;
+        }
+
+        self.current_lap.push(player.position);
+
+        let ghost_position = self
+            .get_best_lap()
+            .and_then(|best| best.path.get(self.current_lap.len() - 1))
+            .cloned();
+
+        match ghost_position {
+            Some(position) => {
+                if !self.visible {
+                    // Don't interpolate from wherever the ghost was last
+                    self.ship.position = position;
+                }
+                self.ship.prev_position = self.ship.position;
+                self.ship.position = position;
+                self.visible = true;
+
+                self.trail
+                    .update(dt as f32, self.ship.get_engine_position(), 1.0);
+            }
+            None => {
+                // Either there is no best lap or the player is slower
+                // than it and the ghost has already finished.
+                self.visible = false;
+            }
+        }
+    }
+}
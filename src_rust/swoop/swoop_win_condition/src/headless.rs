@@ -0,0 +1,82 @@
+//! Runs races without a browser, canvas or renderer so that the
+//! gameplay can be checked from native code (eg `cargo test`).
+use super::gameplay::GamePlay;
+use super::keymap::KeyMap;
+
+/// Steps a race forward one simulation step (SIM_TIME_STEP) at a
+/// time with inputs supplied by a script rather than a keyboard.
+pub struct Simulation {
+    pub gameplay: GamePlay,
+    pub key_map: KeyMap,
+
+    /// How many ticks have been simulated since the race was reset
+    pub tick: u64,
+}
+
+impl Simulation {
+    /// Creates a race on the track with the provided seed. The race
+    /// starts at the beginning of the countdown.
+    pub fn new(seed: u32) -> Self {
+        Self::with_players(seed, 1)
+    }
+
+    /// Creates a race where the first `num_players` ships are driven
+    /// by the key map, as in a split-screen game.
+    pub fn with_players(seed: u32, num_players: usize) -> Self {
+        let mut gameplay = GamePlay::new();
+        gameplay.set_num_players(num_players);
+        gameplay.reset(seed);
+
+        let mut key_map = KeyMap::new();
+        key_map.num_players = gameplay.num_players;
+
+        Self {
+            gameplay,
+            key_map,
+            tick: 0,
+        }
+    }
+
+    /// Advance the race by a single tick using the current state of
+    /// the key map.
+    pub fn step(&mut self) {
+        self.gameplay.step(&self.key_map);
+        self.key_map.update();
+        self.tick += 1;
+    }
+
+    /// Advance the race by `ticks` ticks. Before each tick the script
+    /// is called with the tick number, the key map so the inputs can
+    /// be changed, and the gameplay so the script can react to (or
+    /// check) the state of the race.
+    pub fn run<F>(&mut self, ticks: u64, mut script: F)
+    where
+        F: FnMut(u64, &mut KeyMap, &GamePlay),
+    {
+        for _ in 0..ticks {
+            script(self.tick, &mut self.key_map, &self.gameplay);
+            self.step();
+        }
+    }
+
+    /// Advance the race until every ship has completed the race or
+    /// `max_ticks` have elapsed. Returns true if the race finished.
+    pub fn run_until_complete<F>(&mut self, max_ticks: u64, mut script: F) -> bool
+    where
+        F: FnMut(u64, &mut KeyMap, &GamePlay),
+    {
+        for _ in 0..max_ticks {
+            if self.gameplay.game_complete() {
+                return true;
+            }
+            script(self.tick, &mut self.key_map, &self.gameplay);
+            self.step();
+        }
+        self.gameplay.game_complete()
+    }
+
+    /// How long the race has been going, not including the countdown.
+    pub fn race_time(&self) -> f64 {
+        self.gameplay.game_duration
+    }
+}
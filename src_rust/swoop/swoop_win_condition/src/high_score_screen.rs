@@ -0,0 +1,108 @@
+use super::records::Records;
+use super::text_box::TextBox;
+
+const TEXT_COLOR: [f32; 3] = [0.0, 0.7, 1.0];
+const HIGHLIGHT_COLOR: [f32; 3] = [0.0, 1.0, 0.7];
+const LABEL_COLOR: [f32; 3] = [0.5, 0.5, 0.5];
+
+/// How many tracks fit on the screen
+const MAX_ROWS: usize = 10;
+
+/// A table of the player's personal bests on the tracks they have
+/// raced on most recently
+pub struct HighScoreScreen {
+    title: TextBox,
+    /// The column headings followed by a line for each track. Each
+    /// line is its own text box as a text box can only hold 128
+    /// characters.
+    rows: Vec<TextBox>,
+    instructions: TextBox,
+}
+
+impl HighScoreScreen {
+    pub fn new() -> Self {
+        let mut title = TextBox::new((7, 1), 0.08, (0.0, 0.75));
+        title.append_string("Records", &TEXT_COLOR);
+
+        let rows = (0..MAX_ROWS + 1)
+            .map(|row| TextBox::new((36, 1), 0.03, (0.0, 0.5 - row as f32 * 0.08)))
+            .collect();
+
+        let mut high_score_screen = Self {
+            title,
+            rows,
+            instructions: TextBox::new((18, 1), 0.04, (0.0, -0.6)),
+        };
+        high_score_screen.set_touch_detected(false);
+        high_score_screen
+    }
+
+    pub fn set_touch_detected(&mut self, touch_detected: bool) {
+        self.instructions.clear();
+        if touch_detected {
+            self.instructions.append_string("   ", &TEXT_COLOR);
+            self.instructions.append_string("Tap", &HIGHLIGHT_COLOR);
+            self.instructions.append_string(" to go back", &TEXT_COLOR);
+        } else {
+            self.instructions.append_string("Press ", &TEXT_COLOR);
+            self.instructions.append_string("[ENTER]", &HIGHLIGHT_COLOR);
+            self.instructions.append_string(" to go back", &TEXT_COLOR);
+        }
+    }
+
+    /// Called when the screen is shown
+    pub fn open(&mut self, records: &Records) {
+        for row in self.rows.iter_mut() {
+            row.clear();
+        }
+
+        self.rows[0].append_string(
+            &format!(
+                "{:<7} {:>5} {:>6}{:>4}{:>4}{:>4}{:>4}",
+                "Track", "Lap", "Race", "1st", "2nd", "3rd", "4th"
+            ),
+            &LABEL_COLOR,
+        );
+
+        if records.tracks.is_empty() {
+            self.rows[1].append_string(&format!("{:^36}", "No races yet"), &TEXT_COLOR);
+        }
+
+        let recent_tracks = records.tracks.iter().rev().take(MAX_ROWS);
+        for (row, track) in self.rows[1..].iter_mut().zip(recent_tracks) {
+            row.append_string(&format!("{:<7.7} ", track.get_name()), &HIGHLIGHT_COLOR);
+            row.append_string(
+                &format!(
+                    "{:>5} {:>6}",
+                    format_time(track.best_lap),
+                    format_time(track.best_race)
+                ),
+                &TEXT_COLOR,
+            );
+            let positions: String = (0..4)
+                .map(|position| {
+                    let count = track.positions.get(position).cloned().unwrap_or(0);
+                    // Only three digits fit in a column
+                    format!("{:>4}", count.min(999))
+                })
+                .collect();
+            row.append_string(&positions, &LABEL_COLOR);
+        }
+    }
+
+    pub fn get_text_entities(&self) -> Vec<&TextBox> {
+        let mut text_boxes = vec![&self.title, &self.instructions];
+        text_boxes.extend(self.rows.iter());
+        text_boxes
+    }
+}
+
+fn format_time(time: Option<f64>) -> String {
+    if let Some(sec) = time {
+        let seconds = sec as u32;
+        let millis = (sec.fract() * 100.0).floor() as u32;
+        format!("{:02}:{:02}", seconds, millis)
+    } else {
+        "--:--".to_string()
+    }
+}
@@ -1,4 +1,7 @@
-#[derive(Debug, PartialEq)]
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, PartialEq, Clone)]
 /// A state machine to represent the state of a key on the keyboard
 /// preserving edge-states.
 /// Most of the time will transition `JustPressed -> Down -> JustReleased -> Up`
@@ -40,24 +43,174 @@ impl KeyState {
     }
 }
 
-/// Stores the state of the keys that we are interested in for this game
+/// The most players that can share a keyboard
+pub const MAX_PLAYERS: usize = 4;
+
+/// The things a player can bind a key to, in the same order as the
+/// keys in `Bindings`
+pub const ACTION_NAMES: [&str; 4] = ["Forwards", "Backwards", "Turn Left", "Turn Right"];
+
+/// The keys each player uses to steer their ship until they choose
+/// their own, in the order: forwards, backwards, turn left, turn right
+const DEFAULT_BINDINGS: [[&str; 4]; MAX_PLAYERS] = [
+    ["KeyW", "KeyS", "KeyA", "KeyD"],
+    ["ArrowUp", "ArrowDown", "ArrowLeft", "ArrowRight"],
+    ["KeyI", "KeyK", "KeyJ", "KeyL"],
+    ["Numpad8", "Numpad5", "Numpad4", "Numpad6"],
+];
+
+/// Keys used by the menus, which can't be used for steering
+pub const RESERVED_KEYS: [&str; 12] = [
+    "Enter", "Escape", "KeyC", "KeyE", "KeyH", "KeyR", "KeyU", "KeyV", "Digit1", "Digit2",
+    "Digit3", "Digit4",
+];
+
+#[derive(Debug, PartialEq)]
+pub enum BindingError {
+    /// The key is used by the menus
+    Reserved(String),
+    /// The key is already used by another player or action
+    AlreadyBound {
+        player: usize,
+        action: usize,
+    },
+    /// A player doesn't have a key for every action
+    WrongNumberOfKeys(usize),
+    TooManyPlayers(usize),
+}
+
+/// Which key (as a `KeyboardEvent.code()` string) each player uses
+/// for each action.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bindings {
+    pub keys: Vec<[String; 4]>,
+}
+
+impl Bindings {
+    /// WASD for the first player, arrow keys for the second and so on
+    pub fn defaults() -> Self {
+        Self {
+            keys: DEFAULT_BINDINGS
+                .iter()
+                .map(|player_keys| player_keys.map(|key| key.to_string()))
+                .collect(),
+        }
+    }
+
+    /// Returns the player and action that a key is bound to
+    pub fn find(&self, code: &str) -> Option<(usize, usize)> {
+        self.keys.iter().enumerate().find_map(|(player, keys)| {
+            keys.iter()
+                .position(|key| key == code)
+                .map(|action| (player, action))
+        })
+    }
+
+    /// Binds a key to one of a player's actions. Fails without changing
+    /// anything if the key is used for something else.
+    pub fn bind(&mut self, player: usize, action: usize, code: &str) -> Result<(), BindingError> {
+        if RESERVED_KEYS.contains(&code) {
+            return Err(BindingError::Reserved(code.to_string()));
+        }
+        match self.find(code) {
+            Some(existing) if existing != (player, action) => Err(BindingError::AlreadyBound {
+                player: existing.0,
+                action: existing.1,
+            }),
+            _ => {
+                self.keys[player][action] = code.to_string();
+                Ok(())
+            }
+        }
+    }
+
+    /// Checks that every key is only used once and that no menu keys
+    /// are used.
+    pub fn validate(&self) -> Result<(), BindingError> {
+        for (player, keys) in self.keys.iter().enumerate() {
+            for (action, key) in keys.iter().enumerate() {
+                if RESERVED_KEYS.contains(&key.as_str()) {
+                    return Err(BindingError::Reserved(key.clone()));
+                }
+                let first_use = self.find(key).unwrap();
+                if first_use != (player, action) {
+                    return Err(BindingError::AlreadyBound {
+                        player: first_use.0,
+                        action: first_use.1,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The bindings are stored with the keys for each player separated by
+/// commas and the players separated by slashes.
+impl fmt::Display for Bindings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let players: Vec<String> = self.keys.iter().map(|keys| keys.join(",")).collect();
+        write!(f, "{}", players.join("/"))
+    }
+}
+
+impl FromStr for Bindings {
+    type Err = BindingError;
+
+    /// Reads bindings created with `to_string`. Players that are
+    /// missing get the default keys.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let players: Vec<&str> = s.split('/').collect();
+        if players.len() > MAX_PLAYERS {
+            return Err(BindingError::TooManyPlayers(players.len()));
+        }
+
+        let mut bindings = Self::defaults();
+        for (player, player_keys) in players.iter().enumerate() {
+            let keys: Vec<&str> = player_keys.split(',').collect();
+            if keys.len() != ACTION_NAMES.len() {
+                return Err(BindingError::WrongNumberOfKeys(player));
+            }
+            for (action, key) in keys.iter().enumerate() {
+                bindings.keys[player][action] = key.to_string();
+            }
+        }
+        bindings.validate()?;
+        Ok(bindings)
+    }
+}
+
+/// Analog inputs are stored in replays (and sent over the network) as
+/// a whole number of steps from -INPUT_STEPS to INPUT_STEPS.
+const INPUT_STEPS: f32 = 7.0;
+
+/// The keys used to steer a single ship
 #[derive(Debug)]
-pub struct KeyMap {
+pub struct ShipKeys {
     pub forwards: KeyState,
     pub backwards: KeyState,
     pub turn_left: KeyState,
     pub turn_right: KeyState,
-    pub start_game: KeyState,
+
+    /// Analog throttle (eg from a gamepad) from -1.0 (full reverse) to
+    /// 1.0 (full forwards). This is added to the keys.
+    pub throttle: f32,
+
+    /// Analog steering from -1.0 (full right) to 1.0 (full left), the
+    /// same direction as `Ship::angular_thrust`. This is added to the
+    /// keys.
+    pub steering: f32,
 }
 
-impl KeyMap {
-    pub fn new() -> Self {
+impl ShipKeys {
+    fn new() -> Self {
         Self {
             forwards: KeyState::Up,
             backwards: KeyState::Up,
             turn_left: KeyState::Up,
             turn_right: KeyState::Up,
-            start_game: KeyState::Up,
+            throttle: 0.0,
+            steering: 0.0,
         }
     }
 
@@ -67,18 +220,155 @@ impl KeyMap {
         self.backwards = self.backwards.update();
         self.turn_left = self.turn_left.update();
         self.turn_right = self.turn_right.update();
+    }
+
+    /// How hard the ship should thrust forwards (-1.0 to 1.0)
+    pub fn get_linear_thrust(&self) -> f32 {
+        let mut thrust = self.throttle;
+        if self.forwards.active() {
+            thrust += 1.0;
+        }
+        if self.backwards.active() {
+            thrust -= 1.0;
+        }
+        thrust.clamp(-1.0, 1.0)
+    }
+
+    /// How hard the ship should turn left (-1.0 to 1.0)
+    pub fn get_angular_thrust(&self) -> f32 {
+        let mut thrust = self.steering;
+        if self.turn_left.active() {
+            thrust += 1.0;
+        }
+        if self.turn_right.active() {
+            thrust -= 1.0;
+        }
+        thrust.clamp(-1.0, 1.0)
+    }
+
+    /// Packs the thrust and steering into a single byte. This is what
+    /// gets stored in a replay. The low four bits are the linear thrust
+    /// and the high four bits the angular thrust, each as a signed
+    /// number of INPUT_STEPS. No input is zero.
+    pub fn to_bits(&self) -> u8 {
+        let to_nibble = |value: f32| ((value * INPUT_STEPS).round() as i8 as u8) & 0x0F;
+        to_nibble(self.get_linear_thrust()) | to_nibble(self.get_angular_thrust()) << 4
+    }
+
+    /// Creates a ShipKeys with the same thrust and steering as the
+    /// output of `to_bits`
+    pub fn from_bits(bits: u8) -> Self {
+        // Shifting the nibble to the top of an i8 and back again
+        // restores the sign.
+        let from_nibble = |nibble: u8| ((nibble << 4) as i8 >> 4) as f32 / INPUT_STEPS;
+
+        let mut keys = Self::new();
+        keys.throttle = from_nibble(bits & 0x0F);
+        keys.steering = from_nibble(bits >> 4);
+        keys
+    }
+
+    fn set_state(&mut self, action: usize, new_state: KeyState) {
+        match action {
+            0 => self.forwards = new_state,
+            1 => self.backwards = new_state,
+            2 => self.turn_left = new_state,
+            3 => self.turn_right = new_state,
+            _ => (),
+        }
+    }
+}
+
+/// Stores the state of the keys that we are interested in for this game
+#[derive(Debug)]
+pub struct KeyMap {
+    /// The steering keys for each player
+    pub players: Vec<ShipKeys>,
+    pub start_game: KeyState,
+    pub watch_replay: KeyState,
+    pub show_controls: KeyState,
+    pub show_high_scores: KeyState,
+    pub show_editor: KeyState,
+    pub change_difficulty: KeyState,
+    pub toggle_catch_up: KeyState,
+    /// The number keys 1 to MAX_PLAYERS, used to choose how many
+    /// players there are.
+    pub select_players: Vec<KeyState>,
+
+    /// How many players are sharing the keyboard. When there is only
+    /// one player, they can use either their own keys or the second
+    /// player's.
+    pub num_players: usize,
+
+    /// Which keys steer each player's ship
+    pub bindings: Bindings,
+}
+
+impl Default for KeyMap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl KeyMap {
+    pub fn new() -> Self {
+        Self {
+            players: (0..MAX_PLAYERS).map(|_| ShipKeys::new()).collect(),
+            start_game: KeyState::Up,
+            watch_replay: KeyState::Up,
+            show_controls: KeyState::Up,
+            show_high_scores: KeyState::Up,
+            show_editor: KeyState::Up,
+            change_difficulty: KeyState::Up,
+            toggle_catch_up: KeyState::Up,
+            select_players: vec![KeyState::Up; MAX_PLAYERS],
+            num_players: 1,
+            bindings: Bindings::defaults(),
+        }
+    }
+
+    /// Progress each keys state machine
+    pub fn update(&mut self) {
+        for player in self.players.iter_mut() {
+            player.update();
+        }
         self.start_game = self.start_game.update();
+        self.watch_replay = self.watch_replay.update();
+        self.show_controls = self.show_controls.update();
+        self.show_high_scores = self.show_high_scores.update();
+        self.show_editor = self.show_editor.update();
+        self.change_difficulty = self.change_difficulty.update();
+        self.toggle_catch_up = self.toggle_catch_up.update();
+        for key in self.select_players.iter_mut() {
+            *key = key.update();
+        }
     }
 
     /// Force the state of a specific key based on a "key code" string.
     /// This code generally comes from a javascript `KeyboardEvent.code()`
     pub fn set_state_from_str(&mut self, code: &str, new_state: KeyState) {
+        if let Some((player_id, action)) = self.bindings.find(code) {
+            if self.num_players == 1 && player_id == 1 {
+                // A single player can use the arrow keys as well
+                self.players[0].set_state(action, new_state);
+            } else {
+                self.players[player_id].set_state(action, new_state);
+            }
+            return;
+        }
+
         match code {
-            "KeyW" | "ArrowUp" => self.forwards = new_state,
-            "KeyS" | "ArrowDown" => self.backwards = new_state,
-            "KeyA" | "ArrowLeft" => self.turn_left = new_state,
-            "KeyD" | "ArrowRight" => self.turn_right = new_state,
             "Enter" => self.start_game = new_state,
+            "KeyR" => self.watch_replay = new_state,
+            "KeyC" => self.show_controls = new_state,
+            "KeyH" => self.show_high_scores = new_state,
+            "KeyE" => self.show_editor = new_state,
+            "KeyU" => self.toggle_catch_up = new_state,
+            "KeyV" => self.change_difficulty = new_state,
+            "Digit1" => self.select_players[0] = new_state,
+            "Digit2" => self.select_players[1] = new_state,
+            "Digit3" => self.select_players[2] = new_state,
+            "Digit4" => self.select_players[3] = new_state,
             _ => (),
         };
     }
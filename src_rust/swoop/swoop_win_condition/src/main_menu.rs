@@ -1,21 +1,86 @@
+use super::difficulty::Difficulty;
 use super::logo::Logo;
-use super::text_sprite::TextBox;
+use super::text_box::TextBox;
 
 pub struct MainMenu {
     pub logo: Logo,
     pub text: TextBox,
+    pub players_text: TextBox,
+    pub controls_text: TextBox,
+    pub difficulty_text: TextBox,
+    pub catch_up_text: TextBox,
+    pub editor_text: TextBox,
     logo_flash_time: f32,
+    /// Ask the player to tap rather than press [ENTER]
+    touch_detected: bool,
 }
 
 impl MainMenu {
     pub fn new() -> Self {
         let text = TextBox::new((22, 1), 0.05, (0.0, -0.2));
 
-        Self {
+        let mut main_menu = Self {
             logo: Logo::new(),
             text,
+            players_text: TextBox::new((26, 1), 0.04, (0.0, -0.3)),
+            controls_text: TextBox::new((24, 1), 0.04, (0.0, -0.4)),
+            difficulty_text: TextBox::new((24, 1), 0.04, (0.0, -0.5)),
+            catch_up_text: TextBox::new((24, 1), 0.04, (0.0, -0.6)),
+            editor_text: TextBox::new((20, 1), 0.04, (0.0, -0.7)),
             logo_flash_time: 0.0,
-        }
+            touch_detected: false,
+        };
+        main_menu.set_num_players(1);
+        main_menu.set_difficulty(Difficulty::Normal, false);
+        main_menu
+            .controls_text
+            .append_string("[C]", &[0.0, 1.0, 0.7]);
+        main_menu
+            .controls_text
+            .append_string(" controls  ", &[0.0, 0.5, 0.7]);
+        main_menu
+            .controls_text
+            .append_string("[H]", &[0.0, 1.0, 0.7]);
+        main_menu
+            .controls_text
+            .append_string(" records", &[0.0, 0.5, 0.7]);
+        main_menu.editor_text.append_string("[E]", &[0.0, 1.0, 0.7]);
+        main_menu
+            .editor_text
+            .append_string(" track editor", &[0.0, 0.5, 0.7]);
+        main_menu
+    }
+
+    /// Shows how many players will be racing
+    pub fn set_num_players(&mut self, num_players: usize) {
+        self.players_text.clear();
+        self.players_text
+            .append_string(&format!("Players {}", num_players), &[0.0, 0.7, 1.0]);
+        self.players_text
+            .append_string(" - press ", &[0.0, 0.5, 0.7]);
+        self.players_text.append_string("[1]-[4]", &[0.0, 1.0, 0.7]);
+    }
+
+    /// Shows how good the AI will be
+    pub fn set_difficulty(&mut self, difficulty: Difficulty, catch_up: bool) {
+        self.difficulty_text.clear();
+        self.difficulty_text
+            .append_string(&format!("AI {:<6}", difficulty), &[0.0, 0.7, 1.0]);
+        self.difficulty_text
+            .append_string(" - press ", &[0.0, 0.5, 0.7]);
+        self.difficulty_text.append_string("[V]", &[0.0, 1.0, 0.7]);
+
+        self.catch_up_text.clear();
+        let catch_up = if catch_up { "on " } else { "off" };
+        self.catch_up_text
+            .append_string(&format!("Catch-up {}", catch_up), &[0.0, 0.7, 1.0]);
+        self.catch_up_text
+            .append_string(" - press ", &[0.0, 0.5, 0.7]);
+        self.catch_up_text.append_string("[U]", &[0.0, 1.0, 0.7]);
+    }
+
+    pub fn set_touch_detected(&mut self, touch_detected: bool) {
+        self.touch_detected = touch_detected;
     }
 
     pub fn update(&mut self, dt: f64) {
@@ -31,8 +96,14 @@ impl MainMenu {
         }
 
         self.text.clear();
-        self.text.append_string("Press", &[0.0, 0.7, 1.0]);
-        self.text.append_string(" [ENTER] ", &flash_color);
-        self.text.append_string("to start", &[0.0, 0.7, 1.0]);
+        if self.touch_detected {
+            self.text.append_string("     ", &[0.0, 0.7, 1.0]);
+            self.text.append_string("Tap", &flash_color);
+            self.text.append_string(" to start", &[0.0, 0.7, 1.0]);
+        } else {
+            self.text.append_string("Press", &[0.0, 0.7, 1.0]);
+            self.text.append_string(" [ENTER] ", &flash_color);
+            self.text.append_string("to start", &[0.0, 0.7, 1.0]);
+        }
     }
 }
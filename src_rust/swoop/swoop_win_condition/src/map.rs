@@ -1,17 +1,93 @@
-use js_sys::Math::random;
+use std::fmt;
+use std::str::FromStr;
 
+use super::feature::{Surface, TrackFeature, MAX_FEATURES};
+use super::rng::Rng;
+use super::spline::{Spline, SplineError};
 use super::transform::{length, normalize, PolarCoordinate, Vec2};
 
+/// How many sectors each lap is split into. A lap only counts if the
+/// ship has gone through every sector in order.
+pub const NUM_SECTORS: usize = 8;
+
+/// Increment this if the format of shared maps changes
+const MAP_VERSION: &str = "SWT2";
+
+/// The widest a track can be. Any wider and the edges of most tracks
+/// would overlap.
+pub const MAX_TRACK_WIDTH: f32 = 2.0;
+
+/// The narrowest a track can be anywhere. A ship needs some room to
+/// get around another. This must match MIN_TRACK_WIDTH in map.frag.
+pub const MIN_TRACK_WIDTH: f32 = 0.3;
+
+/// How many waves make up the changes in width of a polar track
+pub const NUM_WIDTH_WAVES: usize = 4;
+
+/// The size of a generated track
+const GENERATED_BASE_RADIUS: f32 = 8.0;
+const GENERATED_TRACK_WIDTH: f32 = 0.7;
+
+/// How many places around a polar track are checked to make sure the
+/// track doesn't cross over the middle of the map. There are eight
+/// waves in a lap, so this is plenty.
+const SHAPE_SAMPLES: usize = 256;
+
+/// Where the start line of a generated track is
+const DEFAULT_START_ANGLE: f32 = std::f32::consts::PI / 2.0;
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Map {
     pub sin_consts: [f32; 8],
     pub cos_consts: [f32; 8],
     pub track_base_radius: f32,
+    /// How far it is from the middle of the track to the edges. On
+    /// tracks where the width changes, this is the width it changes
+    /// from.
     pub track_width: f32,
+
+    /// How the width of a polar track changes around the track. Like
+    /// the shape of the track, this is a sum of sin and cosine waves.
+    /// Spline tracks have a width for each control point instead.
+    pub width_sin_consts: [f32; NUM_WIDTH_WAVES],
+    pub width_cos_consts: [f32; NUM_WIDTH_WAVES],
+
+    /// The angle from the track center of the start line of a polar
+    /// track
+    pub start_angle: f32,
+
+    /// The seed used to generate the sin and cosine constants. Racing
+    /// on a map with the same seed will give the same course.
+    pub seed: u32,
+
+    /// When set, the track follows this spline instead of the polar
+    /// shape made by the sin and cosine constants. Ships race from the
+    /// first control point towards the second.
+    pub spline: Option<Spline>,
+
+    /// Boost pads, sticky zones and jump gates
+    pub features: Vec<TrackFeature>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum MapError {
+    /// The string does not start with the expected version identifier
+    UnsupportedVersion(String),
+    /// A `key=value` pair that isn't part of a map
+    UnknownKey(String),
+    /// A value could not be read
+    InvalidValue(String),
+    /// A value that every map needs is missing
+    MissingValue(&'static str),
+    /// A value can be read but is too big or too small
+    OutOfRange(&'static str),
+    /// The control points don't make a valid spline
+    InvalidSpline(SplineError),
 }
 
 impl Map {
     /// Compute what radius the track has at a given angle from the track
-    /// center.
+    /// center. This is only meaningful for polar tracks.
     pub fn track_radius(&self, angle: f32) -> f32 {
         let mut track_radius = self.track_base_radius;
         for i in 0..8 {
@@ -22,19 +98,60 @@ impl Map {
         track_radius
     }
 
+    /// Whether the inside edge of a polar track stays clear of the
+    /// track center all the way around
+    fn is_polar_shape_valid(&self) -> bool {
+        (0..SHAPE_SAMPLES).all(|i| {
+            let angle = i as f32 / SHAPE_SAMPLES as f32 * std::f32::consts::PI * 2.0;
+            self.track_radius(angle) > self.track_width_at_angle(angle)
+        })
+    }
+
+    /// How wide a polar track is at a given angle from the track center
+    pub fn track_width_at_angle(&self, angle: f32) -> f32 {
+        let mut track_width = self.track_width;
+        for i in 0..NUM_WIDTH_WAVES {
+            let omega = (i + 1) as f32;
+            track_width += f32::sin(angle * omega) * self.width_sin_consts[i];
+            track_width += f32::cos(angle * omega) * self.width_cos_consts[i];
+        }
+        track_width.max(MIN_TRACK_WIDTH)
+    }
+
+    /// How far it is from the middle of the track to the edges at the
+    /// part of the track closest to a position
+    pub fn calc_track_width(&self, position: Vec2) -> f32 {
+        match &self.spline {
+            Some(spline) => {
+                let (_, along) = spline.calc_closest(position);
+                self.spline_track_width(spline, along)
+            }
+            None => self.track_width_at_angle(position.1.atan2(position.0)),
+        }
+    }
+
+    fn spline_track_width(&self, spline: &Spline, along: f32) -> f32 {
+        (self.track_width + spline.get_width(along)).max(MIN_TRACK_WIDTH)
+    }
+
     /// Computes the distance from the edge of the track for a given
     /// Cartesian coordinate. This can be used to check if a coordinate
     /// is inside or outside the track, and is negative inside the track
     /// and positive outside the track.
     pub fn distance_field(&self, position: Vec2) -> f32 {
+        if let Some(spline) = &self.spline {
+            let (distance, along) = spline.calc_closest(position);
+            return distance - self.spline_track_width(spline, along);
+        }
+
         let course = length(&position);
         let angle = position.1.atan2(position.0);
 
         let track_radius = self.track_radius(angle);
 
         let mut track_sdf = course - track_radius;
-        track_sdf = f32::abs(track_sdf) - self.track_width;
-        return track_sdf;
+        track_sdf = f32::abs(track_sdf) - self.track_width_at_angle(angle);
+        track_sdf
     }
 
     /// Uses finite difference to approximate the direction onto the
@@ -49,19 +166,91 @@ impl Map {
         let dx = right - here;
         let dy = above - here;
 
-        return normalize((dx, dy));
+        normalize((dx, dy))
     }
 
-    /// Figure out where the start line should be located. This is
-    /// represented as a polar coordinate from the center of the track.
+    /// Figure out where the start line of a polar track should be
+    /// located. This is represented as a polar coordinate from the
+    /// center of the track.
     pub fn get_start_position(&self) -> PolarCoordinate {
-        const ANGLE: f32 = std::f32::consts::PI / 2.0;
         PolarCoordinate {
-            angle: ANGLE,
-            radius: self.track_radius(ANGLE),
+            angle: self.start_angle,
+            radius: self.track_radius(self.start_angle),
+        }
+    }
+
+    /// Where the middle of the start line is, and the angle (like a
+    /// ship's rotation) that points along the track from it
+    pub fn get_start_line(&self) -> (Vec2, f32) {
+        match &self.spline {
+            Some(spline) => {
+                let (position, direction) = spline.get_point(0.0);
+                (position, f32::atan2(-direction.0, direction.1))
+            }
+            None => {
+                let start_position = self.get_start_position();
+                (
+                    start_position.to_cartesian(),
+                    self.get_track_direction(start_position.angle),
+                )
+            }
+        }
+    }
+
+    /// A point on the middle of the track, `fraction` of a lap from
+    /// the start line, and the direction across the track that points
+    /// away from the track center (for polar tracks) or to the left
+    /// (for spline tracks). Moving from the point in that direction
+    /// changes how far from the edges of the track the position is,
+    /// without changing its lap fraction much.
+    pub fn get_track_point(&self, fraction: f32) -> (Vec2, Vec2) {
+        match &self.spline {
+            Some(spline) => {
+                let (position, direction) = spline.get_point(fraction * spline.get_length());
+                (position, (-direction.1, direction.0))
+            }
+            None => {
+                let angle = self.get_start_position().angle - fraction * std::f32::consts::PI * 2.0;
+                let across = (f32::cos(angle), f32::sin(angle));
+                let radius = self.track_radius(angle);
+                ((across.0 * radius, across.1 * radius), across)
+            }
         }
     }
 
+    /// Where the middle of a feature is and the direction along the
+    /// track there
+    pub fn get_feature_frame(&self, feature: &TrackFeature) -> (Vec2, Vec2) {
+        const DELTA_FRACTION: f32 = 0.001;
+        let (center, across) = self.get_track_point(feature.fraction);
+        let (before, _) = self.get_track_point((feature.fraction - DELTA_FRACTION).rem_euclid(1.0));
+        let (after, _) = self.get_track_point((feature.fraction + DELTA_FRACTION) % 1.0);
+        (
+            (
+                center.0 + across.0 * feature.offset,
+                center.1 + across.1 * feature.offset,
+            ),
+            normalize((after.0 - before.0, after.1 - before.1)),
+        )
+    }
+
+    /// How the features of the track affect a ship at a position
+    pub fn get_surface(&self, position: Vec2) -> Surface {
+        // Away from the features the track makes no difference
+        let mut surface = Surface {
+            acceleration: (0.0, 0.0),
+            damping: 0.0,
+            launch: false,
+        };
+        for feature in self.features.iter() {
+            let (center, forwards) = self.get_feature_frame(feature);
+            if feature.contains(center, forwards, position) {
+                surface.add_feature(feature.kind, forwards);
+            }
+        }
+        surface
+    }
+
     /// Returns the angle pointing along the track at a particular
     /// polar/angular coordinate along the track
     pub fn get_track_direction(&self, angle: f32) -> f32 {
@@ -76,7 +265,7 @@ impl Map {
 
         // Use sin rule to find the angle of the chord and radius_here
         let ratio = radius_here / joining_side_length * f32::sin(DELTA_ANGLE);
-        let ratio = f32::max(f32::min(ratio, 1.0), -1.0); // Floating point precision
+        let ratio = ratio.clamp(-1.0, 1.0); // Floating point precision
         let extra_angle = f32::asin(ratio);
 
         if delta_radius.is_sign_negative() {
@@ -86,12 +275,24 @@ impl Map {
         }
     }
 
-    /// Change the sin and cosine constants to change the map course
-    pub fn randomize(&mut self) {
+    /// Change the sin and cosine constants to change the map course.
+    /// The same seed will always generate the same course.
+    pub fn randomize(&mut self, seed: u32) {
         const WAVINESS: f32 = 3.0;
+        let mut rng = Rng::new(seed);
+        self.seed = seed;
+        // The map may have been loaded from a shared track before, so
+        // put back everything that isn't random
+        self.track_base_radius = GENERATED_BASE_RADIUS;
+        self.track_width = GENERATED_TRACK_WIDTH;
+        self.width_sin_consts = [0.0; NUM_WIDTH_WAVES];
+        self.width_cos_consts = [0.0; NUM_WIDTH_WAVES];
+        self.start_angle = DEFAULT_START_ANGLE;
+        self.spline = None;
+        self.features.clear();
         for i in 0..8 {
-            let rand1 = (random() as f32 - 0.5) * 2.0;
-            let rand2 = (random() as f32 - 0.5) * 2.0;
+            let rand1 = (rng.next_f32() - 0.5) * 2.0;
+            let rand2 = (rng.next_f32() - 0.5) * 2.0;
             let amplitude = WAVINESS / f32::powf((i + 1) as f32, 1.3);
 
             self.sin_consts[i] = rand1 * amplitude;
@@ -99,15 +300,45 @@ impl Map {
         }
     }
 
+    /// How far around the track a position is, from 0.0 at the start
+    /// line to nearly 1.0 just before it. On polar tracks this is
+    /// measured using the angle from the center of the track, and ships
+    /// race clockwise. On spline tracks it is how far along the spline
+    /// the closest point is.
+    pub fn calc_lap_fraction(&self, position: Vec2) -> f32 {
+        if let Some(spline) = &self.spline {
+            // The end of the last piece is the start line again
+            let (_, along) = spline.calc_closest(position);
+            return (along / spline.get_length()) % 1.0;
+        }
+
+        const TAU: f32 = std::f32::consts::PI * 2.0;
+        let start_angle = self.get_start_position().angle;
+        let angle = position.1.atan2(position.0);
+        let fraction = (start_angle - angle).rem_euclid(TAU) / TAU;
+        // rem_euclid can round up to TAU itself
+        if fraction >= 1.0 {
+            0.0
+        } else {
+            fraction
+        }
+    }
+
+    /// Which sector of the track a position is in. The sectors are
+    /// evenly spaced lap fractions around the track, with sector 0
+    /// starting at the start line. The boundaries between the sectors
+    /// are the checkpoints.
+    pub fn get_sector(&self, position: Vec2) -> usize {
+        let sector = (self.calc_lap_fraction(position) * NUM_SECTORS as f32) as usize;
+        sector.min(NUM_SECTORS - 1)
+    }
+
     // Returns a value that can be used to check if the player is across the start line
     // the returned value has the property of switching from 1.0 to 0.0 when the player
     // crosses the start line. When the passed in position is a long way from the start
     // line the value is meaningless but will not be near 1.0 or 0.0.
     pub fn calc_progress_relative_to_startline(&self, position: Vec2) -> f32 {
-        let start_position = self.get_start_position();
-        let start_line_direction = self.get_track_direction(start_position.angle);
-
-        let start_position_cartesian = start_position.to_cartesian();
+        let (start_position_cartesian, start_line_direction) = self.get_start_line();
 
         let distance_from_startline = (
             position.0 - start_position_cartesian.0,
@@ -122,7 +353,7 @@ impl Map {
             s * distance_from_startline.0 + c * distance_from_startline.1,
         );
 
-        if f32::abs(position_local.0) > self.track_width {
+        if f32::abs(position_local.0) > self.calc_track_width(start_position_cartesian) {
             // Position is off to the side of the track
             0.5
         } else {
@@ -154,3 +385,215 @@ impl Map {
 pub fn cosine_rule(a: f32, b: f32, angle: f32) -> f32 {
     f32::sqrt(a * a + b * b - 2.0 * a * b * f32::cos(angle))
 }
+
+/// Maps are shared as the version followed by `;` separated
+/// `key=value` pairs, so that they contain no spaces and can be put in
+/// the canvas options. A polar track lists its waves, eg
+/// `SWT2;width=0.7;start=1.5707964;radius=8;sin=2,0,0,0,0,0,0,0;cos=0,-2,0,1,0,0,0.5,0`
+/// and a spline track lists its control points, eg
+/// `SWT2;width=0.7;spline=0,9/6,7.5/9,2/8,-5`.
+/// Tracks that change width also have `width_sin` and `width_cos`
+/// waves, or `widths` for each control point. Tracks with features
+/// list them last, eg `features=boost,0.25,0,1.5,0.3/jump,0.5,0,0.3,1`
+/// (see `TrackFeature`).
+impl fmt::Display for Map {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let join = |values: &[f32]| {
+            values
+                .iter()
+                .map(|value| value.to_string())
+                .collect::<Vec<String>>()
+                .join(",")
+        };
+
+        write!(f, "{};width={}", MAP_VERSION, self.track_width)?;
+        match &self.spline {
+            Some(spline) => {
+                let points: Vec<String> = spline
+                    .get_points()
+                    .iter()
+                    .map(|point| join(&[point.0, point.1]))
+                    .collect();
+                write!(f, ";spline={}", points.join("/"))?;
+                if spline.get_widths().iter().any(|width| *width != 0.0) {
+                    write!(f, ";widths={}", join(spline.get_widths()))?;
+                }
+            }
+            None => {
+                write!(
+                    f,
+                    ";start={};radius={};sin={};cos={}",
+                    self.start_angle,
+                    self.track_base_radius,
+                    join(&self.sin_consts),
+                    join(&self.cos_consts)
+                )?;
+                let width_consts = self.width_sin_consts.iter().chain(&self.width_cos_consts);
+                if width_consts.clone().any(|width| *width != 0.0) {
+                    write!(
+                        f,
+                        ";width_sin={};width_cos={}",
+                        join(&self.width_sin_consts),
+                        join(&self.width_cos_consts)
+                    )?;
+                }
+            }
+        }
+        if !self.features.is_empty() {
+            let features: Vec<String> = self
+                .features
+                .iter()
+                .map(|feature| feature.to_string())
+                .collect();
+            write!(f, ";features={}", features.join("/"))?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Map {
+    type Err = MapError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut pairs = s.trim().split(';');
+        let version = pairs.next().unwrap_or("");
+        if version != MAP_VERSION {
+            return Err(MapError::UnsupportedVersion(version.to_string()));
+        }
+
+        let mut width = None;
+        let mut start_angle = DEFAULT_START_ANGLE;
+        let mut radius = None;
+        let mut sin_consts = None;
+        let mut cos_consts = None;
+        let mut width_sin_consts = [0.0; NUM_WIDTH_WAVES];
+        let mut width_cos_consts = [0.0; NUM_WIDTH_WAVES];
+        let mut points = None;
+        let mut widths = None;
+        let mut features = vec![];
+        for pair in pairs.filter(|pair| !pair.is_empty()) {
+            let mut parts = pair.splitn(2, '=');
+            let key = parts.next().unwrap_or("");
+            let value = parts.next().unwrap_or("");
+            let invalid = || MapError::InvalidValue(pair.to_string());
+            let parse_value = |value: &str| match value.parse::<f32>() {
+                Ok(value) if value.is_finite() => Ok(value),
+                _ => Err(invalid()),
+            };
+            let parse_list = |list: &str| {
+                list.split(',')
+                    .map(parse_value)
+                    .collect::<Result<Vec<f32>, MapError>>()
+            };
+            let parse_width_consts = |list: &str| {
+                let mut consts = [0.0; NUM_WIDTH_WAVES];
+                let values = parse_list(list)?;
+                if values.len() != consts.len() {
+                    return Err(invalid());
+                }
+                consts.copy_from_slice(&values);
+                Ok(consts)
+            };
+            let parse_consts = |list: &str| {
+                let mut consts = [0.0; 8];
+                let values = parse_list(list)?;
+                if values.len() != consts.len() {
+                    return Err(invalid());
+                }
+                consts.copy_from_slice(&values);
+                Ok(consts)
+            };
+
+            match key {
+                "width" => width = Some(parse_value(value)?),
+                "start" => start_angle = parse_value(value)?,
+                "radius" => radius = Some(parse_value(value)?),
+                "sin" => sin_consts = Some(parse_consts(value)?),
+                "cos" => cos_consts = Some(parse_consts(value)?),
+                "width_sin" => width_sin_consts = parse_width_consts(value)?,
+                "width_cos" => width_cos_consts = parse_width_consts(value)?,
+                "spline" => {
+                    points = Some(
+                        value
+                            .split('/')
+                            .map(|point| match parse_list(point)?.as_slice() {
+                                [x, y] => Ok((*x, *y)),
+                                _ => Err(invalid()),
+                            })
+                            .collect::<Result<Vec<Vec2>, MapError>>()?,
+                    );
+                }
+                "widths" => widths = Some(parse_list(value)?),
+                "features" => {
+                    features = value
+                        .split('/')
+                        .map(|feature| feature.parse().map_err(|_| invalid()))
+                        .collect::<Result<Vec<TrackFeature>, MapError>>()?;
+                }
+                _ => return Err(MapError::UnknownKey(pair.to_string())),
+            }
+        }
+
+        let spline = match (points, widths) {
+            (Some(points), Some(widths)) => Some(Spline::with_widths(points, widths)),
+            (Some(points), None) => Some(Spline::new(points)),
+            (None, Some(_)) => return Err(MapError::MissingValue("spline")),
+            (None, None) => None,
+        }
+        .transpose()
+        .map_err(MapError::InvalidSpline)?;
+
+        // Spline tracks don't need the waves of a polar track
+        let polar_value = |value: Option<[f32; 8]>| match spline {
+            Some(_) => Ok(value.unwrap_or([0.0; 8])),
+            None => value.ok_or(MapError::MissingValue("sin and cos")),
+        };
+        let track_base_radius = match spline {
+            Some(_) => radius.unwrap_or(0.0),
+            None => radius.ok_or(MapError::MissingValue("radius"))?,
+        };
+        if spline.is_none() && track_base_radius <= 0.0 {
+            return Err(MapError::OutOfRange("radius"));
+        }
+        let track_width = width.ok_or(MapError::MissingValue("width"))?;
+        if !(MIN_TRACK_WIDTH..=MAX_TRACK_WIDTH).contains(&track_width) {
+            return Err(MapError::OutOfRange("width"));
+        }
+        // The width can dip below the minimum (it gets clamped) but it
+        // can't get any wider than a track is allowed to be
+        let widest = match &spline {
+            Some(spline) => spline.get_widths().iter().fold(0.0, |a, b| f32::max(a, *b)),
+            None => width_sin_consts
+                .iter()
+                .chain(&width_cos_consts)
+                .map(|value| value.abs())
+                .sum(),
+        };
+        if track_width + widest > MAX_TRACK_WIDTH {
+            return Err(MapError::OutOfRange("widths"));
+        }
+        if features.len() > MAX_FEATURES {
+            return Err(MapError::OutOfRange("features"));
+        }
+
+        let map = Self {
+            sin_consts: polar_value(sin_consts)?,
+            cos_consts: polar_value(cos_consts)?,
+            track_base_radius,
+            track_width,
+            width_sin_consts,
+            width_cos_consts,
+            start_angle,
+            seed: 0,
+            spline,
+            features,
+        };
+        // If the waves are too big for the radius, the inside edge of
+        // the track goes past the middle of the map and the distance
+        // field and lap progress stop making sense
+        if map.spline.is_none() && !map.is_polar_shape_valid() {
+            return Err(MapError::OutOfRange("sin and cos"));
+        }
+        Ok(map)
+    }
+}
@@ -1,7 +1,9 @@
 use web_sys::{WebGl2RenderingContext, WebGlBuffer, WebGlProgram, WebGlUniformLocation};
 
+use super::feature::{FeatureKind, MAX_FEATURES};
 use super::map::Map;
 use super::shader::{init_shader_program, upload_array_f32, ShaderError};
+use super::spline::MAX_SPLINE_POINTS;
 
 pub struct MapSprite {
     position_buffer: WebGlBuffer,
@@ -16,9 +18,20 @@ pub struct MapSprite {
     uniform_cos_consts: Option<WebGlUniformLocation>,
     uniform_track_base_radius: Option<WebGlUniformLocation>,
     uniform_track_width: Option<WebGlUniformLocation>,
+    uniform_width_sin_consts: Option<WebGlUniformLocation>,
+    uniform_width_cos_consts: Option<WebGlUniformLocation>,
+    uniform_num_spline_points: Option<WebGlUniformLocation>,
+    uniform_spline_points: Option<WebGlUniformLocation>,
+    uniform_spline_widths: Option<WebGlUniformLocation>,
 
     uniform_start_line_position: Option<WebGlUniformLocation>,
     uniform_start_line_tangent: Option<WebGlUniformLocation>,
+    uniform_start_line_width: Option<WebGlUniformLocation>,
+
+    uniform_num_features: Option<WebGlUniformLocation>,
+    uniform_feature_frames: Option<WebGlUniformLocation>,
+    uniform_feature_sizes: Option<WebGlUniformLocation>,
+    uniform_feature_kinds: Option<WebGlUniformLocation>,
 
     pub world_to_camera: [f32; 9],
     pub world_to_sprite: [f32; 9],
@@ -51,8 +64,18 @@ impl MapSprite {
         let uniform_cos_consts = gl.get_uniform_location(&program, "cos_consts");
         let uniform_track_base_radius = gl.get_uniform_location(&program, "track_base_radius");
         let uniform_track_width = gl.get_uniform_location(&program, "track_width");
+        let uniform_width_sin_consts = gl.get_uniform_location(&program, "width_sin_consts");
+        let uniform_width_cos_consts = gl.get_uniform_location(&program, "width_cos_consts");
+        let uniform_num_spline_points = gl.get_uniform_location(&program, "num_spline_points");
+        let uniform_spline_points = gl.get_uniform_location(&program, "spline_points");
+        let uniform_spline_widths = gl.get_uniform_location(&program, "spline_widths");
         let uniform_start_line_tangent = gl.get_uniform_location(&program, "start_line_tangent");
         let uniform_start_line_position = gl.get_uniform_location(&program, "start_line_position");
+        let uniform_start_line_width = gl.get_uniform_location(&program, "start_line_width");
+        let uniform_num_features = gl.get_uniform_location(&program, "num_features");
+        let uniform_feature_frames = gl.get_uniform_location(&program, "feature_frames");
+        let uniform_feature_sizes = gl.get_uniform_location(&program, "feature_sizes");
+        let uniform_feature_kinds = gl.get_uniform_location(&program, "feature_kinds");
 
         Ok(Self {
             position_buffer,
@@ -67,8 +90,18 @@ impl MapSprite {
             uniform_cos_consts,
             uniform_track_base_radius,
             uniform_track_width,
+            uniform_width_sin_consts,
+            uniform_width_cos_consts,
+            uniform_num_spline_points,
+            uniform_spline_points,
+            uniform_spline_widths,
             uniform_start_line_tangent,
             uniform_start_line_position,
+            uniform_start_line_width,
+            uniform_num_features,
+            uniform_feature_frames,
+            uniform_feature_sizes,
+            uniform_feature_kinds,
 
             world_to_camera: [0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
             world_to_sprite: [0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
@@ -87,12 +120,38 @@ impl MapSprite {
             map.track_base_radius,
         );
         gl.uniform1f(self.uniform_track_width.as_ref(), map.track_width);
+        gl.uniform4fv_with_f32_array(
+            self.uniform_width_sin_consts.as_ref(),
+            &map.width_sin_consts,
+        );
+        gl.uniform4fv_with_f32_array(
+            self.uniform_width_cos_consts.as_ref(),
+            &map.width_cos_consts,
+        );
 
-        let start_position = map.get_start_position();
-        let start_angle = map.get_track_direction(start_position.angle);
-        let start_tangent = (f32::cos(start_angle), f32::sin(start_angle));
+        // The shader always reads the whole array, so pad it out
+        let mut spline_points = [0.0; MAX_SPLINE_POINTS * 2];
+        let mut spline_widths = [0.0; MAX_SPLINE_POINTS];
+        let num_spline_points = match &map.spline {
+            Some(spline) => {
+                for (i, point) in spline.get_points().iter().enumerate() {
+                    spline_points[i * 2] = point.0;
+                    spline_points[i * 2 + 1] = point.1;
+                }
+                spline_widths[..spline.get_widths().len()].copy_from_slice(spline.get_widths());
+                spline.get_points().len()
+            }
+            None => 0,
+        };
+        gl.uniform1i(
+            self.uniform_num_spline_points.as_ref(),
+            num_spline_points as i32,
+        );
+        gl.uniform2fv_with_f32_array(self.uniform_spline_points.as_ref(), &spline_points);
+        gl.uniform1fv_with_f32_array(self.uniform_spline_widths.as_ref(), &spline_widths);
 
-        let start_position_cartesian = start_position.to_cartesian();
+        let (start_position_cartesian, start_angle) = map.get_start_line();
+        let start_tangent = (f32::cos(start_angle), f32::sin(start_angle));
 
         gl.uniform2f(
             self.uniform_start_line_position.as_ref(),
@@ -104,6 +163,34 @@ impl MapSprite {
             start_tangent.0,
             start_tangent.1,
         );
+        gl.uniform1f(
+            self.uniform_start_line_width.as_ref(),
+            map.calc_track_width(start_position_cartesian),
+        );
+
+        let mut feature_frames = [0.0; MAX_FEATURES * 4];
+        let mut feature_sizes = [0.0; MAX_FEATURES * 2];
+        let mut feature_kinds = [0; MAX_FEATURES];
+        for (i, feature) in map.features.iter().enumerate().take(MAX_FEATURES) {
+            let (center, forwards) = map.get_feature_frame(feature);
+            feature_frames[i * 4..i * 4 + 4]
+                .copy_from_slice(&[center.0, center.1, forwards.0, forwards.1]);
+            feature_sizes[i * 2] = feature.width;
+            feature_sizes[i * 2 + 1] = feature.length * 0.5;
+            // These must match FEATURE_BOOST etc. in map.frag
+            feature_kinds[i] = match feature.kind {
+                FeatureKind::Boost => 0,
+                FeatureKind::Sticky => 1,
+                FeatureKind::Jump => 2,
+            };
+        }
+        gl.uniform1i(
+            self.uniform_num_features.as_ref(),
+            map.features.len().min(MAX_FEATURES) as i32,
+        );
+        gl.uniform4fv_with_f32_array(self.uniform_feature_frames.as_ref(), &feature_frames);
+        gl.uniform2fv_with_f32_array(self.uniform_feature_sizes.as_ref(), &feature_sizes);
+        gl.uniform1iv_with_i32_array(self.uniform_feature_kinds.as_ref(), &feature_kinds);
     }
 
     /// Render the map sprite.
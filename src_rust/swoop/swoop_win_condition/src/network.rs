@@ -0,0 +1,283 @@
+//! The messages sent between swoop and the relay server in an online
+//! race. Every message is a single line of text (a WebSocket text
+//! frame) made of space separated fields, the first of which says what
+//! sort of message it is.
+use std::fmt;
+use std::str::FromStr;
+
+use super::gameplay::GamePlay;
+use super::rng::{from_track_code, to_track_code};
+use super::transform::Transform2d;
+
+/// The server sends a snapshot of the race every this many simulation
+/// steps (30 times a second).
+pub const SNAPSHOT_INTERVAL: u64 = 4;
+
+/// Messages sent from a player to the server
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClientMessage {
+    /// Join (or create) a room. The race in the room starts once
+    /// `players` people have joined. eg `JOIN friday 2`
+    Join { room: String, players: usize },
+
+    /// The keys the player is holding (see `ShipKeys::to_bits`) as of
+    /// the player's simulation step `tick`. eg `INPUT 1200 5`
+    Input { tick: u64, input: u8 },
+}
+
+/// Messages sent from the server to the players in a room
+#[derive(Debug, Clone)]
+pub enum ServerMessage {
+    /// The room is waiting for more players. eg `WAIT 1 2`
+    Waiting { joined: usize, players: usize },
+
+    /// The race has started on the track with this seed. The player
+    /// receiving the message drives the ship `player_id`.
+    /// eg `START 0ZK3M7Q 1 2`
+    Start {
+        seed: u32,
+        player_id: usize,
+        num_players: usize,
+    },
+
+    /// The state of the race according to the server
+    Snapshot(Snapshot),
+
+    /// The room the player asked to join is for a different number of
+    /// players. The server closes the connection after sending this.
+    /// eg `REJECT 3`
+    Rejected { players: usize },
+}
+
+/// The state of a single ship in a snapshot
+#[derive(Debug, Clone)]
+pub struct ShipState {
+    pub position: Transform2d,
+    pub velocity: Transform2d,
+    /// How much longer the ship is in the air (see `Ship::airborne`)
+    pub airborne: f32,
+    /// The times the ship crossed the start line (see `Score::laps`)
+    pub laps: Vec<f64>,
+}
+
+/// Everything needed to put a race back into the state the server had
+/// at the end of a simulation step.
+/// It is sent as `SNAP <tick> <game duration> <inputs> <ships>` where
+/// each ship is `x,y,rot,vx,vy,vrot,airborne/<laps>`
+/// eg `SNAP 960 4 1,0 0.5,-7.2,1.57,0,3.1,0,0/3.2 0.6,-7.4,1.57,0,2.9,0,0.3/`
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    pub tick: u64,
+    pub game_duration: f64,
+    /// The input the server used for each player on this step
+    pub inputs: Vec<u8>,
+    pub ships: Vec<ShipState>,
+}
+
+#[derive(Debug)]
+pub enum NetworkError {
+    /// The first field of the message is not a known message type
+    UnknownMessage(String),
+    /// The message has the wrong number of fields or a field that
+    /// could not be read
+    InvalidMessage(String),
+}
+
+impl Snapshot {
+    /// Takes a snapshot of the race at the end of the most recent step
+    pub fn capture(gameplay: &GamePlay) -> Self {
+        let inputs = (0..gameplay.num_players)
+            .map(|player| {
+                gameplay
+                    .recording
+                    .get_input(player, gameplay.tick.saturating_sub(1))
+            })
+            .collect();
+
+        let ships = gameplay
+            .ship_entities
+            .iter()
+            .zip(gameplay.scores.iter())
+            .map(|(ship, score)| ShipState {
+                position: ship.position,
+                velocity: ship.velocity,
+                airborne: ship.airborne,
+                laps: score.laps.clone(),
+            })
+            .collect();
+
+        Self {
+            tick: gameplay.tick,
+            game_duration: gameplay.game_duration,
+            inputs,
+            ships,
+        }
+    }
+
+    /// Puts the race into the state in the snapshot. Ships that are not
+    /// in the snapshot are left where they are.
+    pub fn restore(&self, gameplay: &mut GamePlay) {
+        gameplay.tick = self.tick;
+        gameplay.game_duration = self.game_duration;
+
+        for (id, state) in self.ships.iter().enumerate() {
+            if let (Some(ship), Some(score)) = (
+                gameplay.ship_entities.get_mut(id),
+                gameplay.scores.get_mut(id),
+            ) {
+                ship.position.x = state.position.x;
+                ship.position.y = state.position.y;
+                ship.position.rot = state.position.rot;
+                ship.prev_position = ship.position;
+                ship.velocity = state.velocity;
+                ship.airborne = state.airborne;
+                score.restore(&gameplay.map, ship, &state.laps);
+            }
+        }
+    }
+}
+
+impl fmt::Display for ClientMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientMessage::Join { room, players } => write!(f, "JOIN {} {}", room, players),
+            ClientMessage::Input { tick, input } => write!(f, "INPUT {} {}", tick, input),
+        }
+    }
+}
+
+impl FromStr for ClientMessage {
+    type Err = NetworkError;
+
+    fn from_str(message: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<&str> = message.split_whitespace().collect();
+        let invalid = || NetworkError::InvalidMessage(message.to_string());
+
+        match fields.as_slice() {
+            ["JOIN", room, players] => Ok(ClientMessage::Join {
+                room: room.to_string(),
+                players: players.parse().map_err(|_| invalid())?,
+            }),
+            ["INPUT", tick, input] => Ok(ClientMessage::Input {
+                tick: tick.parse().map_err(|_| invalid())?,
+                input: input.parse().map_err(|_| invalid())?,
+            }),
+            ["JOIN", ..] | ["INPUT", ..] => Err(invalid()),
+            _ => Err(NetworkError::UnknownMessage(message.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for ServerMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ServerMessage::Waiting { joined, players } => {
+                write!(f, "WAIT {} {}", joined, players)
+            }
+            ServerMessage::Start {
+                seed,
+                player_id,
+                num_players,
+            } => write!(
+                f,
+                "START {} {} {}",
+                to_track_code(*seed),
+                player_id,
+                num_players
+            ),
+            ServerMessage::Snapshot(snapshot) => {
+                let inputs: Vec<String> = snapshot.inputs.iter().map(|i| i.to_string()).collect();
+                write!(
+                    f,
+                    "SNAP {} {} {}",
+                    snapshot.tick,
+                    snapshot.game_duration,
+                    inputs.join(",")
+                )?;
+                for ship in snapshot.ships.iter() {
+                    let laps: Vec<String> = ship.laps.iter().map(|l| l.to_string()).collect();
+                    write!(
+                        f,
+                        " {},{},{},{},{},{},{}/{}",
+                        ship.position.x,
+                        ship.position.y,
+                        ship.position.rot,
+                        ship.velocity.x,
+                        ship.velocity.y,
+                        ship.velocity.rot,
+                        ship.airborne,
+                        laps.join(",")
+                    )?;
+                }
+                Ok(())
+            }
+            ServerMessage::Rejected { players } => write!(f, "REJECT {}", players),
+        }
+    }
+}
+
+impl FromStr for ServerMessage {
+    type Err = NetworkError;
+
+    fn from_str(message: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<&str> = message.split_whitespace().collect();
+        let invalid = || NetworkError::InvalidMessage(message.to_string());
+
+        match fields.as_slice() {
+            ["WAIT", joined, players] => Ok(ServerMessage::Waiting {
+                joined: joined.parse().map_err(|_| invalid())?,
+                players: players.parse().map_err(|_| invalid())?,
+            }),
+            ["START", track_code, player_id, num_players] => Ok(ServerMessage::Start {
+                seed: from_track_code(track_code).ok_or_else(invalid)?,
+                player_id: player_id.parse().map_err(|_| invalid())?,
+                num_players: num_players.parse().map_err(|_| invalid())?,
+            }),
+            ["SNAP", tick, game_duration, inputs, ships @ ..] => {
+                let inputs = parse_list(inputs).ok_or_else(invalid)?;
+                let ships = ships
+                    .iter()
+                    .map(|ship| parse_ship_state(ship))
+                    .collect::<Option<Vec<ShipState>>>()
+                    .ok_or_else(invalid)?;
+
+                Ok(ServerMessage::Snapshot(Snapshot {
+                    tick: tick.parse().map_err(|_| invalid())?,
+                    game_duration: game_duration.parse().map_err(|_| invalid())?,
+                    inputs,
+                    ships,
+                }))
+            }
+            ["REJECT", players] => Ok(ServerMessage::Rejected {
+                players: players.parse().map_err(|_| invalid())?,
+            }),
+            ["WAIT", ..] | ["START", ..] | ["SNAP", ..] | ["REJECT", ..] => Err(invalid()),
+            _ => Err(NetworkError::UnknownMessage(message.to_string())),
+        }
+    }
+}
+
+/// Parses a comma separated list. An empty string is an empty list.
+fn parse_list<T: FromStr>(list: &str) -> Option<Vec<T>> {
+    if list.is_empty() {
+        return Some(vec![]);
+    }
+    list.split(',').map(|item| item.parse().ok()).collect()
+}
+
+/// Parses a ship in the format `x,y,rot,vx,vy,vrot,airborne/lap,lap`
+fn parse_ship_state(ship: &str) -> Option<ShipState> {
+    let mut parts = ship.splitn(2, '/');
+    let motion: Vec<f32> = parse_list(parts.next()?)?;
+    let laps = parse_list(parts.next()?)?;
+
+    match motion.as_slice() {
+        [x, y, rot, vx, vy, vrot, airborne] => Some(ShipState {
+            position: Transform2d::new(*x, *y, *rot, 0.1),
+            velocity: Transform2d::new(*vx, *vy, *vrot, 0.0),
+            airborne: *airborne,
+            laps,
+        }),
+        _ => None,
+    }
+}
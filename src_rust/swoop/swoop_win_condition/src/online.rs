@@ -0,0 +1,263 @@
+//! The client side of an online race. The server runs the race and
+//! sends out snapshots of it. Between snapshots the race is predicted
+//! locally so that the player's ship responds to the keyboard straight
+//! away, and any corrections from the server are smoothed out.
+use std::f32::consts::PI;
+
+use super::gameplay::GamePlay;
+use super::keymap::{KeyMap, ShipKeys};
+use super::network::{ClientMessage, NetworkError, ServerMessage, Snapshot};
+use super::ship::Ship;
+use super::text_box::TextBox;
+use super::transform::Transform2d;
+
+/// How quickly (in seconds) a correction from the server fades out.
+/// Shorter is more accurate, longer hides jitter better.
+const SMOOTHING_TIME: f32 = 0.1;
+
+/// If the server is this many steps ahead of us, give up on predicting
+/// and jump straight to the server's state.
+const MAX_RESIMULATE_STEPS: u64 = 120;
+
+pub enum OnlineState {
+    /// Waiting for enough players to join the room
+    Waiting {
+        joined: usize,
+        players: usize,
+    },
+    Racing,
+    /// The room is for a different number of players
+    Rejected {
+        players: usize,
+    },
+}
+
+pub struct OnlineRace {
+    pub state: OnlineState,
+
+    /// Messages that need to be sent to the server
+    pub outbox: Vec<ClientMessage>,
+
+    /// The most recent snapshot received from the server
+    pub latest_snapshot: Option<Snapshot>,
+
+    pub waiting_text: TextBox,
+
+    /// How far each ship is drawn from where the simulation says it is.
+    /// When the server corrects a ship's position this makes the ship
+    /// glide to its new position rather than jumping there.
+    render_offsets: Vec<Transform2d>,
+
+    /// The most recent input sent to the server
+    sent_input: Option<u8>,
+}
+
+impl OnlineRace {
+    /// Creates a race that will join `room` and start when `players`
+    /// people are in it.
+    pub fn new(room: &str, players: usize) -> Self {
+        let mut online_race = Self {
+            state: OnlineState::Waiting { joined: 0, players },
+            outbox: vec![ClientMessage::Join {
+                room: room.to_string(),
+                players,
+            }],
+            latest_snapshot: None,
+            waiting_text: TextBox::new((24, 1), 0.05, (0.0, 0.0)),
+            render_offsets: vec![],
+            sent_input: None,
+        };
+        online_race.update_waiting_text();
+        online_race
+    }
+
+    /// Handles a message from the server
+    pub fn receive(&mut self, gameplay: &mut GamePlay, message: &str) -> Result<(), NetworkError> {
+        match message.parse()? {
+            ServerMessage::Waiting { joined, players } => {
+                self.state = OnlineState::Waiting { joined, players };
+                self.update_waiting_text();
+            }
+            ServerMessage::Start {
+                seed,
+                player_id,
+                num_players,
+            } => {
+                gameplay.start_online(seed, num_players, player_id);
+                self.render_offsets =
+                    vec![Transform2d::new(0.0, 0.0, 0.0, 0.0); gameplay.ship_entities.len()];
+                self.latest_snapshot = None;
+                self.sent_input = None;
+                self.state = OnlineState::Racing;
+            }
+            ServerMessage::Snapshot(snapshot) => {
+                if let OnlineState::Racing = self.state {
+                    self.apply_snapshot(gameplay, &snapshot);
+                }
+                self.latest_snapshot = Some(snapshot);
+            }
+            ServerMessage::Rejected { players } => {
+                self.state = OnlineState::Rejected { players };
+                self.update_waiting_text();
+            }
+        }
+        Ok(())
+    }
+
+    /// Advance the race by a frame lasting `dt` seconds, predicting
+    /// what the server will do. Any change in the player's input is
+    /// queued to be sent to the server.
+    pub fn update(&mut self, gameplay: &mut GamePlay, dt: f64, key_map: &KeyMap) {
+        if !matches!(self.state, OnlineState::Racing) {
+            return;
+        }
+        gameplay.update(dt, key_map);
+
+        let input = key_map.players[0].to_bits();
+        if self.sent_input != Some(input) {
+            self.outbox.push(ClientMessage::Input {
+                tick: gameplay.tick,
+                input,
+            });
+            self.sent_input = Some(input);
+        }
+
+        let decay = f32::exp(-dt as f32 / SMOOTHING_TIME);
+        for offset in self.render_offsets.iter_mut() {
+            offset.x *= decay;
+            offset.y *= decay;
+            offset.rot *= decay;
+        }
+    }
+
+    /// Takes the messages that are waiting to be sent to the server
+    pub fn take_outbox(&mut self) -> Vec<ClientMessage> {
+        std::mem::take(&mut self.outbox)
+    }
+
+    /// The ships as they should be drawn, including the smoothing of
+    /// any corrections from the server.
+    pub fn get_render_ships(&self, gameplay: &GamePlay) -> Vec<Ship> {
+        let mut ships = gameplay.get_render_ships();
+        for (ship, offset) in ships.iter_mut().zip(self.render_offsets.iter()) {
+            ship.position.x += offset.x;
+            ship.position.y += offset.y;
+            ship.position.rot += offset.rot;
+        }
+        ships
+    }
+
+    pub fn get_text_entities<'a>(&'a self, gameplay: &'a GamePlay) -> Vec<&'a TextBox> {
+        match self.state {
+            OnlineState::Waiting { .. } | OnlineState::Rejected { .. } => {
+                vec![&self.waiting_text]
+            }
+            OnlineState::Racing => gameplay.get_text_entities(),
+        }
+    }
+
+    /// Rewinds the race to the server's snapshot and then re-simulates
+    /// the steps since then using the player's recorded inputs. The
+    /// other players are assumed to have held the same keys since the
+    /// snapshot.
+    fn apply_snapshot(&mut self, gameplay: &mut GamePlay, snapshot: &Snapshot) {
+        let player_id = match gameplay.online_player {
+            Some(player_id) => player_id,
+            None => return,
+        };
+
+        let predicted: Vec<Transform2d> = gameplay
+            .ship_entities
+            .iter()
+            .map(|ship| ship.position)
+            .collect();
+        let current_tick = gameplay.tick;
+
+        snapshot.restore(gameplay);
+        for (remote_input, input) in gameplay
+            .remote_inputs
+            .iter_mut()
+            .zip(snapshot.inputs.iter())
+        {
+            *remote_input = *input;
+        }
+
+        let player_keys: Vec<ShipKeys> = snapshot
+            .inputs
+            .iter()
+            .map(|i| ShipKeys::from_bits(*i))
+            .collect();
+        gameplay.steer_ships(&player_keys);
+
+        if current_tick > snapshot.tick && current_tick - snapshot.tick <= MAX_RESIMULATE_STEPS {
+            for tick in snapshot.tick..current_tick {
+                let player_keys: Vec<ShipKeys> = (0..gameplay.num_players)
+                    .map(|player| {
+                        let input = if player == player_id {
+                            gameplay.recording.get_input(player, tick)
+                        } else {
+                            gameplay.remote_inputs[player]
+                        };
+                        ShipKeys::from_bits(input)
+                    })
+                    .collect();
+                gameplay.simulate(&player_keys);
+            }
+        } else {
+            // We are behind the server (or so far ahead that there is
+            // no point predicting), so continue from the snapshot. The
+            // recording needs to line up with the ticks again.
+            let input = gameplay
+                .recording
+                .get_input(player_id, current_tick.saturating_sub(1));
+            while gameplay.recording.len() < gameplay.tick {
+                let mut inputs = gameplay.remote_inputs.clone();
+                inputs[player_id] = input;
+                gameplay.recording.record(&inputs);
+            }
+            for player_inputs in gameplay.recording.inputs.iter_mut() {
+                player_inputs.truncate(gameplay.tick as usize);
+            }
+        }
+
+        // Keep drawing the ships where they were and glide them to
+        // where they should be.
+        for ((offset, ship), predicted) in self
+            .render_offsets
+            .iter_mut()
+            .zip(gameplay.ship_entities.iter())
+            .zip(predicted.iter())
+        {
+            offset.x += predicted.x - ship.position.x;
+            offset.y += predicted.y - ship.position.y;
+            offset.rot = wrap_angle(offset.rot + predicted.rot - ship.position.rot);
+        }
+    }
+
+    fn update_waiting_text(&mut self) {
+        self.waiting_text.clear();
+        match self.state {
+            OnlineState::Waiting { joined, players } => {
+                self.waiting_text
+                    .append_string("Waiting for players ", &[0.0, 0.7, 1.0]);
+                self.waiting_text
+                    .append_string(&format!("{}/{}", joined, players), &[0.0, 1.0, 0.7]);
+            }
+            OnlineState::Rejected { players } => {
+                self.waiting_text
+                    .append_string("Room is for ", &[0.0, 0.7, 1.0]);
+                self.waiting_text
+                    .append_string(&format!("{} players", players), &[0.0, 1.0, 0.7]);
+            }
+            OnlineState::Racing => (),
+        }
+    }
+}
+
+fn wrap_angle(angle: f32) -> f32 {
+    let angle = angle + PI; // Work between 0 and 2PI;
+    let sig = f32::signum(angle);
+    let mag = f32::abs(angle) % (2.0 * PI);
+
+    sig * (mag - PI)
+}
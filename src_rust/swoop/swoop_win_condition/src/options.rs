@@ -0,0 +1,97 @@
+use super::difficulty::Difficulty;
+use super::keymap::MAX_PLAYERS;
+use super::replay::Replay;
+use super::rng::from_track_code;
+use super::track::Track;
+
+/// Settings that can be passed in through the `options` attribute on
+/// the canvas. The options string is a space separated list of
+/// `key=value` pairs, eg `options="seed=0ZK3M7Q"`.
+/// Unknown keys and invalid values are ignored.
+pub struct GameOptions {
+    /// Always race on the track with this seed rather than a random one
+    pub seed: Option<u32>,
+
+    /// Always race on this track, either the name of a built-in track
+    /// or a whole track (see `Track::to_string`). This takes priority
+    /// over the seed.
+    pub track: Option<Track>,
+
+    /// A replay (see `Replay::to_string`) to play when the game loads
+    pub replay: Option<Replay>,
+
+    /// How many people are sharing the keyboard
+    pub players: Option<usize>,
+
+    /// How good the AI is, eg `difficulty=hard`
+    pub difficulty: Option<Difficulty>,
+
+    /// Whether the AI slows down or speeds up to stay close to the
+    /// players, `catch_up=on` or `catch_up=off`
+    pub catch_up: Option<bool>,
+
+    /// The relay server (eg `ws://127.0.0.1:9001`) to race online with.
+    /// Without it, races are only against the AI.
+    pub server: Option<String>,
+
+    /// Which room on the server to join
+    pub room: String,
+
+    /// How many people need to be in the room for the race to start
+    pub room_size: usize,
+}
+
+impl GameOptions {
+    pub fn parse(options: &str) -> Self {
+        let mut out = Self {
+            seed: None,
+            track: None,
+            replay: None,
+            players: None,
+            difficulty: None,
+            catch_up: None,
+            server: None,
+            room: "swoop".to_string(),
+            room_size: 2,
+        };
+
+        for pair in options.split_whitespace() {
+            let mut parts = pair.splitn(2, '=');
+            let key = parts.next().unwrap_or("");
+            let value = parts.next().unwrap_or("");
+
+            match key {
+                "seed" => out.seed = from_track_code(value),
+                "track" => out.track = Track::find(value).ok(),
+                "replay" => out.replay = value.parse().ok(),
+                "players" => {
+                    out.players = value
+                        .parse()
+                        .ok()
+                        .filter(|players| (1..=MAX_PLAYERS).contains(players))
+                }
+                "difficulty" => out.difficulty = value.parse().ok(),
+                "catch_up" => {
+                    out.catch_up = match value {
+                        "on" => Some(true),
+                        "off" => Some(false),
+                        _ => None,
+                    }
+                }
+                "server" => out.server = Some(value.to_string()),
+                "room" => out.room = value.to_string(),
+                "room_size" => {
+                    if let Some(room_size) = value
+                        .parse()
+                        .ok()
+                        .filter(|players| (1..=MAX_PLAYERS).contains(players))
+                    {
+                        out.room_size = room_size;
+                    }
+                }
+                _ => (),
+            }
+        }
+        out
+    }
+}
@@ -1,41 +1,124 @@
-use wasm_bindgen::prelude::wasm_bindgen;
-
-#[wasm_bindgen]
-extern "C" {
-    #[wasm_bindgen(js_namespace = console)]
-    fn log(s: &str);
-}
-
-use itertools::Itertools;
-use std::cell::RefCell;
-use std::rc::Rc;
-
+use super::feature::JUMP_TIME;
 use super::map::Map;
 use super::ship::Ship;
-use super::transform::{length, normalize, vect_between, Vec2};
+use super::transform::{length, vect_between, Vec2};
+use std::cmp::Ordering;
 
 const SHIP_RADIUS: f32 = 0.05;
 const GROUND_FRICTION: f32 = 5.0;
 
+/// How much the ships grip each other when they rub together. This is
+/// what makes a glancing blow spin a ship.
+const COLLISION_FRICTION: f32 = 0.2;
+
+/// A bump between two ships during a physics step
+#[derive(Debug, Clone, PartialEq)]
+pub struct CollisionEvent {
+    /// The index of each ship in the list of ships
+    pub ships: (usize, usize),
+    /// Where the ships touched
+    pub position: Vec2,
+    /// Points from the second ship towards the first
+    pub normal: Vec2,
+    /// How hard the ships hit each other. This is the change in
+    /// momentum of each ship along the normal, so it is zero when the
+    /// ships were already moving apart.
+    pub impulse: f32,
+}
+
+/// Finds the ships that are close enough to touch without checking
+/// every pair (sort and sweep). The ships are kept in order along the
+/// x axis, and only ships that overlap along it are checked properly.
+/// The order hardly changes from one step to the next, so sorting it
+/// again is quick, and keeping it between steps means nothing is
+/// allocated once it has been filled.
+pub struct Broadphase {
+    /// The x position of each ship and its index, in order of x
+    order: Vec<(f32, usize)>,
+
+    /// How many pairs of ships were checked properly during the most
+    /// recent step
+    pub num_checks: usize,
+}
+
+impl Broadphase {
+    /// Makes room for `num_ships` ships. More ships can be added later,
+    /// but the first step after that allocates.
+    pub fn new(num_ships: usize) -> Self {
+        Self {
+            order: Vec::with_capacity(num_ships),
+            num_checks: 0,
+        }
+    }
+
+    /// Sorts the ships by where they are now
+    fn update(&mut self, all_ships: &[Ship]) {
+        if self.order.len() != all_ships.len() {
+            self.order.clear();
+            self.order.extend((0..all_ships.len()).map(|id| (0.0, id)));
+        }
+        for entry in self.order.iter_mut() {
+            entry.0 = all_ships[entry.1].position.x;
+        }
+        // Ties are broken by index so the order (and so the physics)
+        // doesn't depend on the order from the previous step. Unlike
+        // the stable sort this doesn't need a buffer.
+        self.order.sort_unstable_by(|a, b| {
+            a.0.partial_cmp(&b.0)
+                .unwrap_or(Ordering::Equal)
+                .then(a.1.cmp(&b.1))
+        });
+    }
+}
+
 /// Moves the ships when they are close together, propagates velocity/motion
-/// and all other physics of the ships.
-pub fn calc_ship_physics(all_ships: &mut Vec<Ship>, map: &Map, dt: f32) {
-    // Motion
+/// and all other physics of the ships. Each bump between two ships is
+/// added to `collisions`.
+pub fn calc_ship_physics(
+    all_ships: &mut [Ship],
+    map: &Map,
+    dt: f32,
+    broadphase: &mut Broadphase,
+    collisions: &mut Vec<CollisionEvent>,
+) {
+    // Track features. Ships in the air skip over them.
     for ship in all_ships.iter_mut() {
-        ship.update(dt as f32);
+        if ship.airborne > 0.0 {
+            continue;
+        }
+        let surface = map.get_surface((ship.position.x, ship.position.y));
+        ship.velocity.x += (surface.acceleration.0 - ship.velocity.x * surface.damping) * dt;
+        ship.velocity.y += (surface.acceleration.1 - ship.velocity.y * surface.damping) * dt;
+        if surface.launch {
+            ship.airborne = JUMP_TIME;
+        }
     }
 
-    // Collisions between ships
-    let ship_refs = all_ships.iter_mut().map(|x| Rc::new(RefCell::new(x)));
-    let all_pairs = ship_refs.permutations(2);
-    let collisions = all_pairs.filter_map(|ships: Vec<Rc<RefCell<&mut Ship>>>| {
-        let ship1 = ships[0].clone();
-        let ship2 = ships[1].clone();
-
-        check_collision(ship1, ship2)
-    });
+    // Motion
+    for ship in all_ships.iter_mut() {
+        ship.update(dt);
+    }
 
-    collisions.for_each(|pair| resolve_collision(pair));
+    // Collisions between ships. Once a ship further along the x axis
+    // is too far away to touch, so are all the ones after it.
+    broadphase.update(all_ships);
+    broadphase.num_checks = 0;
+    let order = &broadphase.order;
+    for (position, (x1, id1)) in order.iter().enumerate() {
+        for (x2, id2) in order[position + 1..].iter() {
+            if x2 - x1 >= SHIP_RADIUS * 2.0 {
+                break;
+            }
+            broadphase.num_checks += 1;
+            let ids = (usize::min(*id1, *id2), usize::max(*id1, *id2));
+            let (ship1, ship2) = get_pair(all_ships, ids);
+            if let Some(mut collision) = check_collision(ship1, ship2) {
+                collision.ships = ids;
+                resolve_collision(ship1, ship2, &mut collision);
+                collisions.push(collision);
+            }
+        }
+    }
 
     // Collisions with map
     for ship in all_ships.iter_mut() {
@@ -55,41 +138,102 @@ pub fn calc_ship_physics(all_ships: &mut Vec<Ship>, map: &Map, dt: f32) {
     }
 }
 
-/// Returns the details of a collision between two ships.
-fn check_collision<'a>(
-    ship1: Rc<RefCell<&'a mut Ship>>,
-    ship2: Rc<RefCell<&'a mut Ship>>,
-) -> Option<CollisionEvent<'a>> {
-    let normal = vect_between(&ship1.borrow().position, &ship2.borrow().position);
-    let len = length(&normal);
-    if len < SHIP_RADIUS * 2.0 {
-        Some(CollisionEvent {
-            obj1: ship1,
-            obj2: ship2,
-            normal: normalize(normal),
-            overlap: len - SHIP_RADIUS * 2.0,
-        })
-    } else {
-        None
+/// Borrows two different ships at once. The first index must be the
+/// smaller one.
+fn get_pair(all_ships: &mut [Ship], ids: (usize, usize)) -> (&mut Ship, &mut Ship) {
+    let (before, after) = all_ships.split_at_mut(ids.1);
+    (&mut before[ids.0], &mut after[0])
+}
+
+/// Returns the details of a collision between two ships. The ship
+/// indices and the impulse are filled in when it is resolved.
+fn check_collision(ship1: &Ship, ship2: &Ship) -> Option<CollisionEvent> {
+    // Ships in the air fly over the ones on the ground
+    if ship1.airborne > 0.0 || ship2.airborne > 0.0 {
+        return None;
     }
+    let between = vect_between(&ship1.position, &ship2.position);
+    let len = length(&between);
+    if len >= SHIP_RADIUS * 2.0 {
+        return None;
+    }
+    // Ships exactly on top of each other still need pushing apart
+    let normal = if len > 0.0 {
+        (between.0 / len, between.1 / len)
+    } else {
+        (1.0, 0.0)
+    };
+    Some(CollisionEvent {
+        ships: (0, 0),
+        position: (
+            ship2.position.x + normal.0 * len * 0.5,
+            ship2.position.y + normal.1 * len * 0.5,
+        ),
+        normal,
+        impulse: 0.0,
+    })
 }
 
-/// Use the details of a CollisionEvent to move the ships apart so they are
-/// no longer colliding
-fn resolve_collision(pair: CollisionEvent) {
-    let mut ship1 = pair.obj1.borrow_mut();
-    let mut ship2 = pair.obj2.borrow_mut();
+/// Moves the ships apart so they are no longer overlapping and bounces
+/// them off each other. The bounce conserves momentum, and the
+/// friction between the ships where they touch spins them.
+fn resolve_collision(ship1: &mut Ship, ship2: &mut Ship, collision: &mut CollisionEvent) {
+    let normal = collision.normal;
+    let inverse_mass1 = 1.0 / ship1.mass;
+    let inverse_mass2 = 1.0 / ship2.mass;
+    let inverse_masses = inverse_mass1 + inverse_mass2;
 
-    ship1.position.x -= pair.normal.0 * pair.overlap * 0.5;
-    ship1.position.y -= pair.normal.1 * pair.overlap * 0.5;
-    ship2.position.x += pair.normal.0 * pair.overlap * 0.5;
-    ship2.position.y += pair.normal.1 * pair.overlap * 0.5;
-}
+    // The lighter ship gets pushed further
+    let len = length(&vect_between(&ship1.position, &ship2.position));
+    let overlap = SHIP_RADIUS * 2.0 - len;
+    let push1 = overlap * inverse_mass1 / inverse_masses;
+    let push2 = overlap * inverse_mass2 / inverse_masses;
+    ship1.position.x += normal.0 * push1;
+    ship1.position.y += normal.1 * push1;
+    ship2.position.x -= normal.0 * push2;
+    ship2.position.y -= normal.1 * push2;
+
+    // How fast the ships' surfaces are moving where they touch. The
+    // contact is on the edge of each ship, so spinning moves it too.
+    let tangent = (-normal.1, normal.0);
+    let contact_velocity = |ship: &Ship, side: f32| {
+        let spin = ship.velocity.rot * SHIP_RADIUS * side;
+        (
+            ship.velocity.x + tangent.0 * spin,
+            ship.velocity.y + tangent.1 * spin,
+        )
+    };
+    let velocity1 = contact_velocity(ship1, -1.0);
+    let velocity2 = contact_velocity(ship2, 1.0);
+    let relative = (velocity1.0 - velocity2.0, velocity1.1 - velocity2.1);
+
+    let closing_speed = -(relative.0 * normal.0 + relative.1 * normal.1);
+    if closing_speed <= 0.0 {
+        // Already moving apart
+        return;
+    }
+    let restitution = (ship1.restitution + ship2.restitution) * 0.5;
+    let impulse = (1.0 + restitution) * closing_speed / inverse_masses;
+
+    // The friction can't be more than the bounce allows. Spinning the
+    // ships takes as much of it as moving them, as if each ship's mass
+    // were spread around the edge of its collision circle.
+    let sliding_speed = relative.0 * tangent.0 + relative.1 * tangent.1;
+    let friction = (-sliding_speed / (inverse_masses * 2.0))
+        .clamp(-COLLISION_FRICTION * impulse, COLLISION_FRICTION * impulse);
+
+    let push = (
+        normal.0 * impulse + tangent.0 * friction,
+        normal.1 * impulse + tangent.1 * friction,
+    );
+    ship1.velocity.x += push.0 * inverse_mass1;
+    ship1.velocity.y += push.1 * inverse_mass1;
+    ship2.velocity.x -= push.0 * inverse_mass2;
+    ship2.velocity.y -= push.1 * inverse_mass2;
+
+    // The friction acts on the edge of each ship, so it turns them
+    ship1.velocity.rot -= friction * inverse_mass1 / SHIP_RADIUS;
+    ship2.velocity.rot -= friction * inverse_mass2 / SHIP_RADIUS;
 
-#[derive(Debug)]
-struct CollisionEvent<'a> {
-    obj1: Rc<RefCell<&'a mut Ship>>,
-    obj2: Rc<RefCell<&'a mut Ship>>,
-    normal: Vec2,
-    overlap: f32,
+    collision.impulse = impulse;
 }
@@ -0,0 +1,293 @@
+//! The fastest way around a track, worked out once when the track is
+//! generated. The AI follows the line and slows down to the speed
+//! profile before each corner. The line goes over boost pads and jump
+//! gates and around sticky zones where there's room to.
+use super::feature::{FeatureKind, JUMP_TIME};
+use super::map::Map;
+use super::ship::{ENGINE_THRUST, LINEAR_DAMPING};
+use super::transform::{length, normalize, Vec2};
+
+/// How many points the racing line is made of
+const NUM_POINTS: usize = 256;
+
+/// How close to the edge of the track the line is allowed to go
+const EDGE_MARGIN: f32 = 0.15;
+
+/// How far inside a boost pad or jump gate (or outside a sticky zone)
+/// the line goes
+const FEATURE_MARGIN: f32 = 0.1;
+
+/// How far before and after a feature the line eases across to it
+const FEATURE_LEAD: f32 = 4.0;
+
+/// How finely the room to land after a jump is measured
+const JUMP_STEP: f32 = 0.1;
+
+/// How many times the line is pulled tight. More iterations cut the
+/// corners more, but it stops changing much after this.
+const SMOOTHING_ITERATIONS: usize = 400;
+
+/// How much sideways acceleration a ship can manage through a corner,
+/// as a fraction of the engine thrust. The rest of the thrust is used
+/// keeping up speed and correcting slides.
+const CORNERING_GRIP: f32 = 0.8;
+
+/// The fastest the ship can go in a straight line, when the engine
+/// thrust is balanced by the damping.
+pub const TOP_SPEED: f32 = ENGINE_THRUST / LINEAR_DAMPING;
+
+pub struct RacingPoint {
+    pub position: Vec2,
+    /// How fast to be going when passing this point
+    pub target_speed: f32,
+}
+
+pub struct RacingLine {
+    /// Evenly spaced in lap fraction around the track, starting at the
+    /// start line and going the way the ships do
+    pub points: Vec<RacingPoint>,
+}
+
+impl RacingLine {
+    pub fn new(map: &Map) -> Self {
+        let (centers, across): (Vec<Vec2>, Vec<Vec2>) = (0..NUM_POINTS)
+            .map(|i| map.get_track_point(i as f32 / NUM_POINTS as f32))
+            .unzip();
+        let max_offsets: Vec<f32> = centers
+            .iter()
+            .map(|center| map.calc_track_width(*center) - EDGE_MARGIN)
+            .collect();
+
+        // Where across the track each point has to be to go over the
+        // features it should, and the parts it should keep out of
+        let mut bounds: Vec<(f32, f32)> = max_offsets.iter().map(|max| (-max, *max)).collect();
+        let mut avoid: Vec<Option<(f32, f32)>> = vec![None; NUM_POINTS];
+        for feature in map.features.iter() {
+            let (center, forwards) = map.get_feature_frame(feature);
+            for i in 0..NUM_POINTS {
+                let delta = (centers[i].0 - center.0, centers[i].1 - center.1);
+                let along = delta.0 * forwards.0 + delta.1 * forwards.1;
+                let side = delta.0 * forwards.1 - delta.1 * forwards.0;
+                // Other parts of the track can be level with the
+                // feature, but not right next to it
+                if along.abs() > feature.length * 0.5 + FEATURE_LEAD
+                    || side.abs() > feature.width + max_offsets[i] + EDGE_MARGIN
+                {
+                    continue;
+                }
+                // Eases from 1 on the feature to 0 at the ends of the
+                // lead, so the line doesn't have to swerve
+                let closeness =
+                    1.0 - f32::max(along.abs() - feature.length * 0.5, 0.0) / FEATURE_LEAD;
+                let closeness = closeness * closeness * (3.0 - 2.0 * closeness);
+                let middle = -(delta.0 * across[i].0 + delta.1 * across[i].1);
+                match feature.kind {
+                    FeatureKind::Boost | FeatureKind::Jump => {
+                        let margin = f32::min(FEATURE_MARGIN, feature.width * 0.5);
+                        let low = middle - feature.width + margin;
+                        let high = middle + feature.width - margin;
+                        let low = f32::max(bounds[i].0, lerp(-max_offsets[i], low, closeness));
+                        let high = f32::min(bounds[i].1, lerp(max_offsets[i], high, closeness));
+                        if low <= high {
+                            bounds[i] = (low, high);
+                        }
+                    }
+                    FeatureKind::Sticky => {
+                        let reach = (feature.width + FEATURE_MARGIN) * closeness;
+                        let (low, high) = (middle - reach, middle + reach);
+                        avoid[i] = Some(match avoid[i] {
+                            Some((start, end)) => (f32::min(start, low), f32::max(end, high)),
+                            None => (low, high),
+                        });
+                    }
+                }
+            }
+        }
+
+        // Start on the center of the track and repeatedly pull each
+        // point towards the middle of its neighbours, like tightening
+        // a piece of elastic. This cuts across the apex of each
+        // corner while staying between the edges of the track.
+        let mut offsets = vec![0.0; NUM_POINTS];
+        let position = |offsets: &[f32], i: usize| {
+            (
+                centers[i].0 + across[i].0 * offsets[i],
+                centers[i].1 + across[i].1 * offsets[i],
+            )
+        };
+        for _ in 0..SMOOTHING_ITERATIONS {
+            for i in 0..NUM_POINTS {
+                let before = position(&offsets, (i + NUM_POINTS - 1) % NUM_POINTS);
+                let after = position(&offsets, (i + 1) % NUM_POINTS);
+                let middle = ((before.0 + after.0) * 0.5, (before.1 + after.1) * 0.5);
+
+                // The closest point to the middle across the track
+                let offset = (middle.0 - centers[i].0) * across[i].0
+                    + (middle.1 - centers[i].1) * across[i].1;
+                offsets[i] = keep_off(offset.clamp(bounds[i].0, bounds[i].1), bounds[i], avoid[i]);
+            }
+        }
+
+        let positions: Vec<Vec2> = (0..NUM_POINTS).map(|i| position(&offsets, i)).collect();
+        let spacings: Vec<f32> = (0..NUM_POINTS)
+            .map(|i| distance(positions[i], positions[(i + 1) % NUM_POINTS]))
+            .collect();
+
+        // How much each boost pad pushes along the line, and how much
+        // each sticky zone holds the ship back
+        let (boosts, dampings): (Vec<f32>, Vec<f32>) = (0..NUM_POINTS)
+            .map(|i| {
+                let surface = map.get_surface(positions[i]);
+                let direction = normalize((
+                    positions[(i + 1) % NUM_POINTS].0 - positions[i].0,
+                    positions[(i + 1) % NUM_POINTS].1 - positions[i].1,
+                ));
+                let boost =
+                    surface.acceleration.0 * direction.0 + surface.acceleration.1 * direction.1;
+                (boost, LINEAR_DAMPING + surface.damping)
+            })
+            .unzip();
+        // Boost pads make the ship faster than its top speed for a while
+        let max_speed = (0..NUM_POINTS)
+            .map(|i| (ENGINE_THRUST + boosts[i]) / dampings[i])
+            .fold(TOP_SPEED, f32::max);
+
+        // The fastest each corner can be taken on its own
+        let mut speeds: Vec<f32> = (0..NUM_POINTS)
+            .map(|i| {
+                let curvature = calc_curvature(
+                    positions[(i + NUM_POINTS - 1) % NUM_POINTS],
+                    positions[i],
+                    positions[(i + 1) % NUM_POINTS],
+                );
+                let grip = ENGINE_THRUST * CORNERING_GRIP;
+                f32::min(f32::sqrt(grip / curvature.max(1e-6)), max_speed)
+            })
+            .collect();
+
+        // A ship can't steer in the air, so it has to go over a jump
+        // slowly enough to land before it reaches the edge
+        for i in 0..NUM_POINTS {
+            if map.get_surface(positions[i]).launch {
+                let direction = normalize((
+                    positions[(i + 1) % NUM_POINTS].0 - positions[i].0,
+                    positions[(i + 1) % NUM_POINTS].1 - positions[i].1,
+                ));
+                let mut room = 0.0;
+                while room < max_speed * JUMP_TIME {
+                    let point = (
+                        positions[i].0 + direction.0 * (room + JUMP_STEP),
+                        positions[i].1 + direction.1 * (room + JUMP_STEP),
+                    );
+                    if map.distance_field(point) > -EDGE_MARGIN {
+                        break;
+                    }
+                    room += JUMP_STEP;
+                }
+                speeds[i] = f32::min(speeds[i], room / JUMP_TIME);
+            }
+        }
+
+        // Brake in time for the corners. Braking is reverse thrust
+        // plus the damping, less any boost that can't be braked
+        // against. Going around twice makes sure the braking for the
+        // first corner carries over the start line.
+        for step in 0..NUM_POINTS * 2 {
+            let i = NUM_POINTS - 1 - step % NUM_POINTS;
+            let next = (i + 1) % NUM_POINTS;
+            let deceleration = f32::max(
+                ENGINE_THRUST - boosts[next] + dampings[next] * speeds[next],
+                0.0,
+            );
+            let reachable = f32::sqrt(speeds[next].powi(2) + 2.0 * deceleration * spacings[i]);
+            speeds[i] = f32::min(speeds[i], reachable);
+        }
+
+        // And there's no point asking for more speed than the ship can
+        // build up coming out of a corner. Past a boost pad the damping
+        // brings the speed back down.
+        for step in 0..NUM_POINTS * 2 {
+            let i = step % NUM_POINTS;
+            let next = (i + 1) % NUM_POINTS;
+            let acceleration = ENGINE_THRUST + boosts[i] - dampings[i] * speeds[i];
+            let reachable = f32::sqrt(f32::max(
+                speeds[i].powi(2) + 2.0 * acceleration * spacings[i],
+                0.0,
+            ));
+            speeds[next] = f32::min(speeds[next], reachable);
+        }
+
+        let points = positions
+            .into_iter()
+            .zip(speeds)
+            .map(|(position, target_speed)| RacingPoint {
+                position,
+                target_speed,
+            })
+            .collect();
+        Self { points }
+    }
+
+    /// The point on the line that is level with a position on the
+    /// track
+    pub fn get_index(&self, map: &Map, position: Vec2) -> usize {
+        let fraction = map.calc_lap_fraction(position);
+        (fraction * self.points.len() as f32).round() as usize % self.points.len()
+    }
+
+    /// Which way the line is heading at a point
+    pub fn get_direction(&self, index: usize) -> Vec2 {
+        let before = &self.points[(index + self.points.len() - 1) % self.points.len()];
+        let after = &self.points[(index + 1) % self.points.len()];
+        normalize((
+            after.position.0 - before.position.0,
+            after.position.1 - before.position.1,
+        ))
+    }
+
+    /// The point on the line roughly `distance` further along it than
+    /// the point at `index`
+    pub fn get_index_ahead(&self, index: usize, distance: f32) -> usize {
+        let mut index = index;
+        let mut travelled = 0.0;
+        while travelled < distance {
+            let next = (index + 1) % self.points.len();
+            travelled += distance_between(&self.points[index], &self.points[next]);
+            index = next;
+        }
+        index
+    }
+}
+
+fn lerp(from: f32, to: f32, fraction: f32) -> f32 {
+    from + (to - from) * fraction
+}
+
+/// Moves an offset out of the part of the track to avoid, to
+/// whichever side is closer and still inside the bounds
+fn keep_off(offset: f32, bounds: (f32, f32), avoid: Option<(f32, f32)>) -> f32 {
+    match avoid {
+        Some((start, end)) if offset > start && offset < end => [start, end]
+            .iter()
+            .copied()
+            .filter(|edge| (bounds.0..=bounds.1).contains(edge))
+            .min_by(|a, b| (a - offset).abs().partial_cmp(&(b - offset).abs()).unwrap())
+            .unwrap_or(offset),
+        _ => offset,
+    }
+}
+
+/// How sharply a curve through three points bends (one over the
+/// radius of the circle through them)
+fn calc_curvature(a: Vec2, b: Vec2, c: Vec2) -> f32 {
+    let twice_area = f32::abs((b.0 - a.0) * (c.1 - a.1) - (b.1 - a.1) * (c.0 - a.0));
+    2.0 * twice_area / (distance(a, b) * distance(b, c) * distance(a, c))
+}
+
+fn distance(a: Vec2, b: Vec2) -> f32 {
+    length(&(b.0 - a.0, b.1 - a.1))
+}
+
+fn distance_between(a: &RacingPoint, b: &RacingPoint) -> f32 {
+    distance(a.position, b.position)
+}
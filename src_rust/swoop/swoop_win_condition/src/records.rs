@@ -0,0 +1,217 @@
+use std::fmt;
+use std::str::FromStr;
+
+use super::gameplay::GamePlay;
+use super::rng::from_track_code;
+use super::storage::Storage;
+use super::track::Track;
+
+/// Increment this if the format of the records changes
+const RECORDS_VERSION: &str = "SWH1";
+
+/// Where the records are kept in storage
+const RECORDS_STORAGE_KEY: &str = "records";
+
+/// The player's best results on a single track
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrackRecord {
+    /// The track code of a generated track, or the whole track (see
+    /// `Track`) for a named or shared one, so that tracks with the same
+    /// seed don't share records
+    pub track: String,
+    pub best_lap: Option<f64>,
+    /// The time from the start of the race to the end of the last lap
+    pub best_race: Option<f64>,
+    /// How many times the player has finished in each position, with
+    /// first place first
+    pub positions: Vec<u32>,
+}
+
+impl TrackRecord {
+    /// What the track is called in the table of records: its name, or
+    /// the track code of a generated track
+    pub fn get_name(&self) -> String {
+        match self.track.parse::<Track>() {
+            Ok(track) => track.name,
+            Err(_) => self.track.clone(),
+        }
+    }
+}
+
+/// Which records were broken by a race
+#[derive(Debug, PartialEq)]
+pub struct NewRecords {
+    pub best_lap: bool,
+    pub best_race: bool,
+}
+
+impl NewRecords {
+    pub fn any(&self) -> bool {
+        self.best_lap || self.best_race
+    }
+}
+
+#[derive(Debug)]
+pub enum RecordsError {
+    /// The string does not start with the expected version identifier
+    UnsupportedVersion(String),
+    /// A track's record could not be read
+    InvalidRecord(String),
+}
+
+/// The player's personal bests on every track they have raced on
+#[derive(Debug, Clone, PartialEq)]
+pub struct Records {
+    /// The tracks, with the most recently raced last
+    pub tracks: Vec<TrackRecord>,
+}
+
+impl Records {
+    /// Records with no races in them
+    pub fn empty() -> Self {
+        Self { tracks: vec![] }
+    }
+
+    /// Reads the records from storage. If nothing has been saved yet
+    /// the records start out empty.
+    pub fn load(storage: &dyn Storage) -> Result<Self, RecordsError> {
+        match storage.get_item(RECORDS_STORAGE_KEY) {
+            Some(saved) => saved.parse(),
+            None => Ok(Self::empty()),
+        }
+    }
+
+    /// Returns false if the records could not be saved
+    pub fn save(&self, storage: &mut dyn Storage) -> bool {
+        storage.set_item(RECORDS_STORAGE_KEY, &self.to_string())
+    }
+
+    /// Finds the records for a track (see `GamePlay::get_track_id`)
+    pub fn get(&self, track: &str) -> Option<&TrackRecord> {
+        self.tracks.iter().find(|record| record.track == track)
+    }
+
+    /// Adds the result of a finished race for one of the players and
+    /// returns which of their records were beaten.
+    pub fn record_race(&mut self, gameplay: &GamePlay, player: usize) -> NewRecords {
+        let score = &gameplay.scores[player];
+        let best_lap = score.get_best_lap();
+        let race_time = score.laps.get(score.laps_to_win - 1).cloned();
+        let position = gameplay
+            .scores
+            .iter()
+            .filter(|other| other.cmp_position(score) == std::cmp::Ordering::Less)
+            .count();
+
+        // Move the track to the end so the tracks stay in the order
+        // they were last raced on
+        let track_id = gameplay.get_track_id();
+        let mut track = match self.tracks.iter().position(|track| track.track == track_id) {
+            Some(index) => self.tracks.remove(index),
+            None => TrackRecord {
+                track: track_id,
+                best_lap: None,
+                best_race: None,
+                positions: vec![],
+            },
+        };
+
+        let new_records = NewRecords {
+            best_lap: is_better(best_lap, track.best_lap),
+            best_race: is_better(race_time, track.best_race),
+        };
+        if new_records.best_lap {
+            track.best_lap = best_lap;
+        }
+        if new_records.best_race {
+            track.best_race = race_time;
+        }
+        if track.positions.len() <= position {
+            track.positions.resize(position + 1, 0);
+        }
+        track.positions[position] += 1;
+        self.tracks.push(track);
+
+        new_records
+    }
+}
+
+/// True if there is a new time and it is faster than the old one
+fn is_better(new_time: Option<f64>, old_time: Option<f64>) -> bool {
+    match (new_time, old_time) {
+        (Some(new_time), Some(old_time)) => new_time < old_time,
+        (Some(_), None) => true,
+        (None, _) => false,
+    }
+}
+
+/// Records are stored as the version followed by a line for each track
+/// with the track code (or the whole track), best lap, best race time
+/// and the number of times the player finished in each position, eg
+/// `0ZK3M7Q 10.25 65.5 2,0,1`. Missing times are `-`.
+impl fmt::Display for Records {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let format_time = |time: Option<f64>| match time {
+            Some(time) => time.to_string(),
+            None => "-".to_string(),
+        };
+
+        write!(f, "{}", RECORDS_VERSION)?;
+        for track in self.tracks.iter() {
+            let positions: Vec<String> = track.positions.iter().map(|p| p.to_string()).collect();
+            write!(
+                f,
+                "\n{} {} {} {}",
+                track.track,
+                format_time(track.best_lap),
+                format_time(track.best_race),
+                positions.join(",")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Records {
+    type Err = RecordsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut lines = s.lines();
+        let version = lines.next().unwrap_or("");
+        if version != RECORDS_VERSION {
+            return Err(RecordsError::UnsupportedVersion(version.to_string()));
+        }
+
+        let mut records = Self::empty();
+        for line in lines.filter(|line| !line.trim().is_empty()) {
+            let invalid = || RecordsError::InvalidRecord(line.to_string());
+            let parse_time = |time: &str| match time {
+                "-" => Ok(None),
+                time => time.parse().map(Some).map_err(|_| invalid()),
+            };
+
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            if parts.len() < 3 || parts.len() > 4 {
+                return Err(invalid());
+            }
+            let positions = match parts.get(3) {
+                Some(positions) => positions
+                    .split(',')
+                    .map(|count| count.parse().map_err(|_| invalid()))
+                    .collect::<Result<Vec<u32>, RecordsError>>()?,
+                None => vec![],
+            };
+            let track = parts[0];
+            if from_track_code(track).is_none() && track.parse::<Track>().is_err() {
+                return Err(invalid());
+            }
+            records.tracks.push(TrackRecord {
+                track: track.to_string(),
+                best_lap: parse_time(parts[1])?,
+                best_race: parse_time(parts[2])?,
+                positions,
+            });
+        }
+        Ok(records)
+    }
+}
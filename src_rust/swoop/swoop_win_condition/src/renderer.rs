@@ -1,6 +1,9 @@
 use super::map_sprite::MapSprite;
 use super::ship_sprite::ShipSprite;
-use super::text_sprite::{TextBox, TextSprite};
+use super::text_box::TextBox;
+use super::text_sprite::TextSprite;
+use super::touch::TouchZone;
+use super::touch_sprite::TouchSprite;
 use super::trail_sprite::TrailSprite;
 use wasm_bindgen::{JsCast, JsValue};
 use web_sys::{HtmlCanvasElement, WebGl2RenderingContext};
@@ -8,7 +11,7 @@ use web_sys::{HtmlCanvasElement, WebGl2RenderingContext};
 use super::ship::Ship;
 use super::trail::Trail;
 
-use super::transform::Transform2d;
+use super::transform::{Transform2d, Vec2};
 
 use wasm_bindgen::prelude::wasm_bindgen;
 #[wasm_bindgen]
@@ -28,6 +31,7 @@ pub struct Renderer {
     pub map_sprite: MapSprite,
     trail_sprite: TrailSprite,
     text_sprite: TextSprite,
+    touch_sprite: TouchSprite,
 
     canvas_resolution: (u32, u32),
 }
@@ -76,6 +80,13 @@ impl Renderer {
                 panic!("Text Sprite error");
             }
         };
+        let touch_sprite = match TouchSprite::new(&gl) {
+            Ok(g) => g,
+            Err(err) => {
+                log(&format!("Touch Sprite error {:?}", err));
+                panic!("Touch Sprite error");
+            }
+        };
 
         Ok(Self {
             gl,
@@ -84,6 +95,7 @@ impl Renderer {
             map_sprite,
             trail_sprite,
             text_sprite,
+            touch_sprite,
             canvas_resolution: (100, 100),
         })
     }
@@ -94,15 +106,105 @@ impl Renderer {
         ships: Vec<&Ship>,
         trails: Vec<&Trail>,
         text_boxes: Vec<&TextBox>,
+    ) {
+        self.render_split(&[*camera_transform], ships, trails, text_boxes);
+    }
+
+    /// Renders the world once for each camera, with each camera getting
+    /// its own part of the screen. The text is drawn over the top of
+    /// the whole screen.
+    pub fn render_split(
+        &mut self,
+        camera_transforms: &[Transform2d],
+        ships: Vec<&Ship>,
+        trails: Vec<&Trail>,
+        text_boxes: Vec<&TextBox>,
     ) {
         // Rendering
         self.check_resize();
+        self.gl.viewport(
+            0,
+            0,
+            self.canvas_resolution.0 as i32,
+            self.canvas_resolution.1 as i32,
+        );
         self.gl.clear(
             WebGl2RenderingContext::COLOR_BUFFER_BIT | WebGl2RenderingContext::DEPTH_BUFFER_BIT,
         );
+
+        let viewports = split_viewports(camera_transforms.len(), self.canvas_resolution);
+        for (camera_transform, viewport) in camera_transforms.iter().zip(viewports) {
+            self.gl
+                .viewport(viewport.0, viewport.1, viewport.2, viewport.3);
+            let viewport_aspect_ratio = (viewport.3 as f32) / (viewport.2 as f32);
+            self.render_world(camera_transform, viewport_aspect_ratio, &ships, &trails);
+        }
+
+        self.gl.viewport(
+            0,
+            0,
+            self.canvas_resolution.0 as i32,
+            self.canvas_resolution.1 as i32,
+        );
         let screen_aspect_ratio =
             (self.canvas_resolution.1 as f32) / (self.canvas_resolution.0 as f32);
-        let camera_to_clipspace = [1.0, 0.0, 0.0, 0.0, screen_aspect_ratio, 0.0, 0.0, 0.0, 1.0];
+        self.text_sprite.setup(&self.gl);
+        for text in text_boxes {
+            self.text_sprite.render(&self.gl, text, screen_aspect_ratio);
+        }
+    }
+
+    /// Draws the on-screen touch controls over the top of whatever was
+    /// last rendered
+    pub fn render_touch_zones(&mut self, zones: &[TouchZone]) {
+        let screen_aspect_ratio =
+            (self.canvas_resolution.1 as f32) / (self.canvas_resolution.0 as f32);
+        self.touch_sprite.setup(&self.gl);
+        for zone in zones {
+            self.touch_sprite
+                .render(&self.gl, zone, screen_aspect_ratio);
+        }
+    }
+
+    /// The height of the canvas over its width
+    pub fn get_aspect_ratio(&self) -> f32 {
+        self.canvas.client_height() as f32 / self.canvas.client_width().max(1) as f32
+    }
+
+    /// Where in the world a pixel on the canvas is when the whole
+    /// canvas is looking through the camera
+    pub fn canvas_to_world(&self, camera_transform: &Transform2d, pixel: Vec2) -> Vec2 {
+        let width = self.canvas.client_width().max(1) as f32;
+        let height = self.canvas.client_height() as f32;
+        let camera = (
+            (2.0 * pixel.0 - width) / width,
+            (height - 2.0 * pixel.1) / width,
+        );
+        (
+            camera.0 * camera_transform.scale + camera_transform.x,
+            camera.1 * camera_transform.scale + camera_transform.y,
+        )
+    }
+
+    /// Converts a position in the world to the units used by
+    /// TouchZones, so things can be drawn over the top of the world
+    pub fn world_to_screen(&self, camera_transform: &Transform2d, world: Vec2) -> Vec2 {
+        let aspect_ratio = self.get_aspect_ratio().max(1e-6);
+        (
+            (world.0 - camera_transform.x) / camera_transform.scale / aspect_ratio,
+            (world.1 - camera_transform.y) / camera_transform.scale / aspect_ratio,
+        )
+    }
+
+    /// Draws the trails, ships and map into the current viewport
+    fn render_world(
+        &mut self,
+        camera_transform: &Transform2d,
+        aspect_ratio: f32,
+        ships: &[&Ship],
+        trails: &[&Trail],
+    ) {
+        let camera_to_clipspace = [1.0, 0.0, 0.0, 0.0, aspect_ratio, 0.0, 0.0, 0.0, 1.0];
 
         let world_to_camera = camera_transform.to_mat3_array();
         self.trail_sprite.camera_to_clipspace = camera_to_clipspace;
@@ -114,14 +216,14 @@ impl Renderer {
 
         self.trail_sprite.setup(&self.gl);
         for trail in trails {
-            self.trail_sprite.render(&self.gl, &trail);
+            self.trail_sprite.render(&self.gl, trail);
         }
 
         self.ship_sprite.camera_to_clipspace = camera_to_clipspace;
         self.ship_sprite.world_to_camera = world_to_camera;
         self.ship_sprite.setup(&self.gl);
         for ship in ships {
-            self.ship_sprite.render(&self.gl, &ship);
+            self.ship_sprite.render(&self.gl, ship);
         }
 
         let map_sprite_transform = Transform2d::new(0.0, 0.0, 0.0, 1.0);
@@ -130,11 +232,6 @@ impl Renderer {
         self.map_sprite.camera_to_clipspace = camera_to_clipspace;
         self.map_sprite.world_to_sprite = map_sprite_transform.to_mat3_array();
         self.map_sprite.render(&self.gl);
-
-        self.text_sprite.setup(&self.gl);
-        for text in text_boxes {
-            self.text_sprite.render(&self.gl, text, screen_aspect_ratio);
-        }
     }
 
     fn check_resize(&mut self) {
@@ -144,7 +241,6 @@ impl Renderer {
         let canvas_height = self.canvas.height() as i32;
 
         if client_width != canvas_width || client_height != canvas_height {
-            self.gl.viewport(0, 0, client_width, client_height);
             let client_width = client_width as u32;
             let client_height = client_height as u32;
 
@@ -155,3 +251,22 @@ impl Renderer {
         }
     }
 }
+
+/// Divides the screen into `count` viewports as (x, y, width, height).
+/// Two views are side by side, three or four are arranged in a grid
+/// with the first view in the top left.
+fn split_viewports(count: usize, resolution: (u32, u32)) -> Vec<(i32, i32, i32, i32)> {
+    let width = resolution.0 as i32;
+    let height = resolution.1 as i32;
+    match count {
+        0 | 1 => vec![(0, 0, width, height)],
+        2 => vec![(0, 0, width / 2, height), (width / 2, 0, width / 2, height)],
+        _ => (0..count.min(4) as i32)
+            .map(|id| {
+                let column = id % 2;
+                let row = 1 - id / 2;
+                (column * width / 2, row * height / 2, width / 2, height / 2)
+            })
+            .collect(),
+    }
+}
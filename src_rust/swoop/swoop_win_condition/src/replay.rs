@@ -0,0 +1,226 @@
+use std::fmt;
+use std::str::FromStr;
+
+use super::difficulty::Difficulty;
+use super::gameplay::SIM_TIME_STEP;
+use super::keymap::MAX_PLAYERS;
+use super::rng::{from_track_code, to_track_code};
+use super::track::{Track, TrackError};
+
+/// Increment this if the format of the replay string changes
+const REPLAY_VERSION: &str = "SWR3";
+
+/// The most simulation steps a replay can hold. Races have no time
+/// limit, but an hour is far longer than any real race, and it stops a
+/// shared replay from asking for more memory than there is.
+pub const MAX_REPLAY_TICKS: usize = (60.0 * 60.0 / SIM_TIME_STEP) as usize;
+
+/// A recording of a race. Because the track is generated from the
+/// seed (or stored alongside) and the AI and physics are deterministic,
+/// the only thing that needs to be stored is what each player was
+/// pressing on each simulation step.
+#[derive(Debug, Clone)]
+pub struct Replay {
+    pub seed: u32,
+    /// The named or shared track the race was on. When there isn't one
+    /// the track is generated from the seed.
+    pub track: Option<Track>,
+
+    /// The AI the race was against
+    pub difficulty: Difficulty,
+    pub catch_up: bool,
+
+    /// Each player's inputs (see `ShipKeys::to_bits`) for each
+    /// simulation step
+    pub inputs: Vec<Vec<u8>>,
+}
+
+#[derive(Debug)]
+pub enum ReplayError {
+    /// The string does not start with the expected version identifier
+    UnsupportedVersion(String),
+    InvalidTrackCode(String),
+    /// The list of inputs could not be decoded
+    InvalidInputs(String),
+    /// There are more players than can share a keyboard
+    TooManyPlayers(usize),
+    /// The AI settings could not be read
+    InvalidSettings(String),
+    InvalidTrack(TrackError),
+}
+
+impl Replay {
+    pub fn new(seed: u32, num_players: usize) -> Self {
+        Self {
+            seed,
+            track: None,
+            difficulty: Difficulty::Normal,
+            catch_up: false,
+            inputs: vec![vec![]; num_players],
+        }
+    }
+
+    /// Record a single simulation step. There should be one input for
+    /// each player.
+    pub fn record(&mut self, inputs: &[u8]) {
+        for (player_inputs, input) in self.inputs.iter_mut().zip(inputs) {
+            player_inputs.push(*input);
+        }
+    }
+
+    /// Returns a player's input for a particular simulation step. Once
+    /// the replay has run out, there is no input.
+    pub fn get_input(&self, player: usize, tick: u64) -> u8 {
+        self.inputs
+            .get(player)
+            .and_then(|player_inputs| player_inputs.get(tick as usize))
+            .cloned()
+            .unwrap_or(0)
+    }
+
+    pub fn num_players(&self) -> usize {
+        self.inputs.len()
+    }
+
+    /// The number of simulation steps that were recorded
+    pub fn len(&self) -> u64 {
+        self.inputs.first().map_or(0, |inputs| inputs.len()) as u64
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl fmt::Display for Replay {
+    /// Converts the replay into a compact string that can be shared.
+    /// The format is `SWR3.<track code>.<settings>.<inputs>` with a
+    /// `.<inputs>` section for each player. The settings are the first
+    /// letter of the difficulty followed by `C` if catch-up was on. The
+    /// inputs are run-length encoded as two letters (the high and low
+    /// four bits of the input, `a` = 0) followed by the number of steps
+    /// the input was held for. A race on a named or shared track ends
+    /// with `;` and the track (see `Track`).
+    /// eg: `SWR3.0ZK3M7Q.HC.aa480ah120dh14ah300`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", REPLAY_VERSION, to_track_code(self.seed))?;
+        write!(f, ".{}", self.difficulty.to_letter())?;
+        if self.catch_up {
+            write!(f, "C")?;
+        }
+
+        for player_inputs in self.inputs.iter() {
+            write!(f, ".")?;
+
+            let mut runs = player_inputs.iter().peekable();
+            while let Some(input) = runs.next() {
+                let mut count = 1;
+                while runs.peek() == Some(&input) {
+                    runs.next();
+                    count += 1;
+                }
+                write!(
+                    f,
+                    "{}{}{}",
+                    (b'a' + (input >> 4)) as char,
+                    (b'a' + (input & 0x0F)) as char,
+                    count
+                )?;
+            }
+        }
+        if let Some(track) = &self.track {
+            write!(f, ";{}", track)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Replay {
+    type Err = ReplayError;
+
+    fn from_str(replay: &str) -> Result<Self, Self::Err> {
+        let (replay, track) = match replay.trim().split_once(';') {
+            Some((replay, track)) => (replay, Some(track)),
+            None => (replay.trim(), None),
+        };
+        let track = match track {
+            Some(track) => Some(track.parse().map_err(ReplayError::InvalidTrack)?),
+            None => None,
+        };
+        let mut sections = replay.split('.');
+
+        let version = sections.next().unwrap_or("");
+        if version != REPLAY_VERSION {
+            return Err(ReplayError::UnsupportedVersion(version.to_string()));
+        }
+
+        let track_code = sections.next().unwrap_or("");
+        let seed = from_track_code(track_code)
+            .ok_or_else(|| ReplayError::InvalidTrackCode(track_code.to_string()))?;
+
+        let settings = sections.next().unwrap_or("");
+        let invalid_settings = || ReplayError::InvalidSettings(settings.to_string());
+        let mut letters = settings.chars();
+        let difficulty = letters
+            .next()
+            .and_then(Difficulty::from_letter)
+            .ok_or_else(invalid_settings)?;
+        let catch_up = match letters.as_str() {
+            "" => false,
+            "C" => true,
+            _ => return Err(invalid_settings()),
+        };
+
+        let mut inputs = vec![];
+        for encoded_inputs in sections {
+            inputs.push(decode_inputs(encoded_inputs)?);
+        }
+        if inputs.is_empty() {
+            inputs.push(vec![]);
+        }
+        if inputs.len() > MAX_PLAYERS {
+            return Err(ReplayError::TooManyPlayers(inputs.len()));
+        }
+
+        Ok(Self {
+            seed,
+            track,
+            difficulty,
+            catch_up,
+            inputs,
+        })
+    }
+}
+
+/// Decodes the run-length encoded inputs for a single player
+fn decode_inputs(encoded_inputs: &str) -> Result<Vec<u8>, ReplayError> {
+    let invalid_inputs = || ReplayError::InvalidInputs(encoded_inputs.to_string());
+
+    let mut inputs = vec![];
+    let mut chars = encoded_inputs.chars().peekable();
+    while chars.peek().is_some() {
+        let mut input = 0;
+        // The high then the low four bits
+        for _ in 0..2 {
+            match chars.next() {
+                Some(letter) if ('a'..='p').contains(&letter) => {
+                    input = input << 4 | (letter as u8 - b'a');
+                }
+                _ => return Err(invalid_inputs()),
+            }
+        }
+        let mut count = String::new();
+        while let Some(digit) = chars.peek().filter(|c| c.is_ascii_digit()) {
+            count.push(*digit);
+            chars.next();
+        }
+        let count: usize = count.parse().map_err(|_| invalid_inputs())?;
+        let length = inputs
+            .len()
+            .checked_add(count)
+            .filter(|length| *length <= MAX_REPLAY_TICKS)
+            .ok_or_else(invalid_inputs)?;
+        inputs.resize(length, input);
+    }
+    Ok(inputs)
+}
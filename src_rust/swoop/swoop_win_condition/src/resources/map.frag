@@ -15,8 +15,41 @@ uniform float track_width;
 uniform vec4 sin_consts[2];
 uniform vec4 cos_consts[2];
 
+// How the width of a polar track changes around the track
+uniform vec4 width_sin_consts;
+uniform vec4 width_cos_consts;
+
+// This must match MIN_TRACK_WIDTH in map.rs
+const float MIN_TRACK_WIDTH = 0.3;
+
 uniform vec2 start_line_tangent;
 uniform vec2 start_line_position;
+// How far the start line reaches to either side
+uniform float start_line_width;
+
+// These must match MAX_SPLINE_POINTS and SEGMENT_STEPS in spline.rs
+const int MAX_SPLINE_POINTS = 32;
+const int SPLINE_SEGMENT_STEPS = 8;
+
+// When there are no spline points the track is polar
+uniform int num_spline_points;
+uniform vec2 spline_points[MAX_SPLINE_POINTS];
+// How much wider the track is at each control point
+uniform float spline_widths[MAX_SPLINE_POINTS];
+
+// These must match MAX_FEATURES in feature.rs and the kinds sent by
+// map_sprite.rs
+const int MAX_FEATURES = 16;
+const int FEATURE_BOOST = 0;
+const int FEATURE_STICKY = 1;
+const int FEATURE_JUMP = 2;
+
+uniform int num_features;
+// The middle of each feature and the direction along the track there
+uniform vec4 feature_frames[MAX_FEATURES];
+// How far each feature reaches across and along the track
+uniform vec2 feature_sizes[MAX_FEATURES];
+uniform int feature_kinds[MAX_FEATURES];
 
 
 float map_function(vec2 position) {
@@ -37,12 +70,108 @@ float map_function(vec2 position) {
     track_radius += dot(cos(angles_1), cos_consts_1);
     track_radius += dot(cos(angles_2), cos_consts_2);
 
+    float width = track_width;
+    width += dot(sin(angles_1), width_sin_consts);
+    width += dot(cos(angles_1), width_cos_consts);
+    width = max(width, MIN_TRACK_WIDTH);
+
     float track_sdf = course - track_radius;
-    track_sdf = abs(track_sdf) - track_width;
+    track_sdf = abs(track_sdf) - width;
     return track_sdf;
 }
 
 
+vec2 spline_control_point(int index) {
+    return spline_points[(index + num_spline_points) % num_spline_points];
+}
+
+// A point on the Catmull-Rom segment between two control points
+vec2 spline_position(vec2 p0, vec2 p1, vec2 p2, vec2 p3, float t) {
+    float t2 = t * t;
+    float t3 = t2 * t;
+    return 0.5 * (
+        2.0 * p1
+        + (p2 - p0) * t
+        + (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * t2
+        + (-p0 + 3.0 * p1 - 3.0 * p2 + p3) * t3
+    );
+}
+
+float spline_control_width(int index) {
+    return spline_widths[(index + num_spline_points) % num_spline_points];
+}
+
+// The extra width part way along a segment, eased in and out the same
+// way as calc_width in spline.rs
+float spline_width(float start, float end, float t) {
+    return mix(start, end, t * t * (3.0 - 2.0 * t));
+}
+
+// The distance from a position to a line, and how far along the line
+// (from 0 to 1) the closest point is
+vec2 line_distance(vec2 position, vec2 start, vec2 end) {
+    vec2 to_position = position - start;
+    vec2 line = end - start;
+    float fraction = clamp(dot(to_position, line) / max(dot(line, line), 1e-12), 0.0, 1.0);
+    return vec2(length(to_position - line * fraction), fraction);
+}
+
+float spline_map_function(vec2 position) {
+    float closest = 1e10;
+    float closest_width = 0.0;
+    for (int segment = 0; segment < MAX_SPLINE_POINTS; segment++) {
+        if (segment >= num_spline_points) {
+            break;
+        }
+        vec2 p0 = spline_control_point(segment - 1);
+        vec2 p1 = spline_control_point(segment);
+        vec2 p2 = spline_control_point(segment + 1);
+        vec2 p3 = spline_control_point(segment + 2);
+
+        // The segment stays inside its Bezier control points, so skip
+        // it if they are all further away than the closest so far
+        vec2 center = (p1 + p2) * 0.5;
+        float radius = max(
+            max(length(p1 - center), length(p2 - center)),
+            max(
+                length(p1 + (p2 - p0) / 6.0 - center),
+                length(p2 - (p3 - p1) / 6.0 - center)
+            )
+        );
+        if (length(position - center) - radius > closest) {
+            continue;
+        }
+
+        float w1 = spline_control_width(segment);
+        float w2 = spline_control_width(segment + 1);
+
+        vec2 previous = p1;
+        float previous_width = w1;
+        for (int i = 1; i <= SPLINE_SEGMENT_STEPS; i++) {
+            float t = float(i) / float(SPLINE_SEGMENT_STEPS);
+            vec2 next = spline_position(p0, p1, p2, p3, t);
+            float next_width = spline_width(w1, w2, t);
+            vec2 piece = line_distance(position, previous, next);
+            if (piece.x < closest) {
+                closest = piece.x;
+                closest_width = mix(previous_width, next_width, piece.y);
+            }
+            previous = next;
+            previous_width = next_width;
+        }
+    }
+    return closest - max(track_width + closest_width, MIN_TRACK_WIDTH);
+}
+
+
+float track_function(vec2 position) {
+    if (num_spline_points > 0) {
+        return spline_map_function(position);
+    }
+    return map_function(position);
+}
+
+
 
 vec4 neon(float sdf, vec4 color, float glow_width) {
 	float ramp = clamp(1.0 - sdf / glow_width, 0.0, 1.0);
@@ -77,7 +206,7 @@ float startline(vec2 world_coordinates) {
     float dist_from_line = length(start_line_coords);
     float dist_from_center = projected_dist;
     
-    float start_line_ends = - 1.0 + abs(dist_from_center);
+    float start_line_ends = - start_line_width + abs(dist_from_center);
     
     float start_line = max(dist_from_line, start_line_ends);
     
@@ -85,8 +214,54 @@ float startline(vec2 world_coordinates) {
 }
 
 
+// How far a position (across and along a feature) is from the lines
+// drawn on it. Boost pads have chevrons pointing along the track,
+// sticky zones are hatched and jump gates have stripes across them.
+float feature_pattern(int kind, vec2 local) {
+    float pattern;
+    if (kind == FEATURE_BOOST) {
+        pattern = (local.y - abs(local.x)) * 2.0;
+    } else if (kind == FEATURE_STICKY) {
+        pattern = (local.x + local.y) * 3.0;
+    } else {
+        pattern = local.y * 6.0;
+    }
+    return 0.5 - abs(0.5 - fract(pattern)) + track_background_line_fade;
+}
+
+vec4 feature_color(int kind) {
+    if (kind == FEATURE_BOOST) {
+        return vec4(0.2, 1.0, 0.4, 1.0);
+    } else if (kind == FEATURE_STICKY) {
+        return vec4(1.0, 0.5, 0.1, 1.0);
+    }
+    return vec4(0.3, 0.5, 1.0, 1.0);
+}
+
+vec4 features(vec2 position) {
+    vec4 color = vec4(0.0);
+    for (int i = 0; i < MAX_FEATURES; i++) {
+        if (i >= num_features) {
+            break;
+        }
+        vec2 forwards = feature_frames[i].zw;
+        vec2 delta = position - feature_frames[i].xy;
+        vec2 local = vec2(dot(delta, vec2(forwards.y, -forwards.x)), dot(delta, forwards));
+
+        vec2 outside = abs(local) - feature_sizes[i];
+        float box = length(max(outside, 0.0)) + min(max(outside.x, outside.y), 0.0);
+        float feature_sdf = map_edges(box);
+        if (box < 0.0) {
+            feature_sdf = min(feature_sdf, feature_pattern(feature_kinds[i], local));
+        }
+        color += neon(feature_sdf, feature_color(feature_kinds[i]), 0.1);
+    }
+    return color;
+}
+
+
 void main() {
-    float track = map_function(uv);
+    float track = track_function(uv);
     
     float edge_sdf = map_edges(track);
     
@@ -105,5 +280,6 @@ void main() {
         map_visualized,
         vec4(0.9, 0.9, 0.9, 1.0), 0.1
     );
+    FragColor += features(uv);
 }
 
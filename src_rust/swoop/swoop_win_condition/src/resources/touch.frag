@@ -0,0 +1,28 @@
+#version 300 es
+
+precision mediump float;
+in vec2 uv;
+out vec4 FragColor;
+
+/// Where the finger is, in units of the zone radius
+uniform vec2 knob;
+uniform vec4 zone_color;
+
+vec4 neon(float sdf, vec4 color, float glow_width) {
+	float ramp = clamp(1.0 - sdf / glow_width, 0.0, 1.0);
+	vec4 outp = vec4(0.0);
+	ramp = ramp * ramp;
+	outp += pow(color, vec4(4.0)) * ramp;
+	ramp = ramp * ramp;
+	outp += color * ramp;
+	ramp = ramp * ramp;
+	outp += vec4(1.0) * ramp;
+	return outp;
+}
+
+void main() {
+	float ring = abs(length(uv) - 1.0);
+	float knob_circle = abs(length(uv - knob) - 0.3);
+	FragColor = neon(ring, zone_color, 0.15);
+	FragColor += neon(knob_circle, zone_color, 0.15);
+}
@@ -0,0 +1,23 @@
+#version 300 es
+
+precision highp float;
+in vec4 aVertexPosition;
+
+/// Where the center of the zone is (in screen space)
+uniform vec2 center;
+
+/// The radius of the zone (in screen space)
+uniform float radius;
+
+/// Aspect ratio of the screen
+uniform float screen_aspect;
+
+out vec2 uv;
+
+void main() {
+	// Leave room around the edge of the zone for the glow
+	uv = aVertexPosition.xy * 1.5;
+	vec2 pos = center + uv * radius;
+	pos.x *= screen_aspect;
+	gl_Position = vec4(pos, 0.0, 1.0);
+}
@@ -0,0 +1,79 @@
+/// A small seedable pseudo random number generator (PCG32). This is
+/// used instead of `Math.random` so that a track (and anything else
+/// generated from the seed) can be reproduced exactly.
+pub struct Rng {
+    state: u64,
+}
+
+const MULTIPLIER: u64 = 6364136223846793005;
+const INCREMENT: u64 = 1442695040888963407;
+
+/// The characters used to represent a track code. This is Crockford's
+/// base32 which avoids characters that are easy to confuse (I, L, O, U)
+const TRACK_CODE_CHARS: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+/// Enough base32 characters to represent a u32
+const TRACK_CODE_LENGTH: usize = 7;
+
+impl Rng {
+    pub fn new(seed: u32) -> Self {
+        let mut rng = Self { state: 0 };
+        rng.next_u32();
+        rng.state = rng.state.wrapping_add(seed as u64);
+        rng.next_u32();
+        rng
+    }
+
+    /// Returns a random number across the whole range of a u32
+    pub fn next_u32(&mut self) -> u32 {
+        let old_state = self.state;
+        self.state = old_state.wrapping_mul(MULTIPLIER).wrapping_add(INCREMENT);
+
+        let xorshifted = (((old_state >> 18) ^ old_state) >> 27) as u32;
+        let rotation = (old_state >> 59) as u32;
+        xorshifted.rotate_right(rotation)
+    }
+
+    /// Returns a random number between 0.0 and 1.0 (excluding 1.0)
+    pub fn next_f32(&mut self) -> f32 {
+        // An f32 only has 24 bits of precision
+        (self.next_u32() >> 8) as f32 / (1u32 << 24) as f32
+    }
+}
+
+/// Converts a seed into a short string that people can share with
+/// each other, eg "0ZK3M7Q".
+pub fn to_track_code(seed: u32) -> String {
+    let mut code = String::with_capacity(TRACK_CODE_LENGTH);
+    for i in (0..TRACK_CODE_LENGTH).rev() {
+        let digit = (seed as u64 >> (i * 5)) & 0x1F;
+        code.push(TRACK_CODE_CHARS[digit as usize] as char);
+    }
+    code
+}
+
+/// Converts a track code back into a seed. Lowercase letters and the
+/// easily-confused characters (I, L, O) are accepted. Returns None if
+/// the string is not a valid track code.
+pub fn from_track_code(code: &str) -> Option<u32> {
+    if code.is_empty() || code.len() > TRACK_CODE_LENGTH {
+        return None;
+    }
+
+    let mut seed: u64 = 0;
+    for c in code.chars() {
+        let c = match c.to_ascii_uppercase() {
+            'I' | 'L' => '1',
+            'O' => '0',
+            other => other,
+        };
+        let digit = TRACK_CODE_CHARS.iter().position(|x| *x as char == c)?;
+        seed = (seed << 5) | digit as u64;
+    }
+
+    if seed > u32::MAX as u64 {
+        None
+    } else {
+        Some(seed as u32)
+    }
+}
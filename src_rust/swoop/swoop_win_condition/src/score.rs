@@ -1,47 +1,184 @@
-use super::map::Map;
+use super::gameplay::NUM_LAPS_TO_WIN;
+use super::map::{Map, NUM_SECTORS};
 use super::ship::Ship;
+use super::transform::Vec2;
 use std::cmp::Ordering;
 
 pub struct Score {
     pub laps: Vec<f64>,
+
+    /// The time the ship went through each checkpoint, starting from
+    /// when it first crossed the start line. The start line counts as
+    /// a checkpoint, so every lap adds NUM_SECTORS splits.
+    pub splits: Vec<f64>,
+
+    /// How far through the race the ship is, in laps since it first
+    /// crossed the start line. It is negative before then. Only the
+    /// checkpoints that have been passed in order count, so cutting
+    /// the track doesn't put a ship ahead.
+    pub progress: f64,
+
+    /// How many times the ship has to cross the start line to finish
+    /// the race (see `GamePlay::laps_to_win`)
+    pub laps_to_win: usize,
+
     previous_progress: f32,
+
+    /// The sector the ship was in on the previous update
+    previous_sector: usize,
+    /// The checkpoint (the start of a sector) that the ship has to go
+    /// through next. Once this reaches NUM_SECTORS all of the
+    /// checkpoints have been passed and crossing the start line
+    /// counts as a lap.
+    next_checkpoint: usize,
+    /// Whether the last time the ship crossed the start line it
+    /// completed a lap. If it didn't, going back across the line
+    /// shouldn't take away the lap before.
+    counted_last_crossing: bool,
+}
+
+impl Default for Score {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Score {
     pub fn new() -> Self {
         Self {
             laps: vec![],
+            splits: vec![],
+            progress: 0.0,
+            laps_to_win: NUM_LAPS_TO_WIN,
             previous_progress: 0.0,
+            previous_sector: 0,
+            next_checkpoint: NUM_SECTORS,
+            counted_last_crossing: false,
         }
     }
 
     pub fn reset(&mut self, map: &Map, ship: &Ship) {
         self.laps.clear();
+        self.splits.clear();
+
+        let position = (ship.position.x, ship.position.y);
+        self.previous_progress = map.calc_progress_relative_to_startline(position);
+        self.previous_sector = map.get_sector(position);
 
-        self.previous_progress =
-            map.calc_progress_relative_to_startline((ship.position.x, ship.position.y));
+        // Ships start just behind the start line, so the first time
+        // they cross it always counts.
+        self.next_checkpoint = NUM_SECTORS;
+        self.counted_last_crossing = false;
+        self.update_progress(map, position);
     }
 
-    /// Checks if the player crosses the start/finish line and updates
-    /// the score to match
+    /// Replaces the lap times, eg with ones received from a server.
+    /// The ship should already be in the matching position. The ship
+    /// is assumed to have gone through all the checkpoints up to the
+    /// sector it is in.
+    pub fn restore(&mut self, map: &Map, ship: &Ship, laps: &[f64]) {
+        self.laps = laps.to_vec();
+
+        let position = (ship.position.x, ship.position.y);
+        self.previous_progress = map.calc_progress_relative_to_startline(position);
+        self.previous_sector = map.get_sector(position);
+        self.next_checkpoint = self.previous_sector + 1;
+        self.counted_last_crossing = !self.laps.is_empty();
+
+        // Split times aren't sent by the server, so the ones already
+        // here are kept and trimmed or padded to match the laps
+        let num_splits = match self.laps.len() {
+            0 => 0,
+            laps => (laps - 1) * NUM_SECTORS + self.next_checkpoint,
+        };
+        let padding = self.laps.last().cloned().unwrap_or(0.0);
+        self.splits.resize(num_splits, padding);
+        self.update_progress(map, position);
+    }
+
+    /// Checks if the player passes a checkpoint or crosses the
+    /// start/finish line and updates the score to match. Once the ship
+    /// has finished the race the score no longer changes.
     pub fn update(&mut self, map: &Map, ship: &Ship, time: f64) {
-        let current_progress =
-            map.calc_progress_relative_to_startline((ship.position.x, ship.position.y));
+        if self.finished() {
+            return;
+        }
+        let position = (ship.position.x, ship.position.y);
+        let current_progress = map.calc_progress_relative_to_startline(position);
+        let current_sector = map.get_sector(position);
+
+        if current_sector == self.previous_sector + 1 && current_sector == self.next_checkpoint {
+            // Went forwards through the next checkpoint
+            self.next_checkpoint += 1;
+            self.splits.push(time);
+        } else if current_sector + 1 == self.previous_sector
+            && self.previous_sector + 1 == self.next_checkpoint
+        {
+            // Went backwards through the last checkpoint, so it will
+            // have to be passed again
+            self.next_checkpoint -= 1;
+            self.splits.pop();
+        }
 
         // Progress has jumped from previously being near 1.0 (nearly completed)
-        // to being near 0.0 (just started), so they probably did a lap
+        // to being near 0.0 (just started), so they probably did a lap. It
+        // only counts if they went through every sector on the way.
         if self.previous_progress > 0.8 && current_progress < 0.2 {
-            self.laps.push(time);
+            self.counted_last_crossing = self.next_checkpoint == NUM_SECTORS;
+            if self.counted_last_crossing {
+                self.laps.push(time);
+                self.splits.push(time);
+                self.next_checkpoint = 1;
+            }
         }
 
         // Progress has jumped from previously being near 0.0 (just started)
         // to being close to 1.0 (nearly completed) so the player went back
         // across the line.
         if self.previous_progress < 0.2 && current_progress > 0.8 {
-            self.laps.pop();
+            if self.counted_last_crossing && self.next_checkpoint == 1 {
+                self.laps.pop();
+                self.splits.pop();
+                self.next_checkpoint = NUM_SECTORS;
+            }
+            self.counted_last_crossing = false;
         }
 
-        self.previous_progress = current_progress
+        self.previous_progress = current_progress;
+        self.previous_sector = current_sector;
+        self.update_progress(map, position);
+    }
+
+    /// True once the ship has done all the laps in the race
+    pub fn finished(&self) -> bool {
+        self.laps.len() >= self.laps_to_win
+    }
+
+    fn update_progress(&mut self, map: &Map, position: Vec2) {
+        let lap_fraction = map.calc_lap_fraction(position) as f64;
+        if self.splits.is_empty() {
+            // Still behind the start line
+            self.progress = lap_fraction - 1.0;
+        } else if self.finished() {
+            // Everyone who has finished is equal, so the finishing
+            // times decide between them.
+            self.progress = (self.laps_to_win - 1) as f64;
+        } else {
+            let num_sectors = NUM_SECTORS as f64;
+            let checkpoints_passed = self.splits.len() - 1;
+            let sector = (checkpoints_passed % NUM_SECTORS) as f64;
+
+            // How far through the sector the ship should be in. This
+            // wraps around so that being just behind the start line
+            // is a little before the first sector rather than at the
+            // end of the lap.
+            let sector_offset = (lap_fraction * num_sectors - sector + num_sectors * 0.5)
+                .rem_euclid(num_sectors)
+                - num_sectors * 0.5;
+
+            self.progress =
+                (checkpoints_passed as f64 + sector_offset.clamp(0.0, 1.0)) / num_sectors;
+        }
     }
 
     /// Returns a vector of the times for each lap
@@ -62,7 +199,7 @@ impl Score {
     pub fn get_average_lap(&self) -> Option<f64> {
         let lap_timings = self.get_lap_timings();
 
-        if lap_timings.len() > 0 {
+        if !lap_timings.is_empty() {
             let mut total_time = 0.0;
             for lap_time in &lap_timings {
                 total_time += lap_time
@@ -81,8 +218,80 @@ impl Score {
         lap_timings.first().cloned()
     }
 
-    // Compare two scores to see which is better
-    pub fn cmp(&self, other: &Self) -> Ordering {
+    /// The time of each sector of a completed lap, measured from the
+    /// start of the lap. The first timed lap is lap 0, and the last
+    /// split is the lap time.
+    pub fn get_lap_splits(&self, lap: usize) -> Vec<f64> {
+        let lap_start = lap * NUM_SECTORS;
+        match self.splits.get(lap_start..=lap_start + NUM_SECTORS) {
+            Some(splits) => splits[1..].iter().map(|time| time - splits[0]).collect(),
+            None => vec![],
+        }
+    }
+
+    /// The most recent checkpoint passed on a timed lap. Returns the
+    /// sector that was completed and the time since the start of the
+    /// lap.
+    pub fn get_latest_split(&self) -> Option<(usize, f64)> {
+        if self.splits.len() < 2 {
+            return None;
+        }
+        let index = self.splits.len() - 1;
+        let sector = (index - 1) % NUM_SECTORS;
+        let lap_start = self.splits[index - 1 - sector];
+        Some((sector, self.splits[index] - lap_start))
+    }
+
+    /// How long each sector took on every completed sector, with the
+    /// sectors of each lap one after another
+    pub fn get_sector_times(&self) -> Vec<f64> {
+        self.splits
+            .windows(2)
+            .map(|pair| pair[1] - pair[0])
+            .collect()
+    }
+
+    /// The fastest time through each sector, from any lap
+    pub fn get_best_sectors(&self) -> Vec<Option<f64>> {
+        let mut best_sectors = vec![None; NUM_SECTORS];
+        for (index, time) in self.get_sector_times().iter().enumerate() {
+            let best = &mut best_sectors[index % NUM_SECTORS];
+            let is_best = match best {
+                Some(best) => *time < *best,
+                None => true,
+            };
+            if is_best {
+                *best = Some(*time);
+            }
+        }
+        best_sectors
+    }
+
+    /// The lap time if every sector was as fast as the best time
+    /// through it
+    pub fn get_theoretical_best_lap(&self) -> Option<f64> {
+        self.get_best_sectors().into_iter().sum()
+    }
+
+    /// How long after the `ahead` ship this ship went through the last
+    /// checkpoint it passed
+    pub fn get_gap_to(&self, ahead: &Score) -> Option<f64> {
+        let index = self.splits.len().checked_sub(1)?;
+        let ahead_time = ahead.splits.get(index)?;
+        Some(self.splits[index] - ahead_time)
+    }
+
+    /// Compare two scores to see which is better. The ship that is
+    /// further around the track is ahead. If they are level (eg they
+    /// have both finished) the one that crossed the line first is.
+    pub fn cmp_position(&self, other: &Self) -> Ordering {
+        match other.progress.partial_cmp(&self.progress) {
+            Some(Ordering::Equal) | None => self.cmp_laps(other),
+            Some(ordering) => ordering,
+        }
+    }
+
+    fn cmp_laps(&self, other: &Self) -> Ordering {
         let a_laps = self.laps.len();
         let b_laps = other.laps.len();
         let a_last_lap = self.laps.last();
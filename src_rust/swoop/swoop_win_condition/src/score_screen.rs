@@ -1,47 +1,126 @@
+use super::records::NewRecords;
 use super::score::Score;
 use super::ship::Ship;
-use super::text_sprite::TextBox;
+use super::text_box::TextBox;
 
 pub struct ScoreScreen {
     title: TextBox,
+    /// Shown when the player beats one of their personal bests
+    new_record: TextBox,
     scores: TextBox,
+    best_sectors: TextBox,
+    /// The track code, or the name of a shared track
+    track_code: TextBox,
     instructions: TextBox,
 }
 
 impl ScoreScreen {
     pub fn new() -> Self {
         let mut title = TextBox::new((15, 1), 0.1, (0.0, 0.5));
-        let mut scores = TextBox::new((13, 5), 0.05, (0.0, 0.0));
-        let mut instructions = TextBox::new((27, 1), 0.05, (0.0, -0.5));
+        let new_record = TextBox::new((11, 1), 0.06, (0.0, 0.72));
+        let mut scores = TextBox::new((19, 5), 0.05, (0.0, 0.1));
+        let best_sectors = TextBox::new((36, 3), 0.03, (0.0, -0.27));
+        let track_code = TextBox::new((14, 1), 0.05, (0.0, -0.42));
+        let instructions = TextBox::new((27, 2), 0.05, (0.0, -0.62));
 
         title.clear();
         title.append_string("Round Completed", &[0.0, 0.7, 1.0]);
 
         scores.clear();
 
-        instructions.clear();
-        instructions.append_string("Press ", &[0.0, 0.7, 1.0]);
-        instructions.append_string("[ENTER]", &[0.0, 1.0, 0.7]);
-        instructions.append_string(" to play again", &[0.0, 0.7, 1.0]);
-        Self {
+        let mut score_screen = Self {
             title,
+            new_record,
             scores,
+            best_sectors,
+            track_code,
             instructions,
+        };
+        score_screen.set_touch_detected(false);
+        score_screen
+    }
+
+    /// Phones and tablets don't have an [ENTER] key, so they are asked
+    /// to tap instead
+    pub fn set_touch_detected(&mut self, touch_detected: bool) {
+        self.instructions.clear();
+        if touch_detected {
+            self.instructions.append_string("     ", &[0.0, 0.7, 1.0]);
+            self.instructions.append_string("Tap", &[0.0, 1.0, 0.7]);
+            self.instructions
+                .append_string(" to play again", &[0.0, 0.7, 1.0]);
+        } else {
+            self.instructions.append_string("Press ", &[0.0, 0.7, 1.0]);
+            self.instructions.append_string("[ENTER]", &[0.0, 1.0, 0.7]);
+            self.instructions
+                .append_string(" to play again", &[0.0, 0.7, 1.0]);
+            self.instructions.append_string(" Press ", &[0.0, 0.7, 1.0]);
+            self.instructions.append_string("[R]", &[0.0, 1.0, 0.7]);
+            self.instructions
+                .append_string(" to watch replay ", &[0.0, 0.7, 1.0]);
         }
     }
 
     pub fn get_text_entities<'a>(&'a self) -> Vec<&'a TextBox> {
-        vec![&self.title, &self.scores, &self.instructions]
+        vec![
+            &self.title,
+            &self.new_record,
+            &self.scores,
+            &self.best_sectors,
+            &self.track_code,
+            &self.instructions,
+        ]
     }
 
-    pub fn populate_scores(&mut self, ships: &Vec<Ship>, scores: &Vec<Score>) {
+    /// `new_records` are the personal bests the player beat in this
+    /// race, or None if the race didn't count towards the records (eg
+    /// a replay).
+    pub fn populate_scores(
+        &mut self,
+        ships: &Vec<Ship>,
+        scores: &Vec<Score>,
+        track_name: &str,
+        new_records: Option<&NewRecords>,
+    ) {
         self.scores.clear();
 
+        self.new_record.clear();
+        if let Some(new_records) = new_records {
+            if new_records.any() {
+                self.new_record
+                    .append_string("New record!", &[1.0, 0.8, 0.0]);
+            }
+        }
+
+        // Show the track code so people can race the same track again.
+        // Track names are longer than codes, so size the box to fit.
+        self.track_code = TextBox::new(((track_name.len() + 6) as i32, 1), 0.05, (0.0, -0.42));
+        self.track_code.append_string("Track ", &[0.5, 0.5, 0.5]);
+        self.track_code.append_string(track_name, &[0.0, 1.0, 0.7]);
+
         let mut ship_and_score_refs: Vec<(&Ship, &Score)> =
             ships.iter().zip(scores.iter()).collect();
-        ship_and_score_refs.sort_by(|a, b| a.1.cmp(b.1));
+        ship_and_score_refs.sort_by(|a, b| a.1.cmp_position(b.1));
+
+        // The best sectors of the first player, who is always a person
+        self.best_sectors.clear();
+        if let Some(score) = scores.first() {
+            self.best_sectors
+                .append_string(&format!("{:^36}", "Best Sectors"), &[0.5, 0.5, 0.5]);
+            for (sector, time) in score.get_best_sectors().iter().enumerate() {
+                self.best_sectors
+                    .append_string(&format!("S{} ", sector + 1), &[0.5, 0.5, 0.5]);
+                self.best_sectors
+                    .append_string(&format_time(*time), &[0.0, 0.7, 1.0]);
+                // Each line holds four sectors
+                self.best_sectors.append_string(" ", &[0.5, 0.5, 0.5]);
+            }
+        }
 
-        self.scores.append_string("   Avg   Best", &[0.5, 0.5, 0.5]);
+        // Theo is the theoretical best lap: all of the best sectors put
+        // together
+        self.scores
+            .append_string("   Avg   Best  Theo", &[0.5, 0.5, 0.5]);
 
         for (ship, score) in ship_and_score_refs {
             let color = [ship.color.0, ship.color.1, ship.color.2];
@@ -53,6 +132,9 @@ impl ScoreScreen {
             self.scores.append_string(&format_time(average_lap), &color);
             self.scores.append_string(" ", &color);
             self.scores.append_string(&format_time(best_lap), &color);
+            self.scores.append_string(" ", &color);
+            self.scores
+                .append_string(&format_time(score.get_theoretical_best_lap()), &color);
         }
     }
 }
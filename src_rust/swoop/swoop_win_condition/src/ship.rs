@@ -1,16 +1,35 @@
 use super::transform::{Transform2d, Vec2};
 
-const ENGINE_THRUST: f32 = 10.0;
+pub const ENGINE_THRUST: f32 = 10.0;
 const TURNING_THRUST: f32 = 40.0;
-const LINEAR_DAMPING: f32 = 2.0;
+pub const LINEAR_DAMPING: f32 = 2.0;
 const ANGULAR_DAMPING: f32 = 8.0;
+/// Damping while the ship is in the air, where there's only air
+/// resistance to slow it down
+const AIR_DAMPING: f32 = 0.5;
 
-#[derive(Debug)]
+pub const SHIP_MASS: f32 = 1.0;
+/// How bouncy a ship is, from 0 (the ships stick together) to 1 (they
+/// bounce apart as fast as they hit)
+pub const SHIP_RESTITUTION: f32 = 0.5;
+
+#[derive(Debug, Clone)]
 pub struct Ship {
     pub position: Transform2d,
+    /// Where the ship was at the start of the most recent physics
+    /// step. Used to smoothly draw the ship between physics steps.
+    pub prev_position: Transform2d,
     pub velocity: Transform2d,
     pub linear_thrust: f32,
     pub angular_thrust: f32,
+    /// How much longer the ship is in the air after going over a jump
+    /// gate. The engine can only turn the ship while it is in the air.
+    pub airborne: f32,
+    /// How hard the ship is to push around in a collision
+    pub mass: f32,
+    /// How bouncy the ship is in a collision (see SHIP_RESTITUTION).
+    /// Two ships bounce with the average of their restitutions.
+    pub restitution: f32,
     pub color: (f32, f32, f32, f32),
 }
 
@@ -18,10 +37,14 @@ impl Ship {
     pub fn new(color: (f32, f32, f32, f32)) -> Self {
         Ship {
             position: Transform2d::new(0.0, 0.0, 0.0, 0.1),
+            prev_position: Transform2d::new(0.0, 0.0, 0.0, 0.1),
             velocity: Transform2d::new(0.0, 0.0, 0.0, 0.0),
             linear_thrust: 0.0,
             angular_thrust: 0.0,
-            color: color,
+            airborne: 0.0,
+            mass: SHIP_MASS,
+            restitution: SHIP_RESTITUTION,
+            color,
         }
     }
 
@@ -33,13 +56,20 @@ impl Ship {
 
         let forwards = (-s, c);
 
+        let (linear_thrust, linear_damping) = if self.airborne > 0.0 {
+            self.airborne = f32::max(self.airborne - dt, 0.0);
+            (0.0, AIR_DAMPING)
+        } else {
+            (self.linear_thrust, LINEAR_DAMPING)
+        };
+
         let mut acceleration = (0.0, 0.0, 0.0);
-        acceleration.0 += forwards.0 * self.linear_thrust * ENGINE_THRUST;
-        acceleration.1 += forwards.1 * self.linear_thrust * ENGINE_THRUST;
+        acceleration.0 += forwards.0 * linear_thrust * ENGINE_THRUST;
+        acceleration.1 += forwards.1 * linear_thrust * ENGINE_THRUST;
         acceleration.2 += self.angular_thrust * TURNING_THRUST;
 
-        acceleration.0 -= self.velocity.x * LINEAR_DAMPING;
-        acceleration.1 -= self.velocity.y * LINEAR_DAMPING;
+        acceleration.0 -= self.velocity.x * linear_damping;
+        acceleration.1 -= self.velocity.y * linear_damping;
         acceleration.2 -= self.velocity.rot * ANGULAR_DAMPING;
 
         self.velocity.x += acceleration.0 * dt;
@@ -54,6 +84,12 @@ impl Ship {
         self.position.rot = wrap_angle(self.position.rot);
     }
 
+    /// Where to draw the ship when `fraction` of the way through
+    /// the next physics step.
+    pub fn get_interpolated_position(&self, fraction: f32) -> Transform2d {
+        self.prev_position.lerp(&self.position, fraction)
+    }
+
     pub fn get_engine_position(&self) -> Vec2 {
         let offset = self.position.transform_vec((0.0, -0.45));
         (self.position.x + offset.0, self.position.y + offset.1)
@@ -88,5 +124,5 @@ fn wrap_angle(angle: f32) -> f32 {
     let sig = f32::signum(angle);
     let mag = f32::abs(angle) % (2.0 * std::f32::consts::PI);
 
-    return sig * (mag - std::f32::consts::PI);
+    sig * (mag - std::f32::consts::PI)
 }
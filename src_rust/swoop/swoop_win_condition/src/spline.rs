@@ -0,0 +1,302 @@
+//! A closed Catmull-Rom spline used as the center line of a track.
+//! Unlike the polar tracks, a spline can double back on itself, so it
+//! can have hairpins that point at the middle of the map.
+//!
+//! Each segment of the spline is split into `SEGMENT_STEPS` straight
+//! pieces for measuring distances. `map.frag` splits the segments up
+//! the same way, so the track looks exactly like the ships feel it.
+//!
+//! Each control point can also make the track wider or narrower than
+//! the map's `track_width`. The change in width is eased in and out
+//! between control points.
+use super::transform::{length, normalize, Vec2};
+
+/// How many straight pieces each segment between two control points
+/// is made of. This must match SPLINE_SEGMENT_STEPS in map.frag.
+pub const SEGMENT_STEPS: usize = 8;
+
+/// The most control points a spline can have. This must match
+/// MAX_SPLINE_POINTS in map.frag.
+pub const MAX_SPLINE_POINTS: usize = 32;
+
+/// The fewest control points that make a loop
+pub const MIN_SPLINE_POINTS: usize = 3;
+
+/// The shortest a segment between two control points can be. Shorter
+/// ones (eg two control points in the same place) have no direction,
+/// and a spline with no length can't be measured along at all.
+pub const MIN_SEGMENT_LENGTH: f32 = 0.01;
+
+#[derive(Debug, PartialEq)]
+pub enum SplineError {
+    /// There are not enough control points to make a loop
+    TooFewPoints(usize),
+    /// There are more control points than the shader can draw
+    TooManyPoints(usize),
+    /// There has to be a width for every control point
+    WrongNumberOfWidths { points: usize, widths: usize },
+    /// The segment starting at this control point is shorter than
+    /// MIN_SEGMENT_LENGTH
+    Degenerate(usize),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spline {
+    /// The spline passes through each control point in order and then
+    /// back to the first one
+    points: Vec<Vec2>,
+    /// How much wider than the map's track width the track is at each
+    /// control point
+    widths: Vec<f32>,
+
+    /// The ends of the straight pieces, starting at the first control
+    /// point
+    samples: Vec<Vec2>,
+    /// How far along the spline each sample is
+    distances: Vec<f32>,
+    /// How much wider than the map's track width the track is at each
+    /// sample
+    sample_widths: Vec<f32>,
+    length: f32,
+
+    /// A circle around each segment that the segment never leaves,
+    /// used to skip the segments that are too far away to matter
+    bounds: Vec<(Vec2, f32)>,
+}
+
+impl Spline {
+    /// A spline where the track is the same width all the way around
+    pub fn new(points: Vec<Vec2>) -> Result<Self, SplineError> {
+        let widths = vec![0.0; points.len()];
+        Self::with_widths(points, widths)
+    }
+
+    /// A spline where the track is `widths[i]` wider than the map's
+    /// track width at control point `i`
+    pub fn with_widths(points: Vec<Vec2>, widths: Vec<f32>) -> Result<Self, SplineError> {
+        if points.len() < MIN_SPLINE_POINTS {
+            return Err(SplineError::TooFewPoints(points.len()));
+        }
+        if points.len() > MAX_SPLINE_POINTS {
+            return Err(SplineError::TooManyPoints(points.len()));
+        }
+        if widths.len() != points.len() {
+            return Err(SplineError::WrongNumberOfWidths {
+                points: points.len(),
+                widths: widths.len(),
+            });
+        }
+
+        let num_samples = points.len() * SEGMENT_STEPS;
+        let samples: Vec<Vec2> = (0..num_samples)
+            .map(|i| {
+                let t = i as f32 / SEGMENT_STEPS as f32;
+                calc_position(&points, t)
+            })
+            .collect();
+        let sample_widths = (0..num_samples)
+            .map(|i| {
+                let t = i as f32 / SEGMENT_STEPS as f32;
+                calc_width(&widths, t)
+            })
+            .collect();
+
+        let mut distances = Vec::with_capacity(num_samples);
+        let mut travelled = 0.0;
+        for i in 0..num_samples {
+            distances.push(travelled);
+            travelled += distance(samples[i], samples[(i + 1) % num_samples]);
+        }
+        for segment in 0..points.len() {
+            let start = distances[segment * SEGMENT_STEPS];
+            let end = distances
+                .get((segment + 1) * SEGMENT_STEPS)
+                .copied()
+                .unwrap_or(travelled);
+            if end - start < MIN_SEGMENT_LENGTH {
+                return Err(SplineError::Degenerate(segment));
+            }
+        }
+
+        // Each segment is a Bezier curve, so stays inside the hull of
+        // its Bezier control points
+        let bounds = (0..points.len())
+            .map(|segment| {
+                let (p0, p1, p2, p3) = get_segment_points(&points, segment);
+                let hull = [
+                    p1,
+                    (p1.0 + (p2.0 - p0.0) / 6.0, p1.1 + (p2.1 - p0.1) / 6.0),
+                    (p2.0 - (p3.0 - p1.0) / 6.0, p2.1 - (p3.1 - p1.1) / 6.0),
+                    p2,
+                ];
+                let center = ((p1.0 + p2.0) * 0.5, (p1.1 + p2.1) * 0.5);
+                let radius = hull
+                    .iter()
+                    .map(|point| distance(*point, center))
+                    .fold(0.0, f32::max);
+                (center, radius)
+            })
+            .collect();
+
+        Ok(Self {
+            points,
+            widths,
+            samples,
+            distances,
+            sample_widths,
+            length: travelled,
+            bounds,
+        })
+    }
+
+    pub fn get_points(&self) -> &[Vec2] {
+        &self.points
+    }
+
+    pub fn get_widths(&self) -> &[f32] {
+        &self.widths
+    }
+
+    /// How far it is all the way around the spline
+    pub fn get_length(&self) -> f32 {
+        self.length
+    }
+
+    /// The distance from a position to the closest point on the spline,
+    /// and how far along the spline that point is.
+    pub fn calc_closest(&self, position: Vec2) -> (f32, f32) {
+        let mut closest = f32::MAX;
+        let mut along = 0.0;
+        for (segment, (center, radius)) in self.bounds.iter().enumerate() {
+            if distance(position, *center) - radius > closest {
+                continue;
+            }
+            for step in 0..SEGMENT_STEPS {
+                let i = segment * SEGMENT_STEPS + step;
+                let start = self.samples[i];
+                let end = self.samples[(i + 1) % self.samples.len()];
+                let (piece_distance, fraction) = calc_line_distance(position, start, end);
+                if piece_distance < closest {
+                    closest = piece_distance;
+                    along = self.distances[i] + fraction * distance(start, end);
+                }
+            }
+        }
+        (closest, along)
+    }
+
+    /// The point `along` the spline from the first control point, and
+    /// which way the spline is heading there
+    pub fn get_point(&self, along: f32) -> (Vec2, Vec2) {
+        let (i, fraction) = self.find_piece(along);
+        let start = self.samples[i];
+        let end = self.samples[(i + 1) % self.samples.len()];
+
+        let position = (
+            start.0 + (end.0 - start.0) * fraction,
+            start.1 + (end.1 - start.1) * fraction,
+        );
+        let t = (i as f32 + fraction) / SEGMENT_STEPS as f32;
+        (position, normalize(calc_tangent(&self.points, t)))
+    }
+
+    /// How much wider than the map's track width the track is `along`
+    /// the spline from the first control point
+    pub fn get_width(&self, along: f32) -> f32 {
+        let (i, fraction) = self.find_piece(along);
+        let start = self.sample_widths[i];
+        let end = self.sample_widths[(i + 1) % self.sample_widths.len()];
+        start + (end - start) * fraction
+    }
+
+    /// Which straight piece is `along` the spline, and how far through
+    /// the piece (from 0 to 1) it is
+    fn find_piece(&self, along: f32) -> (usize, f32) {
+        let along = along.rem_euclid(self.length);
+        let i = match self
+            .distances
+            .binary_search_by(|d| d.partial_cmp(&along).unwrap())
+        {
+            Ok(i) => i,
+            Err(i) => i - 1,
+        };
+        let start = self.samples[i];
+        let end = self.samples[(i + 1) % self.samples.len()];
+        let piece_length = distance(start, end).max(1e-6);
+        let fraction = ((along - self.distances[i]) / piece_length).clamp(0.0, 1.0);
+        (i, fraction)
+    }
+}
+
+/// The control points either side of a segment. The segment goes from
+/// the second to the third.
+fn get_segment_points(points: &[Vec2], segment: usize) -> (Vec2, Vec2, Vec2, Vec2) {
+    let n = points.len();
+    (
+        points[(segment + n - 1) % n],
+        points[segment % n],
+        points[(segment + 1) % n],
+        points[(segment + 2) % n],
+    )
+}
+
+/// A point on the spline where `t` counts the segments, so 1.5 is half
+/// way between the second and third control points.
+fn calc_position(points: &[Vec2], t: f32) -> Vec2 {
+    let segment = t.floor() as usize;
+    let t = t - t.floor();
+    let (p0, p1, p2, p3) = get_segment_points(points, segment);
+    let t2 = t * t;
+    let t3 = t2 * t;
+    let blend = |a: f32, b: f32, c: f32, d: f32| {
+        0.5 * (2.0 * b
+            + (c - a) * t
+            + (2.0 * a - 5.0 * b + 4.0 * c - d) * t2
+            + (-a + 3.0 * b - 3.0 * c + d) * t3)
+    };
+    (blend(p0.0, p1.0, p2.0, p3.0), blend(p0.1, p1.1, p2.1, p3.1))
+}
+
+/// The extra width at a point on the spline, eased between the control
+/// points on either side so the edges of the track stay smooth
+fn calc_width(widths: &[f32], t: f32) -> f32 {
+    let segment = t.floor() as usize;
+    let t = t - t.floor();
+    let start = widths[segment % widths.len()];
+    let end = widths[(segment + 1) % widths.len()];
+    let eased = t * t * (3.0 - 2.0 * t);
+    start + (end - start) * eased
+}
+
+/// The derivative of `calc_position`
+fn calc_tangent(points: &[Vec2], t: f32) -> Vec2 {
+    let segment = t.floor() as usize;
+    let t = t - t.floor();
+    let (p0, p1, p2, p3) = get_segment_points(points, segment);
+    let t2 = t * t;
+    let blend = |a: f32, b: f32, c: f32, d: f32| {
+        0.5 * ((c - a)
+            + 2.0 * (2.0 * a - 5.0 * b + 4.0 * c - d) * t
+            + 3.0 * (-a + 3.0 * b - 3.0 * c + d) * t2)
+    };
+    (blend(p0.0, p1.0, p2.0, p3.0), blend(p0.1, p1.1, p2.1, p3.1))
+}
+
+/// The distance from a position to a line segment, and how far along
+/// the segment (from 0 to 1) the closest point is
+fn calc_line_distance(position: Vec2, start: Vec2, end: Vec2) -> (f32, f32) {
+    let to_position = (position.0 - start.0, position.1 - start.1);
+    let line = (end.0 - start.0, end.1 - start.1);
+    let line_length_squared = (line.0 * line.0 + line.1 * line.1).max(1e-12);
+    let fraction =
+        ((to_position.0 * line.0 + to_position.1 * line.1) / line_length_squared).clamp(0.0, 1.0);
+    let offset = (
+        to_position.0 - line.0 * fraction,
+        to_position.1 - line.1 * fraction,
+    );
+    (length(&offset), fraction)
+}
+
+fn distance(a: Vec2, b: Vec2) -> f32 {
+    length(&(b.0 - a.0, b.1 - a.1))
+}
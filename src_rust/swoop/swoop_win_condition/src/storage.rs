@@ -0,0 +1,79 @@
+//! Somewhere to keep things (key bindings, records) between games. In
+//! the browser this is local storage. Natively, such as when running
+//! headless, each item is a file in a directory.
+
+/// Everything saved by the game is prefixed with this so it doesn't
+/// clash with anything else on the page
+const KEY_PREFIX: &str = "swoop_";
+
+pub trait Storage {
+    /// Reads a value saved with `set_item`
+    fn get_item(&self, key: &str) -> Option<String>;
+
+    /// Saves a value so that it is still there the next time the game
+    /// is loaded. Returns false if it couldn't be saved.
+    fn set_item(&mut self, key: &str, value: &str) -> bool;
+}
+
+/// The browser's local storage
+#[cfg(target_arch = "wasm32")]
+pub struct LocalStorage;
+
+#[cfg(target_arch = "wasm32")]
+impl LocalStorage {
+    fn storage() -> Option<web_sys::Storage> {
+        // Local storage can be disabled (eg in private browsing), in
+        // which case nothing is remembered between visits.
+        web_sys::window()?.local_storage().ok()?
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+impl Storage for LocalStorage {
+    fn get_item(&self, key: &str) -> Option<String> {
+        Self::storage()?
+            .get_item(&format!("{}{}", KEY_PREFIX, key))
+            .ok()?
+    }
+
+    fn set_item(&mut self, key: &str, value: &str) -> bool {
+        match Self::storage() {
+            Some(storage) => storage
+                .set_item(&format!("{}{}", KEY_PREFIX, key), value)
+                .is_ok(),
+            None => false,
+        }
+    }
+}
+
+/// Stores each item as a text file in a directory
+#[cfg(not(target_arch = "wasm32"))]
+pub struct FileStorage {
+    directory: std::path::PathBuf,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl FileStorage {
+    /// The directory is created when the first item is saved
+    pub fn new<P: Into<std::path::PathBuf>>(directory: P) -> Self {
+        Self {
+            directory: directory.into(),
+        }
+    }
+
+    fn path(&self, key: &str) -> std::path::PathBuf {
+        self.directory.join(format!("{}{}.txt", KEY_PREFIX, key))
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Storage for FileStorage {
+    fn get_item(&self, key: &str) -> Option<String> {
+        std::fs::read_to_string(self.path(key)).ok()
+    }
+
+    fn set_item(&mut self, key: &str, value: &str) -> bool {
+        std::fs::create_dir_all(&self.directory).is_ok()
+            && std::fs::write(self.path(key), value).is_ok()
+    }
+}
@@ -10,6 +10,7 @@ extern "C" {
 }
 
 use super::shader::{init_shader_program, upload_array_f32, ShaderError};
+use super::text_box::TextBox;
 use super::texture::{bind_2d_texture_to_uniform, load_texture, TextureUnit};
 
 pub struct TextSprite {
@@ -123,56 +124,3 @@ impl TextSprite {
         );
     }
 }
-
-/// The thing that can be drawn with a text sprite.
-pub struct TextBox {
-    data: Vec<f32>,
-
-    // The text box wraps character wise and permits width*height characters to be displayed
-    box_dimensions: (i32, i32),
-
-    /// Height of a single character As percentage of screen size
-    character_height: f32,
-
-    /// Where on the screen to draw the text. Positions the center of the text box with the screen ranging from -1.0 to 1.0 on both axis.
-    anchor: (f32, f32),
-}
-
-impl TextBox {
-    const VALID_CHARS: &'static str =
-        "0123456789ABCDFEGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz:-/![]  ";
-
-    pub fn new(box_dimensions: (i32, i32), character_height: f32, anchor: (f32, f32)) -> Self {
-        Self {
-            data: vec![],
-            box_dimensions,
-            character_height,
-            anchor,
-        }
-    }
-
-    /// Erases the contents of the text box
-    pub fn clear(&mut self) {
-        self.data.clear();
-    }
-
-    pub fn append_string(&mut self, string: &str, color: &[f32; 3]) {
-        for c in string.chars() {
-            self.data.extend(color);
-            self.data.push(Self::encode_char(c));
-        }
-    }
-
-    /// The Text sprite has characters encoded in a non-standard order.
-    /// This does the conversion
-    fn encode_char(c: char) -> f32 {
-        match Self::VALID_CHARS.find(c) {
-            Some(id) => id as f32,
-            None => -1.0,
-        }
-    }
-    // Retrieve the data in a format that can be posted to teh shader
-    pub fn uniform_data(&self) -> &Vec<f32> {
-        &self.data
-    }
-}
@@ -0,0 +1,198 @@
+use web_sys::{HtmlCanvasElement, TouchEvent};
+
+use super::keymap::{KeyMap, KeyState};
+
+/// How far (in screen units) a finger has to move from where it
+/// touched down to give full steering or full reverse
+const ZONE_RADIUS: f32 = 0.25;
+
+/// How far the zones are drawn from the bottom corners of the screen
+/// when nobody is touching them
+const ZONE_INSET: f32 = 0.4;
+
+const ZONE_COLOR: [f32; 4] = [0.0, 0.7, 1.0, 1.0];
+const IDLE_ZONE_COLOR: [f32; 4] = [0.0, 0.2, 0.3, 1.0];
+
+#[derive(Clone, Copy)]
+enum TouchPhase {
+    Start,
+    Move,
+    End,
+}
+
+/// A finger that is currently on the screen
+struct ActiveTouch {
+    id: i32,
+    start: (f32, f32),
+    position: (f32, f32),
+    /// Touches on the menus are taps rather than steering
+    is_tap: bool,
+}
+
+/// Where to draw one of the virtual controls. Positions are in the
+/// same units as a TextBox anchor, but with the x axis not squashed
+/// by the aspect ratio so that the zones stay round.
+pub struct TouchZone {
+    pub center: (f32, f32),
+    /// Where the finger is, relative to the center, in units of the
+    /// zone radius
+    pub knob: (f32, f32),
+    pub radius: f32,
+    pub color: [f32; 4],
+}
+
+/// On-screen controls for phones and tablets. Touching the left half
+/// of the screen and sliding left or right steers. Touching the right
+/// half thrusts forwards, and sliding down from there slows down and
+/// then reverses. On the menus a tap does the same as [ENTER].
+pub struct TouchControls {
+    canvas: HtmlCanvasElement,
+    touches: Vec<ActiveTouch>,
+
+    /// Events are stored until the next frame so that they are all
+    /// applied at the same point as the keyboard events
+    events: Vec<(TouchPhase, i32, (f32, f32))>,
+
+    /// True once the player has touched the screen. Until then the
+    /// controls are hidden and the menus ask for [ENTER].
+    detected: bool,
+}
+
+impl TouchControls {
+    pub fn new(canvas: HtmlCanvasElement) -> Self {
+        Self {
+            canvas,
+            touches: vec![],
+            events: vec![],
+            detected: false,
+        }
+    }
+
+    pub fn detected(&self) -> bool {
+        self.detected
+    }
+
+    pub fn handle_event(&mut self, event: &TouchEvent) {
+        let phase = match event.type_().as_str() {
+            "touchstart" => TouchPhase::Start,
+            "touchmove" => TouchPhase::Move,
+            "touchend" | "touchcancel" => TouchPhase::End,
+            _ => return,
+        };
+        self.detected = true;
+
+        let rect = self.canvas.get_bounding_client_rect();
+        let height = rect.height().max(1.0) as f32;
+        let width = rect.width() as f32;
+
+        let changed = event.changed_touches();
+        for index in 0..changed.length() {
+            if let Some(touch) = changed.get(index) {
+                let pixel_x = touch.client_x() as f32 - rect.left() as f32;
+                let pixel_y = touch.client_y() as f32 - rect.top() as f32;
+                let position = (
+                    (2.0 * pixel_x - width) / height,
+                    1.0 - 2.0 * pixel_y / height,
+                );
+                self.events.push((phase, touch.identifier(), position));
+            }
+        }
+    }
+
+    /// Applies the touches to the first player's ship. When
+    /// `allow_taps` is true, new touches start the game instead.
+    /// Needs to be called after the gamepads are polled as they reset
+    /// the analog inputs.
+    pub fn poll(&mut self, key_map: &mut KeyMap, allow_taps: bool) {
+        for (phase, id, position) in self.events.drain(..) {
+            match phase {
+                TouchPhase::Start => {
+                    self.touches.push(ActiveTouch {
+                        id,
+                        start: position,
+                        position,
+                        is_tap: allow_taps,
+                    });
+                    if allow_taps {
+                        key_map.start_game = KeyState::JustPressed;
+                    }
+                }
+                TouchPhase::Move => {
+                    if let Some(touch) = self.touches.iter_mut().find(|touch| touch.id == id) {
+                        touch.position = position;
+                    }
+                }
+                TouchPhase::End => {
+                    if let Some(index) = self.touches.iter().position(|touch| touch.id == id) {
+                        let touch = self.touches.remove(index);
+                        // A finger that was steering when the race
+                        // ended shouldn't skip the score screen
+                        if touch.is_tap && allow_taps {
+                            key_map.start_game = KeyState::JustReleased;
+                        }
+                    }
+                }
+            }
+        }
+
+        let keys = &mut key_map.players[0];
+        for touch in self.touches.iter().filter(|touch| !touch.is_tap) {
+            if touch.start.0 < 0.0 {
+                keys.steering += (touch.start.0 - touch.position.0) / ZONE_RADIUS;
+            } else {
+                let slide_down = f32::max(touch.start.1 - touch.position.1, 0.0);
+                keys.throttle += 1.0 - 2.0 * slide_down / ZONE_RADIUS;
+            }
+        }
+        keys.steering = keys.steering.clamp(-1.0, 1.0);
+        keys.throttle = keys.throttle.clamp(-1.0, 1.0);
+    }
+
+    /// The zones to draw. A zone is drawn where each finger touched
+    /// down, and an idle one in the corner if nobody is using it.
+    pub fn get_zones(&self) -> Vec<TouchZone> {
+        let aspect = self.canvas.client_width() as f32 / self.canvas.client_height().max(1) as f32;
+
+        let mut zones = vec![];
+        let mut steering = false;
+        let mut throttle = false;
+        for touch in self.touches.iter().filter(|touch| !touch.is_tap) {
+            let offset = (
+                (touch.position.0 - touch.start.0) / ZONE_RADIUS,
+                (touch.position.1 - touch.start.1) / ZONE_RADIUS,
+            );
+            let knob = if touch.start.0 < 0.0 {
+                steering = true;
+                (offset.0.clamp(-1.0, 1.0), 0.0)
+            } else {
+                throttle = true;
+                (0.0, offset.1.clamp(-1.0, 0.0))
+            };
+            zones.push(TouchZone {
+                center: touch.start,
+                knob,
+                radius: ZONE_RADIUS,
+                color: ZONE_COLOR,
+            });
+        }
+
+        let corner_y = -1.0 + ZONE_INSET;
+        if !steering {
+            zones.push(TouchZone {
+                center: (-aspect + ZONE_INSET, corner_y),
+                knob: (0.0, 0.0),
+                radius: ZONE_RADIUS,
+                color: IDLE_ZONE_COLOR,
+            });
+        }
+        if !throttle {
+            zones.push(TouchZone {
+                center: (aspect - ZONE_INSET, corner_y),
+                knob: (0.0, 0.0),
+                radius: ZONE_RADIUS,
+                color: IDLE_ZONE_COLOR,
+            });
+        }
+        zones
+    }
+}
@@ -0,0 +1,91 @@
+use web_sys::{WebGl2RenderingContext, WebGlBuffer, WebGlProgram, WebGlUniformLocation};
+
+use super::shader::{init_shader_program, upload_array_f32, ShaderError};
+use super::touch::TouchZone;
+
+/// Draws the virtual steering and throttle zones over the top of the
+/// game.
+pub struct TouchSprite {
+    position_buffer: WebGlBuffer,
+    program: WebGlProgram,
+    attrib_vertex_positions: u32,
+
+    uniform_center: Option<WebGlUniformLocation>,
+    uniform_radius: Option<WebGlUniformLocation>,
+    uniform_screen_aspect: Option<WebGlUniformLocation>,
+    uniform_knob: Option<WebGlUniformLocation>,
+    uniform_zone_color: Option<WebGlUniformLocation>,
+}
+
+impl TouchSprite {
+    pub fn new(gl: &WebGl2RenderingContext) -> Result<Self, ShaderError> {
+        let position_buffer =
+            upload_array_f32(gl, vec![-1.0, 1.0, 1.0, 1.0, -1.0, -1.0, 1.0, -1.0])?;
+
+        let program = init_shader_program(
+            gl,
+            include_str!("resources/touch.vert"),
+            include_str!("resources/touch.frag"),
+        )?;
+
+        let attrib_vertex_positions = gl.get_attrib_location(&program, "aVertexPosition") as u32;
+
+        let uniform_center = gl.get_uniform_location(&program, "center");
+        let uniform_radius = gl.get_uniform_location(&program, "radius");
+        let uniform_screen_aspect = gl.get_uniform_location(&program, "screen_aspect");
+        let uniform_knob = gl.get_uniform_location(&program, "knob");
+        let uniform_zone_color = gl.get_uniform_location(&program, "zone_color");
+
+        Ok(Self {
+            position_buffer,
+            program,
+            attrib_vertex_positions,
+
+            uniform_center,
+            uniform_radius,
+            uniform_screen_aspect,
+            uniform_knob,
+            uniform_zone_color,
+        })
+    }
+
+    pub fn setup(&mut self, gl: &WebGl2RenderingContext) {
+        gl.use_program(Some(&self.program));
+        gl.blend_func(WebGl2RenderingContext::ONE, WebGl2RenderingContext::ONE);
+
+        gl.bind_buffer(
+            WebGl2RenderingContext::ARRAY_BUFFER,
+            Some(&self.position_buffer),
+        );
+
+        gl.vertex_attrib_pointer_with_i32(
+            self.attrib_vertex_positions,
+            2, // num components
+            WebGl2RenderingContext::FLOAT,
+            false, // normalize
+            0,     // stride
+            0,     // offset
+        );
+        gl.enable_vertex_attrib_array(self.attrib_vertex_positions);
+    }
+
+    pub fn render(&mut self, gl: &WebGl2RenderingContext, zone: &TouchZone, screen_aspect: f32) {
+        gl.uniform2f(self.uniform_center.as_ref(), zone.center.0, zone.center.1);
+        gl.uniform1f(self.uniform_radius.as_ref(), zone.radius);
+        gl.uniform1f(self.uniform_screen_aspect.as_ref(), screen_aspect);
+        gl.uniform2f(self.uniform_knob.as_ref(), zone.knob.0, zone.knob.1);
+        gl.uniform4f(
+            self.uniform_zone_color.as_ref(),
+            zone.color[0],
+            zone.color[1],
+            zone.color[2],
+            zone.color[3],
+        );
+
+        gl.draw_arrays(
+            WebGl2RenderingContext::TRIANGLE_STRIP,
+            0, //offset,
+            4, // vertex count
+        );
+    }
+}
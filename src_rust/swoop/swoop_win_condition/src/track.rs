@@ -0,0 +1,160 @@
+//! A track that can be shared: the shape of the map along with its
+//! name, who made it and how many laps make a race.
+//!
+//! Tracks are written on a single line with no spaces so that they
+//! can be put in the canvas options, eg
+//! `SWK1;name=Oval;author=Swoop;laps=8;map=SWT2;width=1;...`. The map
+//! (see `Map::to_string`) is always last as it has pairs of its own.
+//!
+//! This is a format of its own rather than JSON or RON because a track
+//! has to survive being pasted into chat or an HTML attribute, where
+//! quotes, braces and spaces get mangled, and because the simulation
+//! has no dependencies (so it builds for the headless tests and the
+//! tuner as well as the browser). The maps, replays and AI parameters
+//! use the same kind of format.
+use std::fmt;
+use std::str::FromStr;
+
+use super::gameplay::NUM_LAPS_TO_WIN;
+use super::map::{Map, MapError};
+
+/// Increment this if the format of shared tracks changes
+const TRACK_VERSION: &str = "SWK1";
+
+/// The tracks that come with the game, one per line
+const BUILT_IN_TRACKS: &str = include_str!("resources/tracks.txt");
+
+/// Names and authors have to fit on the score screen
+pub const MAX_NAME_LENGTH: usize = 16;
+
+pub const MAX_LAPS: usize = 20;
+
+/// How many laps a race has when the track doesn't say. The first
+/// crossing of the start line doesn't count as a lap.
+const DEFAULT_LAPS: usize = NUM_LAPS_TO_WIN - 1;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Track {
+    pub name: String,
+    pub author: String,
+    /// How many laps make a race
+    pub laps: usize,
+    pub map: Map,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum TrackError {
+    /// The string does not start with the expected version identifier
+    UnsupportedVersion(String),
+    /// A `key=value` pair that isn't part of a track
+    UnknownKey(String),
+    /// Names and authors can only use letters, numbers, `-` and `!`
+    InvalidName(String),
+    /// The number of laps is not a number from 1 to MAX_LAPS
+    InvalidLaps(String),
+    /// A value that every track needs is missing
+    MissingValue(&'static str),
+    InvalidMap(MapError),
+    /// There is no built-in track with the name
+    UnknownTrack(String),
+}
+
+impl Track {
+    pub fn new(name: &str, author: &str, map: Map) -> Self {
+        Self {
+            name: name.to_string(),
+            author: author.to_string(),
+            laps: DEFAULT_LAPS,
+            map,
+        }
+    }
+
+    /// Finds a built-in track by its name (ignoring case), or reads
+    /// the track if it is a whole track string
+    pub fn find(name: &str) -> Result<Self, TrackError> {
+        if name.starts_with(TRACK_VERSION) {
+            return name.parse();
+        }
+        built_in_tracks()
+            .into_iter()
+            .find(|track| track.name.eq_ignore_ascii_case(name))
+            .ok_or_else(|| TrackError::UnknownTrack(name.to_string()))
+    }
+}
+
+/// The tracks in `resources/tracks.txt`
+pub fn built_in_tracks() -> Vec<Track> {
+    BUILT_IN_TRACKS
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| line.parse().expect("Invalid built-in track"))
+        .collect()
+}
+
+impl fmt::Display for Track {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{};name={};author={};laps={};map={}",
+            TRACK_VERSION, self.name, self.author, self.laps, self.map
+        )
+    }
+}
+
+impl FromStr for Track {
+    type Err = TrackError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (version, mut rest) = s.split_once(';').unwrap_or((s, ""));
+        if version != TRACK_VERSION {
+            return Err(TrackError::UnsupportedVersion(version.to_string()));
+        }
+
+        let mut name = None;
+        let mut author = None;
+        let mut laps = DEFAULT_LAPS;
+        let mut map = None;
+        while !rest.is_empty() {
+            if let Some(map_string) = rest.strip_prefix("map=") {
+                map = Some(map_string.parse().map_err(TrackError::InvalidMap)?);
+                break;
+            }
+            let (pair, remainder) = rest.split_once(';').unwrap_or((rest, ""));
+            rest = remainder;
+
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            match key {
+                "name" => name = Some(parse_name(value)?),
+                "author" => author = Some(parse_name(value)?),
+                "laps" => {
+                    laps = value
+                        .parse()
+                        .ok()
+                        .filter(|laps| (1..=MAX_LAPS).contains(laps))
+                        .ok_or_else(|| TrackError::InvalidLaps(value.to_string()))?
+                }
+                "" => (),
+                _ => return Err(TrackError::UnknownKey(pair.to_string())),
+            }
+        }
+
+        Ok(Self {
+            name: name.ok_or(TrackError::MissingValue("name"))?,
+            author: author.ok_or(TrackError::MissingValue("author"))?,
+            laps,
+            map: map.ok_or(TrackError::MissingValue("map"))?,
+        })
+    }
+}
+
+/// Checks that a name can be shown by a TextBox and doesn't get in the
+/// way of reading the rest of the track
+fn parse_name(name: &str) -> Result<String, TrackError> {
+    let valid_char = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '!';
+    if name.is_empty() || name.len() > MAX_NAME_LENGTH || !name.chars().all(valid_char) {
+        return Err(TrackError::InvalidName(name.to_string()));
+    }
+    Ok(name.to_string())
+}
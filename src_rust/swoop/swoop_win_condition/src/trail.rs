@@ -45,7 +45,7 @@ impl Trail {
             self.path.clear();
             for _ in 0..self.max_length {
                 self.path.push_back(PathPoint {
-                    position: position,
+                    position,
                     tangent: (0.0, 0.0),
                     intensity: 0.0,
                     brightness: 0.0,
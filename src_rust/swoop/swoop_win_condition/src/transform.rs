@@ -2,7 +2,7 @@ pub type Vec2 = (f32, f32);
 
 /// A non-generic transform in 2D. Only supports rotations translations
 /// and a uniform scaling.
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub struct Transform2d {
     pub x: f32,
     pub y: f32,
@@ -27,7 +27,7 @@ impl PolarCoordinate {
         let angle = here.1.atan2(here.0);
         let radius = length(&here);
 
-        return Self { radius, angle };
+        Self { radius, angle }
     }
 }
 
@@ -55,6 +55,21 @@ impl Transform2d {
 
         (c * vec.0 + s * vec.1, -s * vec.0 + c * vec.1)
     }
+
+    /// Blends between this transform and another one. A fraction of
+    /// 0.0 gives this transform and 1.0 gives the other one. The
+    /// rotation takes the shortest way around.
+    pub fn lerp(&self, other: &Transform2d, fraction: f32) -> Transform2d {
+        let delta_rot = other.rot - self.rot;
+        let delta_rot = f32::atan2(f32::sin(delta_rot), f32::cos(delta_rot));
+
+        Transform2d {
+            x: self.x + (other.x - self.x) * fraction,
+            y: self.y + (other.y - self.y) * fraction,
+            rot: self.rot + delta_rot * fraction,
+            scale: self.scale + (other.scale - self.scale) * fraction,
+        }
+    }
 }
 
 pub fn vect_between(trans1: &Transform2d, trans2: &Transform2d) -> Vec2 {
@@ -0,0 +1,1375 @@
+use std::cmp::Ordering;
+use std::f32::consts::FRAC_PI_2;
+
+use swoop_win_condition::ai::calc_ai_control;
+use swoop_win_condition::ai_params::{
+    format_param_sets, parse_param_sets, AiParams, AiParamsError,
+};
+use swoop_win_condition::difficulty::Difficulty;
+use swoop_win_condition::editor::{EditorEvent, Handle, TrackEditor};
+use swoop_win_condition::feature::{FeatureKind, BOOST_ACCELERATION, MAX_FEATURES};
+use swoop_win_condition::gameplay::{GamePlay, NUM_LAPS_TO_WIN, SIM_TIME_STEP};
+use swoop_win_condition::headless::Simulation;
+use swoop_win_condition::keymap::{BindingError, Bindings, KeyMap, KeyState};
+use swoop_win_condition::map::{Map, MapError, NUM_SECTORS};
+use swoop_win_condition::network::{ServerMessage, Snapshot};
+use swoop_win_condition::options::GameOptions;
+use swoop_win_condition::physics::{calc_ship_physics, Broadphase};
+use swoop_win_condition::racing_line::TOP_SPEED;
+use swoop_win_condition::records::Records;
+use swoop_win_condition::replay::{Replay, ReplayError, MAX_REPLAY_TICKS};
+use swoop_win_condition::score::Score;
+use swoop_win_condition::ship::{Ship, LINEAR_DAMPING};
+use swoop_win_condition::spline::{Spline, SplineError, MAX_SPLINE_POINTS};
+use swoop_win_condition::storage::FileStorage;
+use swoop_win_condition::track::{built_in_tracks, Track, TrackError, MAX_LAPS};
+use swoop_win_condition::transform::Vec2;
+
+const SEEDS: [u32; 4] = [0, 1, 1234, 0xDEAD_BEEF];
+
+/// The best AI should comfortably finish the race within this time
+const MAX_RACE_TIME: f64 = 90.0;
+
+#[test]
+fn best_ai_finishes_race() {
+    for seed in SEEDS.iter() {
+        let mut sim = Simulation::new(*seed);
+        let max_ticks = (MAX_RACE_TIME / SIM_TIME_STEP) as u64;
+        let mut finish_time = None;
+
+        sim.run(max_ticks, |_tick, _keys, gameplay| {
+            let best_ai = gameplay.scores.last().unwrap();
+            if finish_time.is_none() && best_ai.laps.len() >= NUM_LAPS_TO_WIN {
+                finish_time = Some(gameplay.game_duration);
+            }
+        });
+        assert!(
+            finish_time.is_some(),
+            "Best AI did not finish within {}s on seed {}",
+            MAX_RACE_TIME,
+            seed
+        );
+    }
+}
+
+#[test]
+fn no_ship_leaves_the_track() {
+    for seed in SEEDS.iter() {
+        let mut sim = Simulation::new(*seed);
+        let ticks = (60.0 / SIM_TIME_STEP) as u64;
+
+        sim.run(ticks, |tick, keys, gameplay| {
+            // The player holds the throttle down and weaves around
+            keys.players[0].forwards = KeyState::Down;
+            keys.players[0].turn_left = if (tick / 90) % 2 == 0 {
+                KeyState::Down
+            } else {
+                KeyState::Up
+            };
+
+            for ship in gameplay.ship_entities.iter() {
+                let sdf = gameplay
+                    .map
+                    .distance_field((ship.position.x, ship.position.y));
+                assert!(sdf < 0.0, "Ship left the track on seed {}", seed);
+            }
+        });
+    }
+}
+
+#[test]
+fn same_seed_gives_same_race() {
+    let mut sim1 = Simulation::new(42);
+    let mut sim2 = Simulation::new(42);
+    let ticks = (20.0 / SIM_TIME_STEP) as u64;
+
+    sim1.run(ticks, |_, keys, _| {
+        keys.players[0].forwards = KeyState::Down
+    });
+    sim2.run(ticks, |_, keys, _| {
+        keys.players[0].forwards = KeyState::Down
+    });
+
+    for (ship1, ship2) in sim1
+        .gameplay
+        .ship_entities
+        .iter()
+        .zip(sim2.gameplay.ship_entities.iter())
+    {
+        assert_eq!(ship1.position.x, ship2.position.x);
+        assert_eq!(ship1.position.y, ship2.position.y);
+    }
+}
+
+#[test]
+fn frame_rate_does_not_change_race() {
+    let mut slow_screen = GamePlay::new();
+    let mut fast_screen = GamePlay::new();
+    slow_screen.reset(7);
+    fast_screen.reset(7);
+    let key_map = KeyMap::new();
+
+    for _ in 0..(60 * 20) {
+        slow_screen.update(1.0 / 60.0, &key_map);
+    }
+    for _ in 0..(144 * 20) {
+        fast_screen.update(1.0 / 144.0, &key_map);
+    }
+
+    // Floating point error in the frame times may leave one screen a
+    // step behind the other. Once they are at the same step the races
+    // should be identical.
+    assert!(slow_screen.tick.max(fast_screen.tick) - slow_screen.tick.min(fast_screen.tick) <= 1);
+    while slow_screen.tick < fast_screen.tick {
+        slow_screen.update(SIM_TIME_STEP / 10.0, &key_map);
+    }
+    while fast_screen.tick < slow_screen.tick {
+        fast_screen.update(SIM_TIME_STEP / 10.0, &key_map);
+    }
+    assert_eq!(slow_screen.tick, fast_screen.tick);
+    for (ship1, ship2) in slow_screen
+        .ship_entities
+        .iter()
+        .zip(fast_screen.ship_entities.iter())
+    {
+        assert_eq!(
+            (ship1.position.x, ship1.position.y, ship1.position.rot),
+            (ship2.position.x, ship2.position.y, ship2.position.rot)
+        );
+        assert_eq!(
+            (ship1.velocity.x, ship1.velocity.y, ship1.velocity.rot),
+            (ship2.velocity.x, ship2.velocity.y, ship2.velocity.rot)
+        );
+    }
+}
+
+#[test]
+fn replay_reproduces_race() {
+    let mut sim = Simulation::new(99);
+    let ticks = (30.0 / SIM_TIME_STEP) as u64;
+    sim.run(ticks, |tick, keys, _| {
+        keys.players[0].forwards = KeyState::Down;
+        keys.players[0].turn_right = if (tick / 50) % 3 == 0 {
+            KeyState::Down
+        } else {
+            KeyState::Up
+        };
+    });
+
+    // Send the replay through a string as if it had been shared
+    let replay: Replay = sim.gameplay.recording.to_string().parse().unwrap();
+    assert_eq!(replay.len(), ticks);
+
+    let mut playback = Simulation::new(0);
+    playback.gameplay.start_playback(replay);
+    playback.run(ticks, |_, _, _| {});
+    assert!(playback.gameplay.playback_finished());
+
+    for (ship1, ship2) in sim
+        .gameplay
+        .ship_entities
+        .iter()
+        .zip(playback.gameplay.ship_entities.iter())
+    {
+        assert_eq!(ship1.position.x, ship2.position.x);
+        assert_eq!(ship1.position.y, ship2.position.y);
+    }
+}
+
+#[test]
+fn analog_input_survives_replay() {
+    let mut sim = Simulation::new(42);
+    let ticks = (20.0 / SIM_TIME_STEP) as u64;
+    sim.run(ticks, |tick, keys, _| {
+        keys.players[0].throttle = 0.6;
+        keys.players[0].steering = f32::sin(tick as f32 * 0.01) * 0.8;
+    });
+
+    let replay: Replay = sim.gameplay.recording.to_string().parse().unwrap();
+    let mut playback = Simulation::new(0);
+    playback.gameplay.start_playback(replay);
+    playback.run(ticks, |_, _, _| {});
+
+    let ship1 = &sim.gameplay.ship_entities[0];
+    let ship2 = &playback.gameplay.ship_entities[0];
+    assert_eq!(ship1.position.x, ship2.position.x);
+    assert_eq!(ship1.position.y, ship2.position.y);
+}
+
+#[test]
+fn replays_only_read_the_current_version() {
+    assert!(matches!(
+        "SWR1.0000000.b10f5".parse::<Replay>(),
+        Err(ReplayError::UnsupportedVersion(_))
+    ));
+    assert!(matches!(
+        "SWR3.0000000.X.aa10".parse::<Replay>(),
+        Err(ReplayError::InvalidSettings(_))
+    ));
+}
+
+#[test]
+fn finished_races_stop_recording() {
+    let mut sim = Simulation::new(0);
+    sim.run(100, |_tick, _keys, _gameplay| {});
+    assert_eq!(sim.gameplay.recording.len(), 100);
+
+    // The ships keep driving around behind the score screen, but the
+    // replay shouldn't keep growing
+    let laps_to_win = sim.gameplay.laps_to_win;
+    for score in sim.gameplay.scores.iter_mut() {
+        score.laps = vec![0.0; laps_to_win];
+    }
+    assert!(sim.gameplay.game_complete());
+    sim.run(100, |_tick, _keys, _gameplay| {});
+    assert_eq!(sim.gameplay.recording.len(), 100);
+}
+
+#[test]
+fn replays_with_huge_inputs_are_rejected() {
+    // More steps than fit in memory, and more than fit in a usize
+    let too_long = format!("SWR3.0000000.N.aa{}", MAX_REPLAY_TICKS + 1);
+    let overflowing = format!("SWR3.0000000.N.aa1ab{}", usize::MAX);
+    for replay in [too_long, overflowing].iter() {
+        assert!(matches!(
+            replay.parse::<Replay>(),
+            Err(ReplayError::InvalidInputs(_))
+        ));
+    }
+
+    let longest: Replay = format!("SWR3.0000000.N.aa{}", MAX_REPLAY_TICKS)
+        .parse()
+        .unwrap();
+    assert_eq!(longest.len(), MAX_REPLAY_TICKS as u64);
+}
+
+#[test]
+fn rebound_keys_steer_the_ship() {
+    let mut keys = KeyMap::new();
+    keys.bindings.bind(0, 0, "KeyT").unwrap();
+
+    keys.set_state_from_str("KeyT", KeyState::JustPressed);
+    assert_eq!(keys.players[0].get_linear_thrust(), 1.0);
+
+    // The old key no longer does anything
+    keys.set_state_from_str("KeyT", KeyState::JustReleased);
+    keys.set_state_from_str("KeyW", KeyState::JustPressed);
+    assert_eq!(keys.players[0].get_linear_thrust(), 0.0);
+
+    // And the new bindings are remembered
+    let saved: Bindings = keys.bindings.to_string().parse().unwrap();
+    assert_eq!(saved, keys.bindings);
+}
+
+#[test]
+fn conflicting_bindings_are_rejected() {
+    let mut bindings = Bindings::defaults();
+    assert_eq!(
+        bindings.bind(0, 0, "ArrowLeft"),
+        Err(BindingError::AlreadyBound {
+            player: 1,
+            action: 2
+        })
+    );
+    assert_eq!(
+        bindings.bind(0, 0, "Enter"),
+        Err(BindingError::Reserved("Enter".to_string()))
+    );
+    assert_eq!(bindings, Bindings::defaults());
+
+    let saved = "KeyW,KeyS,KeyA,KeyW";
+    assert!(saved.parse::<Bindings>().is_err());
+}
+
+/// Moves the ship to the middle of the track, `fraction` of the way
+/// around the lap, and updates the score
+fn move_ship_to(fraction: f32, map: &Map, ship: &mut Ship, score: &mut Score) {
+    let angle = map.get_start_position().angle - fraction * std::f32::consts::PI * 2.0;
+    let radius = map.track_radius(angle);
+    ship.position.x = f32::cos(angle) * radius;
+    ship.position.y = f32::sin(angle) * radius;
+    score.update(map, ship, fraction as f64);
+}
+
+/// Moves the ship in small steps from one fraction of a lap to another
+fn drive_ship(from: f32, to: f32, map: &Map, ship: &mut Ship, score: &mut Score) {
+    let steps = (f32::abs(to - from) / 0.001) as usize;
+    for step in 0..=steps {
+        let fraction = from + (to - from) * step as f32 / steps as f32;
+        move_ship_to(fraction, map, ship, score);
+    }
+}
+
+#[test]
+fn laps_need_every_sector_in_order() {
+    let map = Simulation::new(1234).gameplay.map;
+    let mut ship = Ship::new((1.0, 1.0, 1.0, 1.0));
+    let mut score = Score::new();
+    move_ship_to(-0.01, &map, &mut ship, &mut score);
+    score.reset(&map, &ship);
+
+    // Crossing the start line for the first time starts the race
+    drive_ship(-0.01, 0.05, &map, &mut ship, &mut score);
+    assert_eq!(score.laps.len(), 1);
+
+    // Wiggling back and forth over the line does nothing
+    for _ in 0..3 {
+        drive_ship(0.05, -0.05, &map, &mut ship, &mut score);
+        drive_ship(-0.05, 0.05, &map, &mut ship, &mut score);
+    }
+    assert_eq!(score.laps.len(), 1);
+
+    // A whole lap counts
+    drive_ship(0.05, 1.05, &map, &mut ship, &mut score);
+    assert_eq!(score.laps.len(), 2);
+
+    // Skipping a sector doesn't
+    let sector = 1.0 / NUM_SECTORS as f32;
+    drive_ship(0.05, 2.5 * sector, &map, &mut ship, &mut score);
+    move_ship_to(4.5 * sector, &map, &mut ship, &mut score);
+    drive_ship(4.5 * sector, 1.05, &map, &mut ship, &mut score);
+    assert_eq!(score.laps.len(), 2);
+
+    // Nor does going back across the line and forwards again
+    drive_ship(0.05, -0.05, &map, &mut ship, &mut score);
+    drive_ship(-0.05, 0.05, &map, &mut ship, &mut score);
+    assert_eq!(score.laps.len(), 2);
+
+    // But once all the sectors have been passed again, it does
+    drive_ship(0.05, 1.05, &map, &mut ship, &mut score);
+    assert_eq!(score.laps.len(), 3);
+}
+
+#[test]
+fn race_order_follows_track_progress() {
+    let map = Simulation::new(1234).gameplay.map;
+    let mut ships = vec![];
+    for _ in 0..2 {
+        let mut ship = Ship::new((1.0, 1.0, 1.0, 1.0));
+        let mut score = Score::new();
+        move_ship_to(-0.01, &map, &mut ship, &mut score);
+        score.reset(&map, &ship);
+        ships.push((ship, score));
+    }
+    let (ship, score) = &mut ships[0];
+    drive_ship(-0.01, 0.3, &map, ship, score);
+    let (ship, score) = &mut ships[1];
+    drive_ship(-0.01, 0.6, &map, ship, score);
+
+    assert!((ships[0].1.progress - 0.3).abs() < 0.01);
+    assert!((ships[1].1.progress - 0.6).abs() < 0.01);
+    assert_eq!(ships[1].1.cmp_position(&ships[0].1), Ordering::Less);
+
+    // Cutting across the track doesn't count as progress
+    let (ship, score) = &mut ships[0];
+    move_ship_to(0.9, &map, ship, score);
+    assert!(score.progress < 0.6);
+    assert_eq!(ships[1].1.cmp_position(&ships[0].1), Ordering::Less);
+}
+
+#[test]
+fn sector_times_add_up_to_lap_times() {
+    let mut sim = Simulation::new(1);
+    sim.run(
+        (MAX_RACE_TIME / SIM_TIME_STEP) as u64,
+        |_tick, _keys, _gameplay| {},
+    );
+
+    let score = sim.gameplay.scores.last().unwrap();
+    let lap_times = score.get_lap_timings();
+    assert!(lap_times.len() >= NUM_LAPS_TO_WIN - 1);
+
+    for (lap, lap_time) in lap_times.iter().enumerate() {
+        let splits = score.get_lap_splits(lap);
+        assert_eq!(splits.len(), NUM_SECTORS);
+        assert!((splits[NUM_SECTORS - 1] - lap_time).abs() < 1e-9);
+
+        let sectors = &score.get_sector_times()[lap * NUM_SECTORS..(lap + 1) * NUM_SECTORS];
+        assert!((sectors.iter().sum::<f64>() - lap_time).abs() < 1e-9);
+    }
+
+    let theoretical_best = score.get_theoretical_best_lap().unwrap();
+    assert!(theoretical_best <= score.get_best_lap().unwrap() + 1e-9);
+}
+
+#[test]
+fn split_screen_players_drive_their_own_ships() {
+    let mut sim = Simulation::with_players(21, 2);
+    let ticks = (20.0 / SIM_TIME_STEP) as u64;
+    sim.run(ticks, |tick, keys, _| {
+        // Player one uses WASD and player two the arrow keys
+        keys.set_state_from_str("KeyW", KeyState::Down);
+        keys.set_state_from_str("ArrowUp", KeyState::Down);
+        let state = if (tick / 60) % 2 == 0 {
+            KeyState::Down
+        } else {
+            KeyState::Up
+        };
+        keys.set_state_from_str("ArrowLeft", state);
+    });
+
+    let ships = &sim.gameplay.ship_entities;
+    assert!(ships[0].position.x != ships[1].position.x);
+
+    let replay: Replay = sim.gameplay.recording.to_string().parse().unwrap();
+    assert_eq!(replay.num_players(), 2);
+
+    let mut playback = Simulation::new(0);
+    playback.gameplay.start_playback(replay);
+    playback.run(ticks, |_, _, _| {});
+
+    for (ship1, ship2) in ships.iter().zip(playback.gameplay.ship_entities.iter()) {
+        assert_eq!(ship1.position.x, ship2.position.x);
+        assert_eq!(ship1.position.y, ship2.position.y);
+    }
+}
+
+/// Presses the keys that the AI would use to drive the player's ship
+fn drive_like_ai(keys: &mut KeyMap, gameplay: &GamePlay) {
+    let mut ship = gameplay.ship_entities[0].clone();
+    calc_ai_control(
+        &mut ship,
+        0.7,
+        &gameplay.ai_params,
+        &gameplay.map,
+        &gameplay.racing_line,
+        &gameplay.ship_entities,
+        0,
+    );
+
+    let to_state = |active: bool| if active { KeyState::Down } else { KeyState::Up };
+    keys.players[0].forwards = to_state(ship.linear_thrust > 0.5);
+    keys.players[0].turn_left = to_state(ship.angular_thrust > 0.3);
+    keys.players[0].turn_right = to_state(ship.angular_thrust < -0.3);
+}
+
+#[test]
+fn ghost_follows_best_lap() {
+    let mut sim = Simulation::new(5);
+    let mut ghost_seen = false;
+
+    sim.run((60.0 / SIM_TIME_STEP) as u64, |_, keys, gameplay| {
+        drive_like_ai(keys, gameplay);
+        ghost_seen |= gameplay.ghost.visible;
+    });
+
+    let player_best = sim.gameplay.scores[0].get_best_lap().unwrap();
+    let ghost_best = sim.gameplay.ghost.get_best_lap().unwrap();
+    assert!(f64::abs(player_best - ghost_best.time) < 1e-6);
+    assert!(ghost_seen);
+
+    // The ghost is remembered when racing the same track again
+    sim.gameplay.reset(5);
+    assert!(sim.gameplay.ghost.get_best_lap().is_some());
+    sim.gameplay.reset(6);
+    assert!(sim.gameplay.ghost.get_best_lap().is_none());
+
+    // A different track with the same seed (as every custom track has)
+    // has a ghost of its own
+    sim.gameplay.reset(5);
+    let mut custom = sim.gameplay.map.clone();
+    custom.track_width *= 0.9;
+    assert_eq!(custom.seed, 5);
+    sim.gameplay.reset_on_map(custom);
+    assert!(sim.gameplay.ghost.get_best_lap().is_none());
+}
+
+#[test]
+fn records_are_kept_between_games() {
+    let mut sim = Simulation::new(99);
+    let finished = sim.run_until_complete(
+        (2.0 * MAX_RACE_TIME / SIM_TIME_STEP) as u64,
+        |_tick, keys, gameplay| drive_like_ai(keys, gameplay),
+    );
+    assert!(finished);
+
+    let mut records = Records::empty();
+    let new_records = records.record_race(&sim.gameplay, 0);
+    assert!(new_records.best_lap && new_records.best_race);
+
+    // Matching a record doesn't beat it
+    let new_records = records.record_race(&sim.gameplay, 0);
+    assert!(!new_records.any());
+
+    let track = records.get(&sim.gameplay.get_track_id()).unwrap();
+    assert_eq!(track.best_lap, sim.gameplay.scores[0].get_best_lap());
+    assert_eq!(track.positions.iter().sum::<u32>(), 2);
+
+    let directory = std::env::temp_dir().join(format!("swoop_records_{}", std::process::id()));
+    let mut storage = FileStorage::new(&directory);
+    assert!(records.save(&mut storage));
+    let loaded = Records::load(&storage).unwrap();
+    std::fs::remove_dir_all(&directory).unwrap();
+    assert_eq!(loaded, records);
+
+    assert!("SWH0\n".parse::<Records>().is_err());
+}
+
+#[test]
+fn races_on_built_in_tracks_are_recorded() {
+    let mut track = Track::find("oval").unwrap();
+    track.laps = 1;
+    let mut sim = Simulation::new(0);
+    sim.gameplay.reset_on_track(&track);
+    let finished = sim.run_until_complete(
+        (MAX_RACE_TIME / SIM_TIME_STEP) as u64,
+        |_tick, keys, gameplay| drive_like_ai(keys, gameplay),
+    );
+    assert!(finished);
+
+    // The track has its own records, rather than sharing them with the
+    // generated track that has the same seed
+    let mut records = Records::empty();
+    assert!(records.record_race(&sim.gameplay, 0).best_race);
+    let record = records.get(&track.to_string()).unwrap();
+    assert_eq!(record.get_name(), "Oval");
+    assert!(records.get("0000000").is_none());
+    let saved = records.to_string();
+    assert_eq!(saved.parse::<Records>().unwrap(), records);
+
+    // The replay races on the same track
+    let replay: Replay = sim.gameplay.recording.to_string().parse().unwrap();
+    assert_eq!(replay.track, Some(track));
+    let mut playback = Simulation::new(0);
+    playback.gameplay.start_playback(replay);
+    assert!(playback.run_until_complete(sim.tick, |_, _, _| {}));
+    assert_eq!(
+        playback.gameplay.scores[0].laps,
+        sim.gameplay.scores[0].laps
+    );
+}
+
+#[test]
+fn racing_line_stays_on_the_track() {
+    for seed in SEEDS.iter() {
+        let gameplay = Simulation::new(*seed).gameplay;
+        let points = &gameplay.racing_line.points;
+
+        for point in points.iter() {
+            assert!(gameplay.map.distance_field(point.position) < 0.0);
+            assert!(point.target_speed > 0.0 && point.target_speed <= TOP_SPEED);
+        }
+
+        // Every track has at least one corner that needs braking for
+        let slowest = points
+            .iter()
+            .map(|point| point.target_speed)
+            .fold(TOP_SPEED, f32::min);
+        assert!(slowest < TOP_SPEED * 0.9, "No braking on seed {}", seed);
+    }
+}
+
+#[test]
+fn ai_drives_around_a_stopped_ship() {
+    for seed in SEEDS.iter() {
+        let gameplay = Simulation::new(*seed).gameplay;
+        let (map, racing_line) = (&gameplay.map, &gameplay.racing_line);
+        let params = &gameplay.ai_params;
+
+        // A ship has stopped on the racing line and the AI is coming up
+        // behind it at full speed
+        let place_ship = |index: usize, speed: f32| {
+            let mut ship = Ship::new((1.0, 1.0, 1.0, 1.0));
+            let point = &racing_line.points[index];
+            let direction = racing_line.get_direction(index);
+            ship.position.x = point.position.0;
+            ship.position.y = point.position.1;
+            ship.position.rot = f32::atan2(-direction.0, direction.1);
+            ship.velocity.x = direction.0 * speed;
+            ship.velocity.y = direction.1 * speed;
+            ship
+        };
+        let speed = racing_line.points[10].target_speed;
+        let mut ships = vec![place_ship(10, speed), place_ship(40, 0.0)];
+
+        let mut broadphase = Broadphase::new(ships.len());
+        let mut closest = f32::MAX;
+        for _ in 0..(3.0 / SIM_TIME_STEP) as usize {
+            let snapshot = ships.clone();
+            calc_ai_control(&mut ships[0], 0.7, params, map, racing_line, &snapshot, 0);
+            calc_ship_physics(
+                &mut ships,
+                map,
+                SIM_TIME_STEP as f32,
+                &mut broadphase,
+                &mut vec![],
+            );
+
+            let gap = (
+                ships[0].position.x - ships[1].position.x,
+                ships[0].position.y - ships[1].position.y,
+            );
+            closest = closest.min((gap.0 * gap.0 + gap.1 * gap.1).sqrt());
+        }
+
+        assert!(closest > 0.1, "AI hit the stopped ship on seed {}", seed);
+        let ai_index = racing_line.get_index(map, (ships[0].position.x, ships[0].position.y));
+        assert!(ai_index > 40, "AI didn't get past on seed {}", seed);
+    }
+}
+
+#[test]
+fn ships_bounce_off_each_other() {
+    let map = Simulation::new(0).gameplay.map;
+    let (start, _) = map.get_start_line();
+    let dt = SIM_TIME_STEP as f32;
+
+    // A ship drives into the side of a heavier one that is stopped,
+    // `height` off centre
+    let bump = |height: f32, restitution: f32| {
+        let mut ships = vec![
+            Ship::new((1.0, 1.0, 1.0, 1.0)),
+            Ship::new((1.0, 1.0, 1.0, 1.0)),
+        ];
+        ships[0].position.x = start.0 + 0.095;
+        ships[0].position.y = start.1 + height;
+        ships[0].velocity.x = -2.0;
+        ships[1].position.x = start.0;
+        ships[1].position.y = start.1;
+        ships[1].mass = 4.0;
+        for ship in ships.iter_mut() {
+            ship.restitution = restitution;
+        }
+        let mut collisions = vec![];
+        calc_ship_physics(
+            &mut ships,
+            &map,
+            dt,
+            &mut Broadphase::new(2),
+            &mut collisions,
+        );
+        (ships, collisions)
+    };
+
+    let (ships, collisions) = bump(0.0, 0.5);
+    assert_eq!(collisions.len(), 1);
+    assert_eq!(collisions[0].ships, (0, 1));
+    assert!(collisions[0].impulse > 0.0);
+    assert!((collisions[0].normal.0 - 1.0).abs() < 1e-4);
+
+    // Momentum is kept (apart from the damping), and the lighter ship
+    // bounces back off the heavier one
+    let momentum = ships[0].velocity.x * ships[0].mass + ships[1].velocity.x * ships[1].mass;
+    assert!((momentum - -2.0 * (1.0 - LINEAR_DAMPING * dt)).abs() < 1e-4);
+    assert!(ships[0].velocity.x > 0.0);
+    assert!(ships[1].velocity.x < 0.0);
+    assert_eq!(ships[0].velocity.rot, 0.0);
+
+    // Bouncier ships come apart faster
+    let separation = |(ships, _): (Vec<Ship>, _)| ships[0].velocity.x - ships[1].velocity.x;
+    assert!(separation(bump(0.0, 1.0)) > separation(bump(0.0, 0.0)));
+    assert!(separation(bump(0.0, 0.0)).abs() < 1e-4);
+
+    // A glancing blow pushes the ships sideways and spins them
+    let (ships, _) = bump(0.06, 0.5);
+    assert!(ships[0].velocity.y > 0.0);
+    assert!(ships[1].velocity.y < 0.0);
+    assert!(ships[0].velocity.rot != 0.0);
+    assert!(ships[1].velocity.rot != 0.0);
+
+    // Ships already moving apart are only pushed apart
+    let (_, collisions) = {
+        let mut ships = vec![
+            Ship::new((1.0, 1.0, 1.0, 1.0)),
+            Ship::new((1.0, 1.0, 1.0, 1.0)),
+        ];
+        ships[0].position.x = start.0 + 0.05;
+        ships[0].position.y = start.1;
+        ships[0].velocity.x = 1.0;
+        ships[1].position.x = start.0;
+        ships[1].position.y = start.1;
+        let mut collisions = vec![];
+        calc_ship_physics(
+            &mut ships,
+            &map,
+            dt,
+            &mut Broadphase::new(2),
+            &mut collisions,
+        );
+        (ships, collisions)
+    };
+    assert_eq!(collisions[0].impulse, 0.0);
+
+    // The race keeps the bumps from the latest step, such as two ships
+    // meeting head on
+    let mut sim = Simulation::new(0);
+    sim.gameplay.game_duration = 0.0;
+    for (id, side) in [(0, 1.0), (1, -1.0)].iter() {
+        let ship = &mut sim.gameplay.ship_entities[*id];
+        ship.position.x = start.0 + side * 0.045;
+        ship.position.y = start.1;
+        ship.velocity.x = -side * 2.0;
+        ship.velocity.y = 0.0;
+    }
+    sim.gameplay.simulate(&[]);
+    let bump = sim
+        .gameplay
+        .collisions
+        .iter()
+        .find(|collision| collision.ships == (0, 1))
+        .expect("No bump between the ships");
+    assert!(bump.impulse > 0.0);
+    assert!(sim.gameplay.ship_entities[0].velocity.x > 0.0);
+    assert!(sim.gameplay.ship_entities[1].velocity.x < 0.0);
+
+    sim.gameplay.simulate(&[]);
+    assert!(sim
+        .gameplay
+        .collisions
+        .iter()
+        .all(|collision| collision.ships != (0, 1)));
+}
+
+#[test]
+fn broadphase_finds_every_bump() {
+    let map = Simulation::new(0).gameplay.map;
+
+    // Pairs of ships spread over a grid, some close enough to touch.
+    // Each column of the grid lines up along the x axis.
+    let mut ships = vec![];
+    let mut touching = vec![];
+    for cell in 0..64 {
+        let corner = ((cell % 8) as f32 * 0.5, (cell / 8) as f32 * 0.5);
+        let angle = cell as f32 * 0.7;
+        let gap = 0.05 + (cell % 4) as f32 * 0.03;
+        for offset in [(0.0, 0.0), (f32::cos(angle) * gap, f32::sin(angle) * gap)].iter() {
+            let mut ship = Ship::new((1.0, 1.0, 1.0, 1.0));
+            ship.position.x = corner.0 + offset.0;
+            ship.position.y = corner.1 + offset.1;
+            ships.push(ship);
+        }
+        if gap < 0.1 {
+            touching.push((cell * 2, cell * 2 + 1));
+        }
+    }
+
+    let mut broadphase = Broadphase::new(ships.len());
+    let mut collisions = vec![];
+    calc_ship_physics(&mut ships, &map, 0.0, &mut broadphase, &mut collisions);
+    let mut found: Vec<(usize, usize)> = collisions.iter().map(|bump| bump.ships).collect();
+    found.sort_unstable();
+    assert_eq!(found, touching);
+
+    // Only ships lined up along the x axis are checked, rather than
+    // all 8128 pairs
+    assert!(broadphase.num_checks < 1000);
+}
+
+/// Runs a race between the AI ships for a while and returns how far
+/// the fastest one got
+fn furthest_ai_progress(difficulty: Difficulty, catch_up: bool) -> f64 {
+    let mut sim = Simulation::new(7);
+    sim.gameplay.difficulty = difficulty;
+    sim.gameplay.catch_up = catch_up;
+    sim.gameplay.reset(7);
+    sim.run((30.0 / SIM_TIME_STEP) as u64, |_, _, _| {});
+    sim.gameplay.scores[1..]
+        .iter()
+        .map(|score| score.progress)
+        .fold(f64::MIN, f64::max)
+}
+
+#[test]
+fn harder_ai_is_faster() {
+    let progress: Vec<f64> = Difficulty::ALL
+        .iter()
+        .map(|difficulty| furthest_ai_progress(*difficulty, false))
+        .collect();
+    for pair in progress.windows(2) {
+        assert!(pair[0] < pair[1], "{:?}", progress);
+    }
+
+    // The player never moves, so catch-up slows the AI down
+    assert!(furthest_ai_progress(Difficulty::Insane, true) < progress[3]);
+}
+
+#[test]
+fn ai_skills_cover_the_difficulty_with_any_number_of_players() {
+    for difficulty in Difficulty::ALL.iter() {
+        let (min_skill, max_skill) = difficulty.get_skill_range();
+        // With three players there is only one AI ship left, and it
+        // gets the lowest skill
+        for num_players in 1..=3 {
+            let sim = Simulation::with_players(0, num_players);
+            let num_ai = sim.gameplay.ship_entities.len() - sim.gameplay.num_players;
+            assert_eq!(difficulty.get_ai_skill(0, num_ai), min_skill);
+            if num_ai > 1 {
+                let top_skill = difficulty.get_ai_skill(num_ai - 1, num_ai);
+                assert!((top_skill - max_skill).abs() < 1e-6);
+            }
+        }
+    }
+}
+
+#[test]
+fn difficulty_is_kept_in_replays() {
+    let mut sim = Simulation::new(99);
+    sim.gameplay.difficulty = Difficulty::Hard;
+    sim.gameplay.catch_up = true;
+    sim.gameplay.reset(99);
+    let ticks = (20.0 / SIM_TIME_STEP) as u64;
+    sim.run(ticks, |_, keys, _| {
+        keys.players[0].forwards = KeyState::Down
+    });
+
+    let replay: Replay = sim.gameplay.recording.to_string().parse().unwrap();
+    assert_eq!(replay.difficulty, Difficulty::Hard);
+    assert!(replay.catch_up);
+
+    // The menu settings don't change what happens in the replay
+    let mut playback = Simulation::new(0);
+    playback.gameplay.start_playback(replay);
+    playback.run(ticks, |_, _, _| {});
+    for (ship1, ship2) in sim
+        .gameplay
+        .ship_entities
+        .iter()
+        .zip(playback.gameplay.ship_entities.iter())
+    {
+        assert_eq!(ship1.position.x, ship2.position.x);
+        assert_eq!(ship1.position.y, ship2.position.y);
+    }
+
+    let options = GameOptions::parse("difficulty=insane catch_up=on");
+    assert_eq!(options.difficulty, Some(Difficulty::Insane));
+    assert_eq!(options.catch_up, Some(true));
+}
+
+#[test]
+fn ai_params_survive_being_written_out() {
+    let sets: Vec<(Difficulty, AiParams)> = Difficulty::ALL
+        .iter()
+        .map(|difficulty| (*difficulty, AiParams::for_difficulty(*difficulty)))
+        .collect();
+    let text = format_param_sets(&sets);
+    assert_eq!(parse_param_sets(&text).unwrap(), sets);
+
+    // Missing parameters keep their defaults
+    let params: AiParams = "velocity_gain=5".parse().unwrap();
+    assert_eq!(params.velocity_gain, 5.0);
+    assert_eq!(params.follow_gain, AiParams::defaults().follow_gain);
+
+    assert_eq!(
+        parse_param_sets("[Easy]\nsteering=1"),
+        Err(AiParamsError::UnknownParam("steering=1".to_string()))
+    );
+    assert_eq!(
+        parse_param_sets("follow_gain=1"),
+        Err(AiParamsError::MissingDifficulty(
+            "follow_gain=1".to_string()
+        ))
+    );
+}
+
+/// A track with a hairpin that points back at the middle of the map,
+/// which a polar track can't have
+fn hairpin_track() -> Map {
+    let mut map = Simulation::new(0).gameplay.map;
+    let points = vec![
+        (0.0, 9.0),
+        (6.0, 7.5),
+        (9.0, 2.0),
+        (8.0, -5.0),
+        (2.0, -9.0),
+        (-5.0, -8.0),
+        (-9.0, -3.0),
+        (-6.0, -0.5),
+        (-1.0, 0.0),
+        (0.0, 2.0),
+        (-3.0, 3.5),
+        (-8.0, 4.0),
+        (-6.0, 8.0),
+    ];
+    map.spline = Some(Spline::new(points).unwrap());
+    map
+}
+
+#[test]
+fn spline_tracks_can_double_back() {
+    let map = hairpin_track();
+    let spline = map.spline.as_ref().unwrap();
+    for point in spline.get_points() {
+        assert!((map.distance_field(*point) + map.track_width).abs() < 1e-4);
+    }
+    // Between the two sides of the hairpin
+    assert!(map.distance_field((-5.0, 1.8)) > 0.0);
+    // Both sides of the hairpin are the same angle from the center,
+    // but a different distance around the lap
+    let inwards = map.calc_lap_fraction((-4.0, -0.2));
+    let outwards = map.calc_lap_fraction((-4.0, 3.7));
+    assert!(outwards - inwards > 0.05, "{} {}", inwards, outwards);
+
+    for i in 0..100 {
+        let fraction = i as f32 / 100.0;
+        let (position, across) = map.get_track_point(fraction);
+        assert!((map.calc_lap_fraction(position) - fraction).abs() < 1e-3);
+        let normal = map.calc_normal((position.0 + across.0 * 0.5, position.1 + across.1 * 0.5));
+        assert!(normal.0 * across.0 + normal.1 * across.1 > 0.9);
+    }
+
+    let (start, _) = map.get_start_line();
+    assert!((start.0 - 0.0).abs() < 1e-4 && (start.1 - 9.0).abs() < 1e-4);
+
+    assert_eq!(
+        Spline::new(vec![(0.0, 0.0), (1.0, 0.0)]).unwrap_err(),
+        SplineError::TooFewPoints(2)
+    );
+    assert_eq!(
+        Spline::new(vec![(0.0, 0.0); MAX_SPLINE_POINTS + 1]).unwrap_err(),
+        SplineError::TooManyPoints(MAX_SPLINE_POINTS + 1)
+    );
+    assert_eq!(
+        Spline::new(vec![(2.0, 1.0); 4]).unwrap_err(),
+        SplineError::Degenerate(0)
+    );
+    assert_eq!(
+        "SWT2;width=0.7;spline=0,0/0,0/0,0"
+            .parse::<Map>()
+            .unwrap_err(),
+        MapError::InvalidSpline(SplineError::Degenerate(0))
+    );
+}
+
+#[test]
+fn ai_races_on_spline_tracks() {
+    let mut sim = Simulation::new(0);
+    sim.gameplay.reset_on_map(hairpin_track());
+    for point in sim.gameplay.racing_line.points.iter() {
+        assert!(sim.gameplay.map.distance_field(point.position) < 0.0);
+    }
+
+    let max_ticks = (MAX_RACE_TIME / SIM_TIME_STEP) as u64;
+    sim.run(max_ticks, |_tick, _keys, gameplay| {
+        for ship in gameplay.ship_entities.iter() {
+            let sdf = gameplay
+                .map
+                .distance_field((ship.position.x, ship.position.y));
+            assert!(sdf < 0.0, "Ship left the spline track");
+        }
+    });
+    let best_ai = sim.gameplay.scores.last().unwrap();
+    assert!(best_ai.laps.len() >= NUM_LAPS_TO_WIN);
+}
+
+#[test]
+fn editor_handles_change_the_track() {
+    let map = Simulation::new(1234).gameplay.map;
+    let mut editor = TrackEditor::new(map.clone());
+    assert!(editor.take_changed());
+
+    let handle_position = |editor: &TrackEditor, handle| {
+        editor
+            .get_handles()
+            .into_iter()
+            .find(|(h, _)| *h == handle)
+            .unwrap()
+            .1
+    };
+
+    // Dragging a wave outwards makes it bigger
+    let wave = handle_position(&editor, Handle::Wave(2));
+    editor.pointer_down((wave.0 + 0.1, wave.1));
+    assert_eq!(editor.get_selected(), Some(Handle::Wave(2)));
+    editor.pointer_move((wave.0 * 1.2, wave.1 * 1.2));
+    editor.pointer_up();
+    let old_size = f32::hypot(map.sin_consts[2], map.cos_consts[2]);
+    let new_size = f32::hypot(
+        editor.get_map().sin_consts[2],
+        editor.get_map().cos_consts[2],
+    );
+    assert!(new_size > old_size);
+    assert!(editor.take_changed());
+    assert!(!editor.take_changed());
+
+    // Moving the pointer without a button down does nothing
+    editor.pointer_move((0.0, 0.0));
+    assert!(!editor.take_changed());
+
+    let start = handle_position(&editor, Handle::Width);
+    editor.pointer_down(start);
+    assert_eq!(editor.get_selected(), Some(Handle::Width));
+    let (center, across) = editor.get_map().get_track_point(0.0);
+    editor.pointer_move((center.0 + across.0 * 1.5, center.1 + across.1 * 1.5));
+    assert!((editor.get_map().track_width - 1.5).abs() < 1e-4);
+
+    // Clicking away from every handle selects nothing
+    editor.pointer_down((1000.0, 1000.0));
+    assert_eq!(editor.get_selected(), None);
+
+    assert_eq!(editor.key_pressed("Enter"), EditorEvent::TestDrive);
+    assert_eq!(editor.key_pressed("Escape"), EditorEvent::Exit);
+}
+
+#[test]
+fn editor_turns_tracks_into_splines() {
+    let map = Simulation::new(0).gameplay.map;
+    let mut editor = TrackEditor::new(map.clone());
+    assert_eq!(editor.key_pressed("KeyP"), EditorEvent::None);
+
+    // The spline follows the old track and starts in the same place
+    let spline = editor.get_map().spline.as_ref().unwrap();
+    for point in spline.get_points() {
+        assert!((map.distance_field(*point) + map.track_width).abs() < 1e-3);
+    }
+    let (old_start, _) = map.get_start_line();
+    let (new_start, _) = editor.get_map().get_start_line();
+    assert!((old_start.0 - new_start.0).abs() < 1e-3);
+    assert!((old_start.1 - new_start.1).abs() < 1e-3);
+
+    let num_points = spline.get_points().len();
+    let second = spline.get_points()[1];
+    editor.pointer_down(second);
+    assert_eq!(editor.get_selected(), Some(Handle::ControlPoint(1)));
+    editor.key_pressed("KeyA");
+    let spline = editor.get_map().spline.as_ref().unwrap();
+    assert_eq!(spline.get_points().len(), num_points + 1);
+    editor.key_pressed("KeyX");
+    editor.key_pressed("KeyX");
+    let spline = editor.get_map().spline.as_ref().unwrap();
+    assert_eq!(spline.get_points().len(), num_points);
+
+    // The track can be raced on straight away
+    let mut sim = Simulation::new(0);
+    sim.gameplay.reset_on_map(editor.get_map().clone());
+    let max_ticks = (MAX_RACE_TIME / SIM_TIME_STEP) as u64;
+    sim.run(max_ticks, |_tick, _keys, _gameplay| {});
+    let best_ai = sim.gameplay.scores.last().unwrap();
+    assert!(best_ai.laps.len() >= NUM_LAPS_TO_WIN);
+}
+
+#[test]
+fn maps_survive_being_written_out() {
+    let mut map = Simulation::new(1234).gameplay.map;
+    map.seed = 0;
+    map.start_angle = 0.5;
+    let read: Map = map.to_string().parse().unwrap();
+    assert_eq!(read, map);
+
+    // Spline tracks leave out the waves they don't use
+    let map = hairpin_track();
+    let read: Map = map.to_string().parse().unwrap();
+    assert_eq!(read.spline, map.spline);
+    assert_eq!(read.track_width, map.track_width);
+
+    assert_eq!(
+        "SWT1;width=1".parse::<Map>(),
+        Err(MapError::UnsupportedVersion("SWT1".to_string()))
+    );
+    assert_eq!(
+        "SWT2;width=1;height=2".parse::<Map>(),
+        Err(MapError::UnknownKey("height=2".to_string()))
+    );
+    assert_eq!(
+        "SWT2;width=1;spline=0,0/1,1".parse::<Map>(),
+        Err(MapError::InvalidSpline(SplineError::TooFewPoints(2)))
+    );
+    assert_eq!(
+        "SWT2;width=1;radius=8;sin=1,2".parse::<Map>(),
+        Err(MapError::InvalidValue("sin=1,2".to_string()))
+    );
+    assert_eq!(
+        "SWT2;width=1;radius=8".parse::<Map>(),
+        Err(MapError::MissingValue("sin and cos"))
+    );
+}
+
+#[test]
+fn tracks_can_change_width() {
+    // The narrows are widest where the track crosses the x axis and
+    // narrowest where it crosses the y axis
+    let map = Track::find("narrows").unwrap().map;
+    let wide = (map.track_radius(0.0), 0.0);
+    let narrow = (0.0, map.track_radius(FRAC_PI_2));
+    assert!((map.calc_track_width(wide) - 1.5).abs() < 1e-4);
+    assert!((map.calc_track_width(narrow) - 0.5).abs() < 1e-4);
+    assert!((map.distance_field(wide) + 1.5).abs() < 1e-3);
+    assert!((map.distance_field(narrow) + 0.5).abs() < 1e-3);
+    assert!(map.distance_field((wide.0 + 1.0, 0.0)) < 0.0);
+    assert!(map.distance_field((0.0, narrow.1 + 1.0)) > 0.0);
+
+    // Splines widen around each control point
+    let mut map = hairpin_track();
+    let spline = map.spline.take().unwrap();
+    let points = spline.get_points().to_vec();
+    let widths: Vec<f32> = (0..points.len()).map(|i| (i % 3) as f32 * 0.3).collect();
+    map.spline = Some(Spline::with_widths(points.clone(), widths.clone()).unwrap());
+    for (point, width) in points.iter().zip(widths.iter()) {
+        let expected = map.track_width + width;
+        assert!((map.distance_field(*point) + expected).abs() < 1e-3);
+    }
+    let read: Map = map.to_string().parse().unwrap();
+    assert_eq!(read.spline, map.spline);
+    assert_eq!(
+        Spline::with_widths(points, vec![0.0]).unwrap_err(),
+        SplineError::WrongNumberOfWidths {
+            points: widths.len(),
+            widths: 1
+        }
+    );
+
+    // Leaving the widths out keeps the track the same width all round
+    let even: Map = "SWT2;width=0.7;spline=0,9/6,7.5/9,2".parse().unwrap();
+    assert!(even.spline.unwrap().get_widths().iter().all(|w| *w == 0.0));
+    assert_eq!(
+        "SWT2;width=1;spline=0,9/6,7.5/9,2;widths=0,1.5,0".parse::<Map>(),
+        Err(MapError::OutOfRange("widths"))
+    );
+
+    // Starting on the narrowest part of the track still puts every
+    // ship on the track, and the AI can get through it
+    let mut track = Track::find("narrows").unwrap();
+    track.map.start_angle = FRAC_PI_2;
+    let mut sim = Simulation::new(0);
+    sim.gameplay.reset_on_track(&track);
+    for ship in sim.gameplay.ship_entities.iter() {
+        let sdf = sim
+            .gameplay
+            .map
+            .distance_field((ship.position.x, ship.position.y));
+        assert!(sdf < 0.0, "Ship started off the track");
+    }
+    for point in sim.gameplay.racing_line.points.iter() {
+        assert!(sim.gameplay.map.distance_field(point.position) < 0.0);
+    }
+    let max_ticks = (MAX_RACE_TIME / SIM_TIME_STEP) as u64;
+    sim.run(max_ticks, |_tick, _keys, _gameplay| {});
+    let best_ai = sim.gameplay.scores.last().unwrap();
+    assert!(best_ai.finished());
+}
+
+#[test]
+fn snapshots_keep_ships_in_the_air() {
+    let mut server = Simulation::new(0);
+    server.run(10, |_tick, _keys, _gameplay| {});
+    server.gameplay.ship_entities[1].airborne = 0.3;
+    let message = ServerMessage::Snapshot(Snapshot::capture(&server.gameplay)).to_string();
+
+    // A client that thought the ship had landed puts it back in the air
+    let snapshot = match message.parse::<ServerMessage>() {
+        Ok(ServerMessage::Snapshot(snapshot)) => snapshot,
+        _ => panic!("Snapshot couldn't be read back: {}", message),
+    };
+    let mut client = Simulation::new(0);
+    snapshot.restore(&mut client.gameplay);
+    assert_eq!(client.gameplay.ship_entities[1].airborne, 0.3);
+    assert_eq!(client.gameplay.ship_entities[0].airborne, 0.0);
+}
+
+#[test]
+fn features_change_how_ships_drive() {
+    let map = Track::find("stunts").unwrap().map;
+    let find = |kind| {
+        map.features
+            .iter()
+            .find(|feature| feature.kind == kind)
+            .map(|feature| map.get_feature_frame(feature))
+            .unwrap()
+    };
+    let (boost, forwards) = find(FeatureKind::Boost);
+    let surface = map.get_surface(boost);
+    assert!((surface.acceleration.0 - forwards.0 * BOOST_ACCELERATION).abs() < 1e-4);
+    assert!((surface.acceleration.1 - forwards.1 * BOOST_ACCELERATION).abs() < 1e-4);
+    let (start, _) = map.get_start_line();
+    let plain = map.get_surface(start);
+    assert_eq!(plain.acceleration, (0.0, 0.0));
+    assert_eq!(plain.damping, 0.0);
+    assert!(!plain.launch);
+
+    // A ship at full speed going the same way as the track
+    let ship_at = |position: Vec2, forwards: Vec2| {
+        let mut ship = Ship::new((1.0, 1.0, 1.0, 1.0));
+        ship.position.x = position.0;
+        ship.position.y = position.1;
+        ship.position.rot = f32::atan2(-forwards.0, forwards.1);
+        ship.velocity.x = forwards.0 * TOP_SPEED;
+        ship.velocity.y = forwards.1 * TOP_SPEED;
+        ship.linear_thrust = 1.0;
+        vec![ship]
+    };
+    let speed_after_step = |mut ships: Vec<Ship>| {
+        calc_ship_physics(
+            &mut ships,
+            &map,
+            SIM_TIME_STEP as f32,
+            &mut Broadphase::new(1),
+            &mut vec![],
+        );
+        f32::hypot(ships[0].velocity.x, ships[0].velocity.y)
+    };
+    assert!(speed_after_step(ship_at(boost, forwards)) > TOP_SPEED + 0.1);
+    let (sticky, forwards) = find(FeatureKind::Sticky);
+    assert!(speed_after_step(ship_at(sticky, forwards)) < TOP_SPEED - 0.1);
+
+    // Ships in the air can't use their engines and fly over others
+    let (jump, forwards) = find(FeatureKind::Jump);
+    let mut ships = ship_at(jump, forwards);
+    ships.push(ships[0].clone());
+    ships[1].position.x += 0.01;
+    calc_ship_physics(
+        &mut ships,
+        &map,
+        SIM_TIME_STEP as f32,
+        &mut Broadphase::new(2),
+        &mut vec![],
+    );
+    assert!(ships[0].airborne > 0.0);
+    assert!((ships[1].position.x - ships[0].position.x - 0.01).abs() < 1e-4);
+    ships[0].velocity.x = 0.0;
+    ships[0].velocity.y = 0.0;
+    ships[0].update(SIM_TIME_STEP as f32);
+    assert_eq!((ships[0].velocity.x, ships[0].velocity.y), (0.0, 0.0));
+
+    // The AI goes over the boost pads and around the sticky zone
+    let mut sim = Simulation::new(0);
+    sim.gameplay.reset_on_map(map.clone());
+    let line = &sim.gameplay.racing_line;
+    let on_line = |position: Vec2| {
+        let index = line.get_index(&map, position);
+        map.get_surface(line.points[index].position)
+    };
+    assert!(on_line(boost).acceleration != (0.0, 0.0));
+    assert_eq!(on_line(sticky).damping, 0.0);
+    assert!(on_line(jump).launch);
+    assert!(line
+        .points
+        .iter()
+        .any(|point| point.target_speed > TOP_SPEED));
+
+    let mut jumped = false;
+    let max_ticks = (MAX_RACE_TIME / SIM_TIME_STEP) as u64;
+    sim.run(max_ticks, |_tick, _keys, gameplay| {
+        jumped |= gameplay
+            .ship_entities
+            .iter()
+            .any(|ship| ship.airborne > 0.0);
+    });
+    assert!(jumped);
+    let best_ai = sim.gameplay.scores.last().unwrap();
+    assert!(best_ai.laps.len() >= NUM_LAPS_TO_WIN);
+
+    let features = vec!["boost,0.5,0,1,1"; MAX_FEATURES + 1].join("/");
+    assert_eq!(
+        format!(
+            "SWT2;width=1;radius=8;sin=0,0,0,0,0,0,0,0;cos=0,0,0,0,0,0,0,0;features={}",
+            features
+        )
+        .parse::<Map>(),
+        Err(MapError::OutOfRange("features"))
+    );
+    assert_eq!(
+        "SWT2;width=1;spline=0,9/6,7.5/9,2;features=rocket,0.5,0,1,1".parse::<Map>(),
+        Err(MapError::InvalidValue(
+            "features=rocket,0.5,0,1,1".to_string()
+        ))
+    );
+    assert_eq!(
+        "SWT2;width=1;spline=0,9/6,7.5/9,2;features=boost,1.5,0,1,1".parse::<Map>(),
+        Err(MapError::InvalidValue(
+            "features=boost,1.5,0,1,1".to_string()
+        ))
+    );
+}
+
+#[test]
+fn tracks_survive_being_written_out() {
+    let tracks = built_in_tracks();
+    assert!(tracks.len() >= 3);
+    for track in tracks.iter() {
+        let read: Track = track.to_string().parse().unwrap();
+        assert_eq!(&read, track);
+        assert_eq!(Track::find(&track.name.to_uppercase()).as_ref(), Ok(track));
+    }
+    assert!(Track::find("hairpin").unwrap().map.spline.is_some());
+
+    let mut track = Track::new("My-Track!", "Someone", hairpin_track());
+    track.laps = 2;
+    let options = GameOptions::parse(&format!("players=1 track={}", track));
+    let read = options.track.unwrap();
+    assert_eq!(read.name, "My-Track!");
+    assert_eq!(read.laps, 2);
+    assert_eq!(read.map.spline, track.map.spline);
+
+    let map = "map=SWT2;width=0.7;spline=0,9/6,7.5/9,2";
+    assert_eq!(
+        Track::find("nowhere"),
+        Err(TrackError::UnknownTrack("nowhere".to_string()))
+    );
+    assert_eq!(
+        "SWK0;name=A".parse::<Track>(),
+        Err(TrackError::UnsupportedVersion("SWK0".to_string()))
+    );
+    assert_eq!(
+        format!("SWK1;name=Two words;author=A;{}", map).parse::<Track>(),
+        Err(TrackError::InvalidName("Two words".to_string()))
+    );
+    assert_eq!(
+        format!("SWK1;name=A;author=B;laps=0;{}", map).parse::<Track>(),
+        Err(TrackError::InvalidLaps("0".to_string()))
+    );
+    assert_eq!(
+        format!("SWK1;name=A;{}", map).parse::<Track>(),
+        Err(TrackError::MissingValue("author"))
+    );
+    assert_eq!(
+        "SWK1;name=A;author=B;map=SWT2;width=5;spline=0,9/6,7.5/9,2".parse::<Track>(),
+        Err(TrackError::InvalidMap(MapError::OutOfRange("width")))
+    );
+    assert_eq!(
+        "SWK1;name=A;author=B;map=SWT2;width=0.5;radius=1;sin=50,0,0,0,0,0,0,0;cos=0,0,0,0,0,0,0,0"
+            .parse::<Track>(),
+        Err(TrackError::InvalidMap(MapError::OutOfRange("sin and cos")))
+    );
+    assert_eq!(
+        "SWK1;name=A;author=B;map=SWT2;width=NaN;spline=0,9/6,7.5/9,2".parse::<Track>(),
+        Err(TrackError::InvalidMap(MapError::InvalidValue(
+            "width=NaN".to_string()
+        )))
+    );
+}
+
+#[test]
+fn races_last_as_many_laps_as_the_track_says() {
+    let mut track = Track::find("oval").unwrap();
+    track.laps = 1;
+    let mut sim = Simulation::new(0);
+    sim.gameplay.reset_on_track(&track);
+
+    let max_ticks = (MAX_RACE_TIME / SIM_TIME_STEP) as u64;
+    sim.run(max_ticks, |_tick, _keys, _gameplay| {});
+    for score in sim.gameplay.scores[1..].iter() {
+        assert!(score.finished());
+        assert_eq!(score.laps.len(), 2);
+    }
+
+    // Going back to a generated track goes back to the normal laps
+    sim.gameplay.reset(0);
+    assert_eq!(sim.gameplay.laps_to_win, NUM_LAPS_TO_WIN);
+    assert!(sim.gameplay.scores.iter().all(|score| !score.finished()));
+}
+
+#[test]
+fn leaderboard_fits_long_races() {
+    let mut track = Track::find("oval").unwrap();
+    track.laps = MAX_LAPS;
+    let mut sim = Simulation::new(0);
+    sim.gameplay.reset_on_track(&track);
+
+    // Halfway through the race with the player just behind their best
+    // lap, every line should take up exactly one row of the box
+    for score in sim.gameplay.scores.iter_mut() {
+        score.laps = vec![0.0; 11];
+    }
+    sim.gameplay.ghost.split_delta = Some(1.5);
+    sim.gameplay.generate_leaderboard_text();
+
+    let text = &sim.gameplay.leaderboard_text;
+    let (width, height) = text.box_dimensions;
+    assert!(width as usize >= "Lap 10/20".len());
+    assert_eq!(
+        text.uniform_data().len() / 4,
+        (width * height) as usize,
+        "leaderboard lines don't fill the {}x{} box",
+        width,
+        height
+    );
+}